@@ -0,0 +1,103 @@
+//! Signs IdP responses with a key held in a PKCS#11 token, here SoftHSM, through the
+//! `pkcs11-tool` of OpenSC. The private key never leaves the token: samael only hands it digests.
+//!
+//! Set up a token with a key pair and a certificate:
+//!
+//! ```sh
+//! export PKCS11_MODULE=/usr/lib/softhsm/libsofthsm2.so
+//! softhsm2-util --init-token --free --label samael --so-pin 0000 --pin 1234
+//! openssl req -x509 -newkey rsa:2048 -nodes -subj /CN=idp.example.com -days 365 \
+//!     -keyout idp-key.pem -out idp-cert.pem
+//! pkcs11-tool --module $PKCS11_MODULE --token-label samael --login --pin 1234 \
+//!     --write-object idp-key.pem --type privkey --label idp-signing --id 01
+//! rm idp-key.pem
+//! PKCS11_PIN=1234 IDP_CERTIFICATE=idp-cert.pem cargo run --example pkcs11-signer
+//! ```
+use samael::idp::key_set::{KeySet, SigningKey};
+use samael::idp::IdentityProvider;
+use samael::signer::{Error, SignatureAlgorithm, Signer};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+
+struct Pkcs11Signer {
+    module: String,
+    token_label: String,
+    key_label: String,
+    pin: String,
+    certificate: openssl::x509::X509,
+}
+
+impl Pkcs11Signer {
+    fn temp_file(&self, name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("samael-pkcs11-{}-{}", std::process::id(), name))
+    }
+}
+
+impl Signer for Pkcs11Signer {
+    fn algorithm(&self) -> SignatureAlgorithm {
+        SignatureAlgorithm::RsaSha256
+    }
+
+    fn certificate(&self) -> Option<openssl::x509::X509> {
+        Some(self.certificate.clone())
+    }
+
+    fn sign_digest(&self, digest: &[u8]) -> Result<Vec<u8>, Error> {
+        let failed = |message: String| Error::SigningFailed { message };
+        let (input, output) = (self.temp_file("digest"), self.temp_file("signature"));
+        // CKM_RSA_PKCS only pads, the DigestInfo has to be made here
+        std::fs::write(&input, self.algorithm().digest_info(digest))
+            .map_err(|error| failed(error.to_string()))?;
+        let status = Command::new("pkcs11-tool")
+            .args(["--module", &self.module])
+            .args(["--token-label", &self.token_label])
+            .args(["--login", "--pin", &self.pin])
+            .args(["--sign", "--mechanism", "RSA-PKCS"])
+            .args(["--label", &self.key_label])
+            .arg("--input-file")
+            .arg(&input)
+            .arg("--output-file")
+            .arg(&output)
+            .status()
+            .map_err(|error| failed(format!("failed to run pkcs11-tool: {}", error)));
+        let signature = status.and_then(|status| {
+            if status.success() {
+                std::fs::read(&output).map_err(|error| failed(error.to_string()))
+            } else {
+                Err(failed(format!("pkcs11-tool exited with {}", status)))
+            }
+        });
+        let _ = std::fs::remove_file(&input);
+        let _ = std::fs::remove_file(&output);
+        signature
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let env =
+        |name: &str, default: &str| std::env::var(name).unwrap_or_else(|_| default.to_string());
+    let signer = Pkcs11Signer {
+        module: env("PKCS11_MODULE", "/usr/lib/softhsm/libsofthsm2.so"),
+        token_label: env("PKCS11_TOKEN_LABEL", "samael"),
+        key_label: env("PKCS11_KEY_LABEL", "idp-signing"),
+        pin: std::env::var("PKCS11_PIN")?,
+        certificate: openssl::x509::X509::from_pem(&std::fs::read(env(
+            "IDP_CERTIFICATE",
+            "idp-cert.pem",
+        ))?)?,
+    };
+
+    let idp =
+        IdentityProvider::from_key_set(KeySet::new(SigningKey::from_signer(Arc::new(signer))));
+    let response = idp.sign_response(
+        "testuser@example.com",
+        "https://sp.example.com/audience",
+        "https://sp.example.com/acs",
+        "https://idp.example.com",
+        "",
+        &[],
+    )?;
+    println!("{}", yaserde::ser::to_string(&response)?);
+    Ok(())
+}
@@ -1,12 +1,328 @@
+use crate::metadata::Scope;
+use crate::schema::NameId;
+use chrono::{DateTime, SecondsFormat, Utc};
+use snafu::Snafu;
+use std::fmt;
+use std::str::FromStr;
 use yaserde_derive::{YaDeserialize, YaSerialize};
 
 #[derive(Clone, Debug, Default, Eq, PartialEq, YaDeserialize, YaSerialize)]
-#[yaserde(namespace = "xsi: http://www.w3.org/2001/XMLSchema-instance")]
+#[yaserde(
+    namespace = "saml: urn:oasis:names:tc:SAML:2.0:assertion",
+    namespace = "xs: http://www.w3.org/2001/XMLSchema",
+    namespace = "xsi: http://www.w3.org/2001/XMLSchema-instance"
+)]
 pub struct AttributeValue {
     #[yaserde(attribute, rename = "type", prefix = "xsi")]
     pub attribute_type: Option<String>,
     #[yaserde(text)]
     pub value: Option<String>,
+    /// A `NameID` as the value, as in eduPersonTargetedID.
+    #[yaserde(rename = "NameID", prefix = "saml")]
+    pub name_id: Option<NameId>,
+}
+
+impl AttributeValue {
+    /// The local name of the `xsi:type`, whatever prefix the IdP bound the XML Schema namespace to.
+    fn xsd_type(&self) -> Option<&str> {
+        self.attribute_type
+            .as_deref()
+            .map(|t| t.rsplit_once(':').map(|(_, local)| local).unwrap_or(t))
+    }
+
+    /// Whether the value is a plain string, either untyped or typed as a string.
+    pub fn is_string(&self) -> bool {
+        self.name_id.is_none()
+            && matches!(self.xsd_type(), None | Some("string") | Some("XSString"))
+    }
+
+    /// Parses the value according to its `xsi:type`. Untyped values are strings, and values of
+    /// types other than the ones of [`TypedValue`] are kept as [`TypedValue::Other`].
+    pub fn typed(&self) -> Result<TypedValue, Error> {
+        if let Some(name_id) = &self.name_id {
+            return Ok(TypedValue::NameId(name_id.clone()));
+        }
+        let value = self.value.clone().unwrap_or_default();
+        match self.xsd_type() {
+            None | Some("string") | Some("XSString") => Ok(TypedValue::String(value)),
+            Some("boolean") => self
+                .parse_text("xs:boolean", parse_boolean)
+                .map(TypedValue::Boolean),
+            Some("integer") => self
+                .parse_text("xs:integer", |text| text.parse().ok())
+                .map(TypedValue::Integer),
+            Some("dateTime") => self
+                .parse_text("xs:dateTime", parse_date_time)
+                .map(TypedValue::DateTime),
+            Some(_) => Ok(TypedValue::Other {
+                attribute_type: self.attribute_type.clone().unwrap_or_default(),
+                value,
+            }),
+        }
+    }
+
+    /// Parses the text of the value, whatever its `xsi:type`, since some IdPs send every value as
+    /// a string.
+    fn parse_text<T>(
+        &self,
+        attribute_type: &str,
+        parse: impl FnOnce(&str) -> Option<T>,
+    ) -> Result<T, Error> {
+        let text = self.value.as_deref().unwrap_or_default();
+        match &self.name_id {
+            None => parse(text.trim()),
+            Some(_) => None,
+        }
+        .ok_or_else(|| Error::InvalidValue {
+            attribute_type: attribute_type.to_string(),
+            value: text.to_string(),
+        })
+    }
+}
+
+fn parse_boolean(text: &str) -> Option<bool> {
+    match text {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_date_time(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text)
+        .ok()
+        .map(|date_time| date_time.with_timezone(&Utc))
+}
+
+/// Conversion of a single attribute value, see [`crate::schema::Assertion::attribute`].
+pub trait FromAttributeValue: Sized {
+    fn from_attribute_value(value: &AttributeValue) -> Result<Self, Error>;
+}
+
+impl FromAttributeValue for String {
+    fn from_attribute_value(value: &AttributeValue) -> Result<Self, Error> {
+        value.parse_text("xs:string", |_| {
+            Some(value.value.clone().unwrap_or_default())
+        })
+    }
+}
+
+impl FromAttributeValue for bool {
+    fn from_attribute_value(value: &AttributeValue) -> Result<Self, Error> {
+        value.parse_text("xs:boolean", parse_boolean)
+    }
+}
+
+impl FromAttributeValue for i64 {
+    fn from_attribute_value(value: &AttributeValue) -> Result<Self, Error> {
+        value.parse_text("xs:integer", |text| text.parse().ok())
+    }
+}
+
+impl FromAttributeValue for DateTime<Utc> {
+    fn from_attribute_value(value: &AttributeValue) -> Result<Self, Error> {
+        value.parse_text("xs:dateTime", parse_date_time)
+    }
+}
+
+impl FromAttributeValue for NameId {
+    fn from_attribute_value(value: &AttributeValue) -> Result<Self, Error> {
+        value.name_id.clone().ok_or_else(|| Error::InvalidValue {
+            attribute_type: "saml:NameID".to_string(),
+            value: value.value.clone().unwrap_or_default(),
+        })
+    }
+}
+
+impl FromAttributeValue for ScopedValue {
+    fn from_attribute_value(value: &AttributeValue) -> Result<Self, Error> {
+        String::from_attribute_value(value)?.parse()
+    }
+}
+
+impl FromAttributeValue for TypedValue {
+    fn from_attribute_value(value: &AttributeValue) -> Result<Self, Error> {
+        value.typed()
+    }
+}
+
+/// A value qualified by the domain it belongs to, like `student@example.edu` in
+/// eduPersonScopedAffiliation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScopedValue {
+    pub value: String,
+    pub scope: String,
+}
+
+impl ScopedValue {
+    /// Checks that the scope is one the IdP is authoritative for, as listed in its metadata, see
+    /// [`crate::metadata::EntityDescriptor::idp_scopes`]. Without this check, any trusted IdP
+    /// could assert values in the domain of another.
+    pub fn check_scope(&self, scopes: &[&Scope]) -> Result<(), Error> {
+        if scopes.iter().any(|scope| scope.matches(&self.scope)) {
+            Ok(())
+        } else {
+            Err(Error::ScopeNotAllowed {
+                value: self.to_string(),
+            })
+        }
+    }
+}
+
+impl FromStr for ScopedValue {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().rsplit_once('@') {
+            Some((value, scope)) if !value.is_empty() && !scope.is_empty() => Ok(ScopedValue {
+                value: value.to_string(),
+                scope: scope.to_string(),
+            }),
+            _ => Err(Error::NotScoped {
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ScopedValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.value, self.scope)
+    }
+}
+
+/// The value of an attribute with its XML Schema type.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TypedValue {
+    /// `xs:string`
+    String(String),
+    /// `xs:boolean`
+    Boolean(bool),
+    /// `xs:integer`
+    Integer(i64),
+    /// `xs:dateTime`
+    DateTime(DateTime<Utc>),
+    /// A nested `saml:NameID`
+    NameId(NameId),
+    /// Any other `xsi:type`, with the value as written.
+    Other {
+        attribute_type: String,
+        value: String,
+    },
+}
+
+impl TypedValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            TypedValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            TypedValue::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            TypedValue::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_date_time(&self) -> Option<DateTime<Utc>> {
+        match self {
+            TypedValue::DateTime(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_name_id(&self) -> Option<&NameId> {
+        match self {
+            TypedValue::NameId(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl From<&TypedValue> for AttributeValue {
+    fn from(value: &TypedValue) -> Self {
+        let typed = |attribute_type: &str, value: String| AttributeValue {
+            attribute_type: Some(attribute_type.to_string()),
+            value: Some(value),
+            name_id: None,
+        };
+        match value {
+            TypedValue::String(value) => typed("xs:string", value.clone()),
+            TypedValue::Boolean(value) => typed("xs:boolean", value.to_string()),
+            TypedValue::Integer(value) => typed("xs:integer", value.to_string()),
+            TypedValue::DateTime(value) => typed(
+                "xs:dateTime",
+                value.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            ),
+            TypedValue::NameId(name_id) => AttributeValue {
+                attribute_type: None,
+                value: None,
+                name_id: Some(name_id.clone()),
+            },
+            TypedValue::Other {
+                attribute_type,
+                value,
+            } => typed(attribute_type, value.clone()),
+        }
+    }
+}
+
+impl From<&str> for TypedValue {
+    fn from(value: &str) -> Self {
+        TypedValue::String(value.to_string())
+    }
+}
+
+impl From<String> for TypedValue {
+    fn from(value: String) -> Self {
+        TypedValue::String(value)
+    }
+}
+
+impl From<bool> for TypedValue {
+    fn from(value: bool) -> Self {
+        TypedValue::Boolean(value)
+    }
+}
+
+impl From<i64> for TypedValue {
+    fn from(value: i64) -> Self {
+        TypedValue::Integer(value)
+    }
+}
+
+impl From<DateTime<Utc>> for TypedValue {
+    fn from(value: DateTime<Utc>) -> Self {
+        TypedValue::DateTime(value)
+    }
+}
+
+impl From<NameId> for TypedValue {
+    fn from(value: NameId) -> Self {
+        TypedValue::NameId(value)
+    }
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Invalid {attribute_type} attribute value {value:?}"))]
+    InvalidValue {
+        attribute_type: String,
+        value: String,
+    },
+    #[snafu(display("Attribute value {value:?} has no scope"))]
+    NotScoped { value: String },
+    #[snafu(display("The IdP is not authoritative for the scope of {value:?}"))]
+    ScopeNotAllowed { value: String },
 }
 
 #[derive(Clone, Debug, Default, Eq, PartialEq, YaDeserialize, YaSerialize)]
@@ -23,11 +339,20 @@ pub struct Attribute {
 }
 
 pub static NAME_FORMAT_URI: &str = "urn:oasis:names:tc:SAML:2.0:attrname-format:uri";
+pub static NAME_FORMAT_BASIC: &str = "urn:oasis:names:tc:SAML:2.0:attrname-format:basic";
+pub static NAME_FORMAT_UNSPECIFIED: &str =
+    "urn:oasis:names:tc:SAML:2.0:attrname-format:unspecified";
 pub static SUBJECT_ID_URI: &str = "urn:oasis:names:tc:SAML:attribute:subject-id";
 pub static UID_URI: &str = "urn:oid:0.9.2342.19200300.100.1.1";
+pub static COMMON_NAME_URI: &str = "urn:oid:2.5.4.3";
 pub static TELEPHONE_NUMBER_URI: &str = "urn:oid:2.5.4.20";
 pub static MAIL_URI: &str = "urn:oid:0.9.2342.19200300.100.1.3";
 pub static SURNAME_URI: &str = "urn:oid:2.5.4.4";
 pub static DISPLAY_NAME_URI: &str = "urn:oid:2.16.840.1.113730.3.1.241";
 pub static GIVEN_NAME_URI: &str = "urn:oid:2.5.4.42";
+pub static EDU_PERSON_AFFILIATION_URI: &str = "urn:oid:1.3.6.1.4.1.5923.1.1.1.1";
+pub static EDU_PERSON_PRINCIPAL_NAME_URI: &str = "urn:oid:1.3.6.1.4.1.5923.1.1.1.6";
 pub static EDU_PERSON_ENTITLEMENT_URI: &str = "urn:oid:1.3.6.1.4.1.5923.1.1.1.7";
+pub static EDU_PERSON_SCOPED_AFFILIATION_URI: &str = "urn:oid:1.3.6.1.4.1.5923.1.1.1.9";
+pub static EDU_PERSON_TARGETED_ID_URI: &str = "urn:oid:1.3.6.1.4.1.5923.1.1.1.10";
+pub static PAIRWISE_ID_URI: &str = "urn:oasis:names:tc:SAML:attribute:pairwise-id";
@@ -0,0 +1,255 @@
+//! Normalizes the attributes of assertions into canonical keys, whatever names the IdP gives them.
+//!
+//! The canonical keys are the LDAP names of the eduPerson and X.500 attributes, such as `mail` or
+//! `givenName`, see the constants of this module. An [`AttributeMapper`] goes through its
+//! [`AttributeProfile`]s in order and files each attribute under the key of the first rule
+//! matching its Name and NameFormat.
+//!
+//! ```
+//! use samael::attribute_mapping::{AttributeMapper, AttributeProfile, GIVEN_NAME};
+//!
+//! let mapper = AttributeMapper::standard()
+//!     // an IdP specific name, checked before the built-in profiles
+//!     .with_rules(AttributeProfile::new().rule("urn:example:first-name", None, GIVEN_NAME));
+//! ```
+
+use crate::attribute::{
+    Attribute, AttributeValue, COMMON_NAME_URI, DISPLAY_NAME_URI, EDU_PERSON_AFFILIATION_URI,
+    EDU_PERSON_ENTITLEMENT_URI, EDU_PERSON_PRINCIPAL_NAME_URI, EDU_PERSON_SCOPED_AFFILIATION_URI,
+    EDU_PERSON_TARGETED_ID_URI, GIVEN_NAME_URI, MAIL_URI, NAME_FORMAT_URI, PAIRWISE_ID_URI,
+    SUBJECT_ID_URI, SURNAME_URI, TELEPHONE_NUMBER_URI, UID_URI,
+};
+use crate::schema::{Assertion, AttributeValues};
+use std::collections::BTreeMap;
+
+pub const MAIL: &str = "mail";
+pub const GIVEN_NAME: &str = "givenName";
+pub const SURNAME: &str = "sn";
+pub const COMMON_NAME: &str = "cn";
+pub const DISPLAY_NAME: &str = "displayName";
+pub const UID: &str = "uid";
+pub const TELEPHONE_NUMBER: &str = "telephoneNumber";
+pub const PRINCIPAL_NAME: &str = "eduPersonPrincipalName";
+pub const AFFILIATION: &str = "eduPersonAffiliation";
+pub const SCOPED_AFFILIATION: &str = "eduPersonScopedAffiliation";
+pub const ENTITLEMENT: &str = "eduPersonEntitlement";
+pub const TARGETED_ID: &str = "eduPersonTargetedID";
+pub const SUBJECT_ID: &str = "subject-id";
+pub const PAIRWISE_ID: &str = "pairwise-id";
+pub const GROUPS: &str = "groups";
+pub const ROLES: &str = "roles";
+
+const AZURE_CLAIMS: &str = "http://schemas.xmlsoap.org/ws/2005/05/identity/claims/";
+const MICROSOFT_CLAIMS: &str = "http://schemas.microsoft.com/ws/2008/06/identity/claims/";
+
+/// Files the attributes with a given Name, and NameFormat if set, under a canonical key.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MappingRule {
+    pub name: String,
+    /// Matches any NameFormat when unset.
+    pub name_format: Option<String>,
+    pub key: String,
+}
+
+impl MappingRule {
+    pub fn matches(&self, attribute: &Attribute) -> bool {
+        attribute.name.as_deref() == Some(self.name.as_str())
+            && self.name_format.as_deref().map_or(true, |format| {
+                attribute.name_format.as_deref() == Some(format)
+            })
+    }
+}
+
+/// An ordered set of mapping rules, either built-in for a family of IdPs or supplied by the SP.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AttributeProfile {
+    pub rules: Vec<MappingRule>,
+}
+
+impl AttributeProfile {
+    pub fn new() -> Self {
+        AttributeProfile::default()
+    }
+
+    pub fn rule(mut self, name: &str, name_format: Option<&str>, key: &str) -> Self {
+        self.rules.push(MappingRule {
+            name: name.to_string(),
+            name_format: name_format.map(str::to_string),
+            key: key.to_string(),
+        });
+        self
+    }
+
+    fn uri_rules(self, rules: &[(&str, &str)]) -> Self {
+        rules.iter().fold(self, |profile, (name, key)| {
+            profile.rule(name, Some(NAME_FORMAT_URI), key)
+        })
+    }
+
+    fn any_format_rules(self, rules: &[(&str, &str)]) -> Self {
+        rules
+            .iter()
+            .fold(self, |profile, (name, key)| profile.rule(name, None, key))
+    }
+
+    /// The X.500 and LDAP attributes by OID, as released by Shibboleth and SimpleSAMLphp.
+    pub fn x500() -> Self {
+        AttributeProfile::new().uri_rules(&[
+            (MAIL_URI, MAIL),
+            (GIVEN_NAME_URI, GIVEN_NAME),
+            (SURNAME_URI, SURNAME),
+            (COMMON_NAME_URI, COMMON_NAME),
+            (DISPLAY_NAME_URI, DISPLAY_NAME),
+            (UID_URI, UID),
+            (TELEPHONE_NUMBER_URI, TELEPHONE_NUMBER),
+        ])
+    }
+
+    /// The eduPerson attributes by OID, and the SAML subject identifiers.
+    pub fn edu_person() -> Self {
+        AttributeProfile::new().uri_rules(&[
+            (EDU_PERSON_PRINCIPAL_NAME_URI, PRINCIPAL_NAME),
+            (EDU_PERSON_AFFILIATION_URI, AFFILIATION),
+            (EDU_PERSON_SCOPED_AFFILIATION_URI, SCOPED_AFFILIATION),
+            (EDU_PERSON_ENTITLEMENT_URI, ENTITLEMENT),
+            (EDU_PERSON_TARGETED_ID_URI, TARGETED_ID),
+            (SUBJECT_ID_URI, SUBJECT_ID),
+            (PAIRWISE_ID_URI, PAIRWISE_ID),
+        ])
+    }
+
+    /// The claims of Azure AD and ADFS. Azure AD sends the user principal name as `name`.
+    pub fn azure_ad() -> Self {
+        let claim = |name: &str| format!("{}{}", AZURE_CLAIMS, name);
+        let microsoft_claim = |name: &str| format!("{}{}", MICROSOFT_CLAIMS, name);
+        AttributeProfile::new().any_format_rules(&[
+            (&claim("emailaddress"), MAIL),
+            (&claim("givenname"), GIVEN_NAME),
+            (&claim("surname"), SURNAME),
+            (&claim("name"), PRINCIPAL_NAME),
+            (&claim("upn"), PRINCIPAL_NAME),
+            (
+                "http://schemas.microsoft.com/identity/claims/displayname",
+                DISPLAY_NAME,
+            ),
+            ("http://schemas.xmlsoap.org/claims/CommonName", COMMON_NAME),
+            (&microsoft_claim("groups"), GROUPS),
+            ("http://schemas.xmlsoap.org/claims/Group", GROUPS),
+            (&microsoft_claim("role"), ROLES),
+        ])
+    }
+
+    /// Plain names, usually in the basic or unspecified NameFormat or without one, as sent by
+    /// Okta, Auth0 or Google by default.
+    pub fn basic() -> Self {
+        let profile = AttributeProfile::new().any_format_rules(&[
+            ("email", MAIL),
+            ("emailAddress", MAIL),
+            ("firstName", GIVEN_NAME),
+            ("given_name", GIVEN_NAME),
+            ("lastName", SURNAME),
+            ("surname", SURNAME),
+            ("family_name", SURNAME),
+            ("username", UID),
+            ("memberOf", GROUPS),
+            ("role", ROLES),
+        ]);
+        // the canonical keys themselves, without the OIDs
+        [
+            MAIL,
+            GIVEN_NAME,
+            SURNAME,
+            COMMON_NAME,
+            DISPLAY_NAME,
+            UID,
+            TELEPHONE_NUMBER,
+            PRINCIPAL_NAME,
+            AFFILIATION,
+            SCOPED_AFFILIATION,
+            ENTITLEMENT,
+            GROUPS,
+            ROLES,
+        ]
+        .iter()
+        .fold(profile, |profile, key| profile.rule(key, None, key))
+    }
+
+    pub fn key(&self, attribute: &Attribute) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(attribute))
+            .map(|rule| rule.key.as_str())
+    }
+}
+
+/// Maps attributes to canonical keys through its profiles, the first matching rule winning.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AttributeMapper {
+    pub profiles: Vec<AttributeProfile>,
+}
+
+impl AttributeMapper {
+    pub fn new(profiles: Vec<AttributeProfile>) -> Self {
+        AttributeMapper { profiles }
+    }
+
+    /// All the built-in profiles.
+    pub fn standard() -> Self {
+        AttributeMapper::new(vec![
+            AttributeProfile::x500(),
+            AttributeProfile::edu_person(),
+            AttributeProfile::azure_ad(),
+            AttributeProfile::basic(),
+        ])
+    }
+
+    /// Adds rules that take precedence over the profiles of the mapper.
+    pub fn with_rules(mut self, rules: AttributeProfile) -> Self {
+        self.profiles.insert(0, rules);
+        self
+    }
+
+    pub fn key(&self, attribute: &Attribute) -> Option<&str> {
+        self.profiles
+            .iter()
+            .find_map(|profile| profile.key(attribute))
+    }
+
+    pub fn map<'a>(&self, assertion: &'a Assertion) -> MappedAttributes<'a> {
+        let mut mapped = MappedAttributes::default();
+        for attribute in assertion.attributes() {
+            match self.key(attribute) {
+                Some(key) => mapped
+                    .values
+                    .entry(key.to_string())
+                    .or_default()
+                    .extend(attribute.values.iter()),
+                None => mapped.unmapped.push(attribute),
+            }
+        }
+        mapped
+    }
+}
+
+/// The values of the attributes of an assertion by canonical key.
+#[derive(Clone, Debug, Default)]
+pub struct MappedAttributes<'a> {
+    values: BTreeMap<String, Vec<&'a AttributeValue>>,
+    unmapped: Vec<&'a Attribute>,
+}
+
+impl<'a> MappedAttributes<'a> {
+    /// The values filed under the key, parsed with the accessors of [`AttributeValues`].
+    pub fn attribute(&self, key: &str) -> AttributeValues<'a> {
+        AttributeValues::new(key, self.values.get(key).cloned().unwrap_or_default())
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+
+    /// The attributes no rule matched.
+    pub fn unmapped(&self) -> &[&'a Attribute] {
+        &self.unmapped
+    }
+}
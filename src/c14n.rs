@@ -0,0 +1,616 @@
+//!
+//! XML Canonicalization: Inclusive C14N 1.0 and 1.1, and Exclusive C14N 1.0
+//!
+use crate::dom::{
+    escape_attribute, escape_text, Attribute, Document, Element, NodeId, NodeKind, XMLNS_XML,
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const C14N: &str = "http://www.w3.org/TR/2001/REC-xml-c14n-20010315";
+pub const C14N_WITH_COMMENTS: &str = "http://www.w3.org/TR/2001/REC-xml-c14n-20010315#WithComments";
+pub const C14N_11: &str = "http://www.w3.org/2006/12/xml-c14n11";
+pub const C14N_11_WITH_COMMENTS: &str = "http://www.w3.org/2006/12/xml-c14n11#WithComments";
+pub const EXCLUSIVE_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+pub const EXCLUSIVE_C14N_WITH_COMMENTS: &str =
+    "http://www.w3.org/2001/10/xml-exc-c14n#WithComments";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Algorithm {
+    /// Canonical XML 1.0
+    Inclusive,
+    /// Canonical XML 1.1
+    Inclusive11,
+    /// Exclusive XML Canonicalization 1.0
+    Exclusive,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Canonicalization {
+    pub algorithm: Algorithm,
+    pub with_comments: bool,
+    /// The `InclusiveNamespaces PrefixList` of Exclusive XML Canonicalization, where `#default`
+    /// stands for the default namespace. Ignored by the inclusive algorithms.
+    pub inclusive_prefixes: Vec<String>,
+}
+
+impl Canonicalization {
+    pub fn new(algorithm: Algorithm) -> Self {
+        Canonicalization {
+            algorithm,
+            with_comments: false,
+            inclusive_prefixes: vec![],
+        }
+    }
+
+    pub fn from_uri(uri: &str) -> Option<Self> {
+        let (algorithm, with_comments) = match uri {
+            C14N => (Algorithm::Inclusive, false),
+            C14N_WITH_COMMENTS => (Algorithm::Inclusive, true),
+            C14N_11 => (Algorithm::Inclusive11, false),
+            C14N_11_WITH_COMMENTS => (Algorithm::Inclusive11, true),
+            EXCLUSIVE_C14N => (Algorithm::Exclusive, false),
+            EXCLUSIVE_C14N_WITH_COMMENTS => (Algorithm::Exclusive, true),
+            _ => return None,
+        };
+        Some(Canonicalization::new(algorithm).with_comments(with_comments))
+    }
+
+    pub fn uri(&self) -> &'static str {
+        match (self.algorithm, self.with_comments) {
+            (Algorithm::Inclusive, false) => C14N,
+            (Algorithm::Inclusive, true) => C14N_WITH_COMMENTS,
+            (Algorithm::Inclusive11, false) => C14N_11,
+            (Algorithm::Inclusive11, true) => C14N_11_WITH_COMMENTS,
+            (Algorithm::Exclusive, false) => EXCLUSIVE_C14N,
+            (Algorithm::Exclusive, true) => EXCLUSIVE_C14N_WITH_COMMENTS,
+        }
+    }
+
+    pub fn with_comments(mut self, with_comments: bool) -> Self {
+        self.with_comments = with_comments;
+        self
+    }
+
+    pub fn with_inclusive_prefixes<S: Into<String>, I: IntoIterator<Item = S>>(
+        mut self,
+        prefixes: I,
+    ) -> Self {
+        self.inclusive_prefixes = prefixes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Canonicalizes the whole document, including the comments and processing instructions
+    /// around the root element.
+    pub fn canonicalize_document(&self, document: &Document) -> String {
+        let mut out = String::new();
+        let mut after_root = false;
+        for node in document.top_level_nodes() {
+            if matches!(document.kind(*node), NodeKind::Comment(_)) && !self.with_comments {
+                continue;
+            }
+            let is_root = document.element(*node).is_some();
+            if after_root {
+                out.push('\n');
+            }
+            self.write_node(
+                document,
+                *node,
+                &BTreeMap::new(),
+                &BTreeMap::new(),
+                &[],
+                false,
+                &mut out,
+            );
+            if is_root {
+                after_root = true;
+            } else if !after_root {
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    /// Canonicalizes the subtree rooted at the given node, leaving out the subtrees rooted at the
+    /// `excluded` nodes (e.g. an enveloped signature).
+    pub fn canonicalize_node(
+        &self,
+        document: &Document,
+        node: NodeId,
+        excluded: &[NodeId],
+    ) -> String {
+        let mut out = String::new();
+        let scope = match document.parent(node) {
+            Some(parent) => document.in_scope_namespaces(parent),
+            None => BTreeMap::new(),
+        };
+        self.write_node(
+            document,
+            node,
+            &scope,
+            &BTreeMap::new(),
+            excluded,
+            true,
+            &mut out,
+        );
+        out
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn write_node(
+        &self,
+        document: &Document,
+        node: NodeId,
+        scope: &BTreeMap<String, String>,
+        rendered: &BTreeMap<String, String>,
+        excluded: &[NodeId],
+        is_apex: bool,
+        out: &mut String,
+    ) {
+        if excluded.contains(&node) {
+            return;
+        }
+        match document.kind(node) {
+            NodeKind::Element(element) => self.write_element(
+                document, node, element, scope, rendered, excluded, is_apex, out,
+            ),
+            NodeKind::Text(text) => out.push_str(&escape_text(text)),
+            NodeKind::Comment(text) => {
+                if self.with_comments {
+                    out.push_str("<!--");
+                    out.push_str(text);
+                    out.push_str("-->");
+                }
+            }
+            NodeKind::ProcessingInstruction { target, data } => {
+                out.push_str("<?");
+                out.push_str(target);
+                let data = data.as_deref().unwrap_or_default().trim_start();
+                if !data.is_empty() {
+                    out.push(' ');
+                    out.push_str(data);
+                }
+                out.push_str("?>");
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn write_element(
+        &self,
+        document: &Document,
+        node: NodeId,
+        element: &Element,
+        parent_scope: &BTreeMap<String, String>,
+        rendered: &BTreeMap<String, String>,
+        excluded: &[NodeId],
+        is_apex: bool,
+        out: &mut String,
+    ) {
+        let mut scope = parent_scope.clone();
+        for (prefix, uri) in &element.namespace_declarations {
+            scope.insert(prefix.clone().unwrap_or_default(), uri.clone());
+        }
+
+        let namespaces = self.namespaces_to_render(element, &scope, rendered);
+        let mut rendered = rendered.clone();
+        rendered.extend(namespaces.clone());
+
+        let mut attributes = element.attributes.clone();
+        // the apex of a document subset carries the xml:* attributes of its omitted ancestors
+        if is_apex && self.algorithm != Algorithm::Exclusive {
+            self.inherit_xml_attributes(document, node, &mut attributes);
+        }
+        attributes.sort_by(|a, b| {
+            (a.namespace.as_deref().unwrap_or(""), &a.local_name)
+                .cmp(&(b.namespace.as_deref().unwrap_or(""), &b.local_name))
+        });
+
+        let name = element.qualified_name();
+        out.push('<');
+        out.push_str(&name);
+        for (prefix, uri) in &namespaces {
+            if prefix.is_empty() {
+                out.push_str(" xmlns=\"");
+            } else {
+                out.push_str(" xmlns:");
+                out.push_str(prefix);
+                out.push_str("=\"");
+            }
+            out.push_str(&escape_attribute(uri));
+            out.push('"');
+        }
+        for attr in &attributes {
+            out.push(' ');
+            out.push_str(&attr.qualified_name());
+            out.push_str("=\"");
+            out.push_str(&escape_attribute(&attr.value));
+            out.push('"');
+        }
+        out.push('>');
+        for child in document.children(node) {
+            self.write_node(document, *child, &scope, &rendered, excluded, false, out);
+        }
+        out.push_str("</");
+        out.push_str(&name);
+        out.push('>');
+    }
+
+    /// Returns the namespace declarations to output on an element, sorted by prefix (the default
+    /// namespace, with an empty prefix, comes first).
+    fn namespaces_to_render(
+        &self,
+        element: &Element,
+        scope: &BTreeMap<String, String>,
+        rendered: &BTreeMap<String, String>,
+    ) -> BTreeMap<String, String> {
+        let mut prefixes = BTreeSet::new();
+        if self.algorithm == Algorithm::Exclusive {
+            prefixes.insert(element.prefix.clone().unwrap_or_default());
+            for attr in &element.attributes {
+                if let Some(prefix) = attr.prefix.as_ref().filter(|p| *p != "xml") {
+                    prefixes.insert(prefix.clone());
+                }
+            }
+            for prefix in &self.inclusive_prefixes {
+                if prefix == "#default" {
+                    prefixes.insert(String::new());
+                } else if scope.contains_key(prefix) {
+                    prefixes.insert(prefix.clone());
+                }
+            }
+        } else {
+            prefixes.insert(String::new());
+            prefixes.extend(scope.keys().cloned());
+        }
+
+        prefixes
+            .into_iter()
+            .filter_map(|prefix| {
+                let uri = scope.get(&prefix).map(String::as_str).unwrap_or("");
+                let in_effect = rendered.get(&prefix).map(String::as_str).unwrap_or("");
+                if uri == in_effect {
+                    None
+                } else {
+                    Some((prefix, uri.to_string()))
+                }
+            })
+            .collect()
+    }
+
+    /// Adds the `xml:*` attributes of the ancestors of the given element that it does not
+    /// override. C14N 1.1 does not inherit `xml:id`, and joins the `xml:base` values instead.
+    fn inherit_xml_attributes(
+        &self,
+        document: &Document,
+        node: NodeId,
+        attributes: &mut Vec<Attribute>,
+    ) {
+        let is_xml_attribute =
+            |attr: &Attribute, name: &str| attr.local_name == name && is_xml_namespace(attr);
+
+        // xml:base values of the ancestors, nearest first
+        let mut bases = vec![];
+        for ancestor in document.ancestors(node) {
+            let Some(element) = document.element(ancestor) else {
+                continue;
+            };
+            for attr in element.attributes.iter().filter(|a| is_xml_namespace(a)) {
+                match (self.algorithm, attr.local_name.as_str()) {
+                    (Algorithm::Inclusive11, "id") => continue,
+                    (Algorithm::Inclusive11, "base") => {
+                        bases.push(attr.value.as_str());
+                        continue;
+                    }
+                    _ => {}
+                }
+                if !attributes
+                    .iter()
+                    .any(|a| is_xml_attribute(a, &attr.local_name))
+                {
+                    attributes.push(attr.clone());
+                }
+            }
+        }
+
+        if bases.is_empty() {
+            return;
+        }
+        let base = bases.iter().rev().fold(String::new(), |base, value| {
+            join_uri_references(&base, value)
+        });
+        match attributes.iter_mut().find(|a| is_xml_attribute(a, "base")) {
+            Some(attr) => attr.value = join_uri_references(&base, &attr.value),
+            None => attributes.push(Attribute {
+                prefix: Some("xml".to_string()),
+                local_name: "base".to_string(),
+                namespace: Some(XMLNS_XML.to_string()),
+                value: base,
+            }),
+        }
+    }
+}
+
+fn is_xml_namespace(attr: &Attribute) -> bool {
+    attr.namespace.as_deref() == Some(XMLNS_XML)
+}
+
+fn has_scheme(uri: &str) -> bool {
+    match uri.split_once(':') {
+        Some((scheme, _)) => {
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+/// Resolves a (possibly relative) URI reference against a (possibly relative) base, as required
+/// by the `xml:base` fixup of C14N 1.1. Leading `..` segments of relative paths are kept.
+fn join_uri_references(base: &str, reference: &str) -> String {
+    if base.is_empty() || has_scheme(reference) || reference.starts_with("//") {
+        return reference.to_string();
+    }
+    if reference.is_empty() {
+        return base.to_string();
+    }
+
+    // split the base into its scheme and authority, and its path
+    let after_scheme = if has_scheme(base) {
+        base.find(':').unwrap() + 1
+    } else {
+        0
+    };
+    let path_start = if base[after_scheme..].starts_with("//") {
+        base[after_scheme + 2..]
+            .find('/')
+            .map(|i| after_scheme + 2 + i)
+            .unwrap_or(base.len())
+    } else {
+        after_scheme
+    };
+    let (authority, path) = base.split_at(path_start);
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+
+    let merged = if reference.starts_with('/') {
+        reference.to_string()
+    } else if reference.starts_with(['?', '#']) {
+        format!("{}{}", path, reference)
+    } else {
+        match path.rfind('/') {
+            Some(i) => format!("{}{}", &path[..=i], reference),
+            None if path_start > after_scheme => format!("/{}", reference),
+            None => reference.to_string(),
+        }
+    };
+    format!("{}{}", authority, remove_dot_segments(&merged))
+}
+
+fn remove_dot_segments(path: &str) -> String {
+    let (path, suffix) = path.split_at(path.find(['?', '#']).unwrap_or(path.len()));
+    let absolute = path.starts_with('/');
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    let last = segments.len() - 1;
+    let mut output: Vec<&str> = vec![];
+    for (i, segment) in segments.into_iter().enumerate() {
+        match segment {
+            "." | ".." => {
+                if segment == ".." {
+                    if output.last().map(|s| *s != "..").unwrap_or(false) {
+                        output.pop();
+                    } else if !absolute {
+                        output.push("..");
+                    }
+                }
+                if i == last {
+                    output.push("");
+                }
+            }
+            segment => output.push(segment),
+        }
+    }
+    format!(
+        "{}{}{}",
+        if absolute { "/" } else { "" },
+        output.join("/"),
+        suffix
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn canonicalize_document(input: &str, method: &Canonicalization) -> String {
+        let document = Document::parse(input).expect("failed to parse input");
+        method.canonicalize_document(&document)
+    }
+
+    fn canonicalize_element(input: &str, name: &str, method: &Canonicalization) -> String {
+        let document = Document::parse(input).expect("failed to parse input");
+        let node = document
+            .elements()
+            .into_iter()
+            .find(|node| document.element(*node).unwrap().qualified_name() == name)
+            .expect("element not found");
+        method.canonicalize_node(&document, node, &[])
+    }
+
+    macro_rules! test_vector {
+        ($name:literal) => {
+            include_str!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/test_vectors/c14n/",
+                $name
+            ))
+        };
+    }
+
+    #[test]
+    fn test_w3c_pis_comments_and_outside_of_document_element() {
+        let input = test_vector!("w3c-3.1-input.xml");
+        let method = Canonicalization::new(Algorithm::Inclusive);
+        assert_eq!(
+            canonicalize_document(input, &method),
+            test_vector!("w3c-3.1-output.xml")
+        );
+        assert_eq!(
+            canonicalize_document(input, &method.with_comments(true)),
+            test_vector!("w3c-3.1-output-with-comments.xml")
+        );
+    }
+
+    #[test]
+    fn test_w3c_whitespace_in_document_content() {
+        assert_eq!(
+            canonicalize_document(
+                test_vector!("w3c-3.2-input.xml"),
+                &Canonicalization::new(Algorithm::Inclusive)
+            ),
+            test_vector!("w3c-3.2-output.xml")
+        );
+    }
+
+    #[test]
+    fn test_w3c_start_and_end_tags() {
+        assert_eq!(
+            canonicalize_document(
+                test_vector!("w3c-3.3-input.xml"),
+                &Canonicalization::new(Algorithm::Inclusive)
+            ),
+            test_vector!("w3c-3.3-output.xml")
+        );
+    }
+
+    #[test]
+    fn test_w3c_character_modifications_and_character_references() {
+        assert_eq!(
+            canonicalize_document(
+                test_vector!("w3c-3.4-input.xml"),
+                &Canonicalization::new(Algorithm::Inclusive)
+            ),
+            test_vector!("w3c-3.4-output.xml")
+        );
+    }
+
+    #[test]
+    fn test_w3c_utf8_encoding() {
+        assert_eq!(
+            canonicalize_document(
+                test_vector!("w3c-3.6-input.xml"),
+                &Canonicalization::new(Algorithm::Inclusive)
+            ),
+            test_vector!("w3c-3.6-output.xml")
+        );
+    }
+
+    #[test]
+    fn test_w3c_exclusive_c14n_examples() {
+        for (input, inclusive_output) in [
+            (
+                test_vector!("w3c-exc-input-1.xml"),
+                test_vector!("w3c-exc-output-inclusive-1.xml"),
+            ),
+            (
+                test_vector!("w3c-exc-input-2.xml"),
+                test_vector!("w3c-exc-output-inclusive-2.xml"),
+            ),
+        ] {
+            assert_eq!(
+                canonicalize_element(
+                    input,
+                    "n1:elem2",
+                    &Canonicalization::new(Algorithm::Inclusive)
+                ),
+                inclusive_output
+            );
+            assert_eq!(
+                canonicalize_element(
+                    input,
+                    "n1:elem2",
+                    &Canonicalization::new(Algorithm::Exclusive)
+                ),
+                test_vector!("w3c-exc-output-exclusive.xml")
+            );
+        }
+    }
+
+    #[test]
+    fn test_exclusive_c14n_with_comments() {
+        let input = test_vector!("exc-with-comments-input.xml");
+        let with_comments = Canonicalization::new(Algorithm::Exclusive).with_comments(true);
+        assert_eq!(
+            canonicalize_document(input, &with_comments),
+            test_vector!("exc-output-with-comments.xml")
+        );
+        let elem2 = |comment: &str| {
+            format!(
+                "<n1:elem2 xmlns:n1=\"http://example.net\" xml:lang=\"en\">{}\n       \
+                 <n3:stuff xmlns:n3=\"ftp://example.org\"></n3:stuff>\n   </n1:elem2>",
+                comment
+            )
+        };
+        assert_eq!(
+            canonicalize_element(input, "n1:elem2", &with_comments),
+            elem2("<!-- Comment 3 -->")
+        );
+        assert_eq!(
+            canonicalize_element(
+                input,
+                "n1:elem2",
+                &Canonicalization::new(Algorithm::Exclusive)
+            ),
+            elem2("")
+        );
+    }
+
+    #[test]
+    fn test_exclusive_inclusive_prefix_list() {
+        assert_eq!(
+            canonicalize_element(
+                test_vector!("w3c-exc-input-2.xml"),
+                "n1:elem2",
+                &Canonicalization::new(Algorithm::Exclusive).with_inclusive_prefixes(["n2"])
+            ),
+            test_vector!("exc-prefix-list-output.xml")
+        );
+    }
+
+    #[test]
+    fn test_xml_attributes_inheritance() {
+        let input = test_vector!("xml-attributes-input.xml");
+        assert_eq!(
+            canonicalize_element(input, "e3", &Canonicalization::new(Algorithm::Inclusive)),
+            r#"<e3 xmlns:w3c="http://www.w3.org" id="E3" xml:base="foo" xml:id="abc"></e3>"#
+        );
+        assert_eq!(
+            canonicalize_element(input, "e3", &Canonicalization::new(Algorithm::Inclusive11)),
+            r#"<e3 xmlns:w3c="http://www.w3.org" id="E3" xml:base="something/bar/foo"></e3>"#
+        );
+        assert_eq!(
+            canonicalize_element(input, "e3", &Canonicalization::new(Algorithm::Exclusive)),
+            r#"<e3 id="E3" xml:base="foo"></e3>"#
+        );
+    }
+
+    #[test]
+    fn test_join_uri_references() {
+        assert_eq!(join_uri_references("", "foo"), "foo");
+        assert_eq!(
+            join_uri_references("something/else", "bar/"),
+            "something/bar/"
+        );
+        assert_eq!(
+            join_uri_references("http://a/b/c/d", "../g"),
+            "http://a/b/g"
+        );
+        assert_eq!(join_uri_references("http://a/b", "/g"), "http://a/g");
+        assert_eq!(join_uri_references("http://a", "g"), "http://a/g");
+        assert_eq!(join_uri_references("a/b", "../../../g"), "../../g");
+        assert_eq!(
+            join_uri_references("http://a/b", "https://c/d"),
+            "https://c/d"
+        );
+    }
+}
@@ -0,0 +1,51 @@
+//!
+//! The source of the current time, for the validity windows of messages and certificates
+//!
+use crate::utils::UtcDateTime;
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Mutex;
+
+/// Tells the time to a [`crate::service_provider::ServiceProvider`] or an
+/// [`crate::idp::IdentityProvider`].
+pub trait Clock: Send + Sync {
+    fn now(&self) -> UtcDateTime;
+}
+
+/// The time of the system.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> UtcDateTime {
+        UtcDateTime(Utc::now())
+    }
+}
+
+/// A clock which only moves when told to, so that time-dependent validation can be tested
+/// deterministically.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl ManualClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        ManualClock {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> UtcDateTime {
+        UtcDateTime(*self.now.lock().unwrap())
+    }
+}
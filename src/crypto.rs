@@ -8,12 +8,15 @@ use crate::xmlsec::{self, XmlSecKey, XmlSecKeyFormat, XmlSecSignatureContext};
 #[cfg(feature = "xmlsec")]
 use libxml::parser::Parser as XmlParser;
 #[cfg(feature = "xmlsec")]
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 #[cfg(feature = "xmlsec")]
 use std::convert::TryInto;
 #[cfg(feature = "xmlsec")]
 use std::ffi::CString;
 
+pub const XMLNS_SAML_ASSERTION: &str = "urn:oasis:names:tc:SAML:2.0:assertion";
+pub const XMLNS_SAML_PROTOCOL: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+
 #[cfg(feature = "xmlsec")]
 pub const XMLNS_XML_DSIG: &str = "http://www.w3.org/2000/09/xmldsig#";
 #[cfg(feature = "xmlsec")]
@@ -58,11 +61,60 @@ pub enum Error {
         error: Box<dyn std::error::Error>,
     },
 
-    #[cfg(feature = "xmlsec")]
+    #[cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
     #[snafu(display("OpenSSL error stack: {}", error))]
     OpenSSLError {
         error: openssl::error::ErrorStack,
     },
+
+    #[cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
+    #[snafu(display("duplicate ID attribute: {}", id))]
+    DuplicateId {
+        id: String,
+    },
+
+    #[cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
+    #[snafu(display(
+        "signature reference {} must point to the Response or Assertion containing the signature",
+        uri
+    ))]
+    InvalidReferenceTarget {
+        uri: String,
+    },
+
+    #[cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
+    #[snafu(display("a SAML response with several assertions must have every one signed"))]
+    MultipleAssertions,
+
+    #[cfg(feature = "native-xmldsig")]
+    #[snafu(display("xml Error: {}", error))]
+    XmlDomError {
+        error: crate::dom::Error,
+    },
+
+    #[cfg(feature = "native-xmldsig")]
+    #[snafu(display("missing {} in signature", name))]
+    MissingSignatureElement {
+        name: String,
+    },
+
+    #[cfg(feature = "native-xmldsig")]
+    #[snafu(display("unsupported algorithm: {}", algorithm))]
+    UnsupportedAlgorithm {
+        algorithm: String,
+    },
+
+    #[cfg(feature = "native-xmldsig")]
+    #[snafu(display("signature reference not found: {}", uri))]
+    ReferenceNotFound {
+        uri: String,
+    },
+
+    #[cfg(feature = "native-xmldsig")]
+    #[snafu(display("digest mismatch for reference: {}", uri))]
+    DigestMismatch {
+        uri: String,
+    },
 }
 
 impl From<base64::DecodeError> for Error {
@@ -85,13 +137,41 @@ impl From<libxml::parser::XmlParseError> for Error {
     }
 }
 
-#[cfg(feature = "xmlsec")]
+#[cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
 impl From<openssl::error::ErrorStack> for Error {
     fn from(error: openssl::error::ErrorStack) -> Self {
         Error::OpenSSLError { error }
     }
 }
 
+#[cfg(feature = "native-xmldsig")]
+impl From<crate::dom::Error> for Error {
+    fn from(error: crate::dom::Error) -> Self {
+        Error::XmlDomError { error }
+    }
+}
+
+/// The outcome of verifying the signatures of an XML document.
+#[derive(Clone, Debug, Default)]
+pub struct VerificationReport {
+    /// The `Signature` elements found in the document, in document order.
+    pub signatures: Vec<SignatureReport>,
+    /// The paths of the elements that were removed because no signature covers them.
+    pub removed_elements: Vec<String>,
+    /// The document without the removed elements.
+    pub reduced_xml: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct SignatureReport {
+    /// The path of the `Signature` element, such as `/samlp:Response/ds:Signature`.
+    pub path: String,
+    /// The `URI` attributes of the `Reference` elements of the signature.
+    pub reference_uris: Vec<String>,
+    /// The certificate whose key verified the signature.
+    pub certificate: openssl::x509::X509,
+}
+
 #[cfg(feature = "xmlsec")]
 pub fn sign_xml<Bytes: AsRef<[u8]>>(xml: Bytes, private_key_der: &[u8]) -> Result<String, Error> {
     let parser = XmlParser::default();
@@ -132,11 +212,14 @@ pub fn verify_signed_xml<Bytes: AsRef<[u8]>>(
 /// document's internal ID table.
 ///
 /// This is necessary for signature verification to successfully follow the references from a
-/// `<dsig:Signature>` element to the element it has signed.
+/// `<dsig:Signature>` element to the element it has signed. Documents where several elements share
+/// an `ID` are rejected, so that a reference cannot be made to point to an element other than the
+/// one the application reads.
 #[cfg(feature = "xmlsec")]
 fn collect_id_attributes(doc: &mut libxml::tree::Document) -> Result<(), Error> {
     const ID_STR: &str = "ID";
     let id_attr_name = CString::new(ID_STR).unwrap();
+    let mut seen_ids = HashSet::new();
 
     let mut nodes_to_visit = Vec::new();
     if let Some(root_elem) = doc.get_root_element() {
@@ -144,6 +227,9 @@ fn collect_id_attributes(doc: &mut libxml::tree::Document) -> Result<(), Error>
     }
     while let Some(node) = nodes_to_visit.pop() {
         if let Some(id_value) = node.get_attribute(ID_STR) {
+            if !seen_ids.insert(id_value.clone()) {
+                return Err(Error::DuplicateId { id: id_value });
+            }
             let id_value_cstr = CString::new(id_value).unwrap();
             let node_ptr = node.node_ptr();
             unsafe {
@@ -245,7 +331,6 @@ fn get_elements_by_predicate<F: FnMut(&libxml::tree::Node) -> bool>(
 
 /// Searches for and returns the element with the given value of the `ID` attribute from the subtree
 /// rooted at the given node.
-#[allow(unused)]
 #[cfg(feature = "xmlsec")]
 fn get_element_by_id(elem: &libxml::tree::Node, id: &str) -> Option<libxml::tree::Node> {
     let mut elems = get_elements_by_predicate(elem, |node| {
@@ -406,54 +491,173 @@ fn place_signature_verified_attributes(
     }
 }
 
-/// Remove all elements that do not contain a signature-verified attribute ([`ATTRIB_SIGVER`] in
-/// the namespace [`XMLNS_SIGVER`]).
+/// Returns the qualified names of an element and its ancestors, such as
+/// `/samlp:Response/ds:Signature`.
 #[cfg(feature = "xmlsec")]
-fn remove_unverified_elements(node: &mut libxml::tree::Node) {
-    // depth-first
-    for mut child in node.get_child_elements() {
-        remove_unverified_elements(&mut child);
+fn element_path(node: &libxml::tree::Node) -> String {
+    let mut names = vec![];
+    let mut current = Some(node.clone());
+    while let Some(node) = current {
+        if node.get_type() == Some(libxml::tree::NodeType::ElementNode) {
+            names.push(match node.get_namespace() {
+                Some(ns) if !ns.get_prefix().is_empty() => {
+                    format!("{}:{}", ns.get_prefix(), node.get_name())
+                }
+                _ => node.get_name(),
+            });
+        }
+        current = node.get_parent();
     }
+    names
+        .iter()
+        .rev()
+        .map(|name| format!("/{}", name))
+        .collect()
+}
+
+/// Returns the `URI` attributes of the `Reference` elements of a signature.
+#[cfg(feature = "xmlsec")]
+fn get_reference_uris(signature_node: &libxml::tree::Node) -> Vec<String> {
+    get_first_child_name_ns(signature_node, "SignedInfo", XMLNS_XML_DSIG)
+        .map(|signed_info| {
+            signed_info
+                .get_child_elements()
+                .into_iter()
+                .filter(|child| is_element(child, "Reference", XMLNS_XML_DSIG))
+                .map(|reference| reference.get_attribute("URI").unwrap_or_default())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Checks whether the given node is an element with the given name and namespace.
+#[cfg(feature = "xmlsec")]
+fn is_element(node: &libxml::tree::Node, name: &str, ns: &str) -> bool {
+    node.get_name() == name
+        && node
+            .get_namespace()
+            .map(|node_ns| node_ns.get_href())
+            .as_deref()
+            == Some(ns)
+}
 
+/// Only accepts signatures whose references all point to the Response or Assertion element that
+/// contains the signature.
+#[cfg(feature = "xmlsec")]
+fn check_reference_targets(
+    signature_node: &libxml::tree::Node,
+    root_elem: &libxml::tree::Node,
+) -> Result<(), Error> {
+    let parent_ptr = signature_node.get_parent().map(|parent| parent.node_ptr());
+    for uri in get_reference_uris(signature_node) {
+        let target = if uri.is_empty() {
+            Some(root_elem.clone())
+        } else {
+            uri.strip_prefix('#')
+                .and_then(|id| get_element_by_id(root_elem, id))
+        };
+        let valid = target
+            .map(|target| {
+                (is_element(&target, "Response", XMLNS_SAML_PROTOCOL)
+                    || is_element(&target, "Assertion", XMLNS_SAML_ASSERTION)
+                    || is_element(&target, "AuthnRequest", XMLNS_SAML_PROTOCOL))
+                    && Some(target.node_ptr()) == parent_ptr
+            })
+            .unwrap_or(false);
+        if !valid {
+            return Err(Error::InvalidReferenceTarget { uri });
+        }
+    }
+    Ok(())
+}
+
+/// Remove all elements that do not contain a signature-verified attribute ([`ATTRIB_SIGVER`] in
+/// the namespace [`XMLNS_SIGVER`]), and record their paths.
+#[cfg(feature = "xmlsec")]
+fn remove_unverified_elements(node: &mut libxml::tree::Node, removed: &mut Vec<String>) {
     if node.get_attribute_ns(ATTRIB_SIGVER, XMLNS_SIGVER) != Some(String::from(VALUE_SIGVER)) {
-        // element is unverified; remove it
+        // element is unverified, and so are all its descendants; remove it
+        removed.push(element_path(node));
         node.unlink_node();
+        return;
+    }
+
+    for mut child in node.get_child_elements() {
+        remove_unverified_elements(&mut child, removed);
     }
 }
 
 /// Takes an XML document, parses it, verifies all XML digital signatures against the given
-/// certificates, and returns a derived version of the document where all elements that are not
-/// covered by a digital signature have been removed.
+/// certificates, and reports on a derived version of the document where all elements that are
+/// not covered by a digital signature have been removed.
 #[cfg(feature = "xmlsec")]
-pub(crate) fn reduce_xml_to_signed(
+pub fn verify_signatures(
     xml_str: &str,
     certs: &[openssl::x509::X509],
-) -> Result<String, Error> {
+) -> Result<VerificationReport, Error> {
+    let mut report = VerificationReport::default();
     let mut xml = XmlParser::default().parse_string(xml_str)?;
     let mut root_elem = xml.get_root_element().ok_or(Error::XmlMissingRootElement)?;
 
     // collect ID attribute values and tell libxml about them
     collect_id_attributes(&mut xml)?;
 
+    let assertions = get_elements_by_predicate(&root_elem, |node| {
+        is_element(node, "Assertion", XMLNS_SAML_ASSERTION)
+            || is_element(node, "EncryptedAssertion", XMLNS_SAML_ASSERTION)
+    });
+
     // verify each signature
     {
         let mut signature_nodes = find_signature_nodes(&root_elem);
         for sig_node in signature_nodes.drain(..) {
-            let mut verified = false;
+            check_reference_targets(&sig_node, &root_elem)?;
+            let mut verified = None;
             for openssl_key in certs {
                 let key_data = openssl_key.to_der()?;
                 let key = XmlSecKey::from_memory(&key_data, XmlSecKeyFormat::CertDer)?;
                 let mut sig_ctx = XmlSecSignatureContext::new()?;
                 sig_ctx.insert_key(key);
-                verified = sig_ctx.verify_node(&sig_node)?;
-                if verified {
+                if sig_ctx.verify_node(&sig_node)? {
+                    verified = Some(openssl_key.clone());
                     break;
                 }
             }
 
-            if !verified {
-                return Err(Error::InvalidSignature);
+            let certificate = verified.ok_or(Error::InvalidSignature)?;
+            report.signatures.push(SignatureReport {
+                path: element_path(&sig_node),
+                reference_uris: get_reference_uris(&sig_node),
+                certificate,
+            });
+        }
+    }
+
+    // several assertions are only accepted when each is covered by a signature, so that none can
+    // be smuggled in next to a signed one
+    if assertions.len() > 1 {
+        let signed_ids: HashSet<String> = report
+            .signatures
+            .iter()
+            .flat_map(|signature| signature.reference_uris.iter())
+            .map(|uri| uri.trim_start_matches('#').to_string())
+            .collect();
+        let covered = |assertion: &libxml::tree::Node| {
+            let mut node = Some(assertion.clone());
+            while let Some(current) = node {
+                if current
+                    .get_attribute("ID")
+                    .is_some_and(|id| signed_ids.contains(&id))
+                {
+                    return true;
+                }
+                node = current.get_parent();
             }
+            // a reference to the whole document
+            signed_ids.contains("")
+        };
+        if !assertions.iter().all(covered) {
+            return Err(Error::MultipleAssertions);
         }
     }
 
@@ -474,16 +678,19 @@ pub(crate) fn reduce_xml_to_signed(
 
     // delete all elements that don't have a "signature verified" attribute
     let mut root_elem = xml.get_root_element().ok_or(Error::XmlMissingRootElement)?;
-    remove_unverified_elements(&mut root_elem);
+    remove_unverified_elements(&mut root_elem, &mut report.removed_elements);
 
     // remove all "signature verified" attributes again
     remove_signature_verified_attributes(&mut root_elem)?;
 
     // serialize XML again
-    let reduced_xml_str = xml.to_string();
-    Ok(reduced_xml_str)
+    report.reduced_xml = xml.to_string();
+    Ok(report)
 }
 
+#[cfg(all(feature = "native-xmldsig", not(feature = "xmlsec")))]
+pub use crate::dsig::verify_signatures;
+
 // Util
 // strip out 76-width format and decode base64
 pub fn decode_x509_cert(x509_cert: &str) -> Result<Vec<u8>, base64::DecodeError> {
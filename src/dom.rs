@@ -0,0 +1,520 @@
+use snafu::Snafu;
+use std::collections::{BTreeMap, BTreeSet};
+use xml::reader::{EventReader, ParserConfig, XmlEvent};
+
+pub const XMLNS_XML: &str = "http://www.w3.org/XML/1998/namespace";
+pub const XMLNS_XMLNS: &str = "http://www.w3.org/2000/xmlns/";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Failed to parse XML: {}", error))]
+    InvalidXml {
+        error: xml::reader::Error,
+    },
+
+    #[snafu(display("Unexpected character data outside of the root element"))]
+    UnexpectedCharacters,
+
+    XmlMissingRootElement,
+}
+
+impl From<xml::reader::Error> for Error {
+    fn from(error: xml::reader::Error) -> Self {
+        Error::InvalidXml { error }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Ord, PartialOrd)]
+pub struct NodeId(usize);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Attribute {
+    pub prefix: Option<String>,
+    pub local_name: String,
+    pub namespace: Option<String>,
+    pub value: String,
+}
+
+impl Attribute {
+    pub fn qualified_name(&self) -> String {
+        match &self.prefix {
+            Some(prefix) => format!("{}:{}", prefix, self.local_name),
+            None => self.local_name.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Element {
+    pub prefix: Option<String>,
+    pub local_name: String,
+    pub namespace: Option<String>,
+    /// Namespace declarations (`xmlns` and `xmlns:prefix` attributes) made on this element. The
+    /// default namespace is stored with a `None` prefix.
+    pub namespace_declarations: Vec<(Option<String>, String)>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Element {
+    pub fn qualified_name(&self) -> String {
+        match &self.prefix {
+            Some(prefix) => format!("{}:{}", prefix, self.local_name),
+            None => self.local_name.clone(),
+        }
+    }
+
+    pub fn is(&self, local_name: &str, namespace: &str) -> bool {
+        self.local_name == local_name && self.namespace.as_deref() == Some(namespace)
+    }
+
+    /// Returns the value of the attribute with the given local name that is not in any namespace.
+    pub fn attribute(&self, local_name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attr| attr.namespace.is_none() && attr.local_name == local_name)
+            .map(|attr| attr.value.as_str())
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum NodeKind {
+    Element(Element),
+    Text(String),
+    Comment(String),
+    ProcessingInstruction {
+        target: String,
+        data: Option<String>,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct NodeData {
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    kind: NodeKind,
+}
+
+/// An XML document held as an arena of nodes, so that elements can refer to their ancestors.
+#[derive(Clone, Debug)]
+pub struct Document {
+    nodes: Vec<NodeData>,
+    children: Vec<NodeId>,
+}
+
+impl Document {
+    pub fn parse(xml: &str) -> Result<Self, Error> {
+        let config = ParserConfig::new()
+            .trim_whitespace(false)
+            .whitespace_to_characters(true)
+            .cdata_to_characters(true)
+            .coalesce_characters(true)
+            .ignore_comments(false);
+
+        let mut document = Document {
+            nodes: vec![],
+            children: vec![],
+        };
+        // in-scope namespace mappings of the currently open elements
+        let mut open: Vec<(NodeId, BTreeMap<String, String>)> = vec![];
+        let initial_scope = default_scope();
+
+        for event in EventReader::new_with_config(xml.as_bytes(), config) {
+            let kind = match event? {
+                XmlEvent::StartElement {
+                    name,
+                    attributes,
+                    namespace,
+                } => {
+                    let scope: BTreeMap<String, String> = namespace.0;
+                    let parent_scope = open
+                        .last()
+                        .map(|(_, parent_scope)| parent_scope)
+                        .unwrap_or(&initial_scope);
+                    let namespace_declarations = scope
+                        .iter()
+                        .filter(|(prefix, uri)| parent_scope.get(*prefix) != Some(uri))
+                        .filter(|(prefix, _)| !matches!(prefix.as_str(), "xml" | "xmlns"))
+                        .map(|(prefix, uri)| {
+                            let prefix = Some(prefix.clone()).filter(|p| !p.is_empty());
+                            (prefix, uri.clone())
+                        })
+                        .collect();
+                    let element = Element {
+                        prefix: name.prefix,
+                        local_name: name.local_name,
+                        namespace: name.namespace.filter(|ns| !ns.is_empty()),
+                        namespace_declarations,
+                        attributes: attributes
+                            .into_iter()
+                            .map(|attr| Attribute {
+                                prefix: attr.name.prefix,
+                                local_name: attr.name.local_name,
+                                namespace: attr.name.namespace.filter(|ns| !ns.is_empty()),
+                                value: attr.value,
+                            })
+                            .collect(),
+                    };
+                    let id =
+                        document.push(open.last().map(|(id, _)| *id), NodeKind::Element(element));
+                    open.push((id, scope));
+                    continue;
+                }
+                XmlEvent::EndElement { .. } => {
+                    open.pop();
+                    continue;
+                }
+                XmlEvent::Characters(text) | XmlEvent::Whitespace(text) => {
+                    if open.is_empty() {
+                        if text.trim().is_empty() {
+                            continue;
+                        }
+                        return Err(Error::UnexpectedCharacters);
+                    }
+                    NodeKind::Text(text)
+                }
+                XmlEvent::CData(text) => NodeKind::Text(text),
+                XmlEvent::Comment(text) => NodeKind::Comment(text),
+                XmlEvent::ProcessingInstruction { name, data } => {
+                    NodeKind::ProcessingInstruction { target: name, data }
+                }
+                XmlEvent::StartDocument { .. } | XmlEvent::EndDocument => continue,
+            };
+            document.push(open.last().map(|(id, _)| *id), kind);
+        }
+
+        document
+            .root_element()
+            .ok_or(Error::XmlMissingRootElement)?;
+        Ok(document)
+    }
+
+    fn push(&mut self, parent: Option<NodeId>, kind: NodeKind) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(NodeData {
+            parent,
+            children: vec![],
+            kind,
+        });
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.children.push(id),
+        }
+        id
+    }
+
+    /// The root element along with the comments and processing instructions around it.
+    pub fn top_level_nodes(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    pub fn root_element(&self) -> Option<NodeId> {
+        self.children
+            .iter()
+            .copied()
+            .find(|id| self.element(*id).is_some())
+    }
+
+    pub fn kind(&self, id: NodeId) -> &NodeKind {
+        &self.nodes[id.0].kind
+    }
+
+    pub fn element(&self, id: NodeId) -> Option<&Element> {
+        match &self.nodes[id.0].kind {
+            NodeKind::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.nodes[id.0].children
+    }
+
+    pub fn child_elements(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.children(id)
+            .iter()
+            .copied()
+            .filter(|child| self.element(*child).is_some())
+    }
+
+    /// Returns the first child element with the given local name and namespace.
+    pub fn first_child(&self, id: NodeId, local_name: &str, namespace: &str) -> Option<NodeId> {
+        self.child_elements(id).find(|child| {
+            self.element(*child)
+                .map(|e| e.is(local_name, namespace))
+                .unwrap_or(false)
+        })
+    }
+
+    /// Returns the chain of ancestors of the given node, nearest first.
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.parent(id), move |node| self.parent(*node))
+    }
+
+    /// Returns the given node and all the nodes below it, in document order.
+    pub fn descendants(&self, id: NodeId) -> Vec<NodeId> {
+        let mut nodes = vec![];
+        let mut to_visit = vec![id];
+        while let Some(node) = to_visit.pop() {
+            nodes.push(node);
+            to_visit.extend(self.children(node).iter().rev());
+        }
+        nodes
+    }
+
+    /// Returns all the elements of the document, in document order.
+    pub fn elements(&self) -> Vec<NodeId> {
+        self.root_element()
+            .map(|root| self.descendants(root))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|id| self.element(*id).is_some())
+            .collect()
+    }
+
+    /// Returns the namespace prefixes in scope for the given element, with the default namespace
+    /// under the empty prefix. The `xml` prefix is not included.
+    pub fn in_scope_namespaces(&self, id: NodeId) -> BTreeMap<String, String> {
+        let mut scope = BTreeMap::new();
+        let mut chain: Vec<NodeId> = self.ancestors(id).collect();
+        chain.reverse();
+        chain.push(id);
+        for node in chain {
+            if let Some(element) = self.element(node) {
+                for (prefix, uri) in &element.namespace_declarations {
+                    scope.insert(prefix.clone().unwrap_or_default(), uri.clone());
+                }
+            }
+        }
+        scope
+    }
+
+    /// Returns the text content of the given node and all its descendants.
+    pub fn text(&self, id: NodeId) -> String {
+        self.descendants(id)
+            .into_iter()
+            .filter_map(|node| match self.kind(node) {
+                NodeKind::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Unlinks the given node (and therefore its whole subtree) from the document.
+    pub fn detach(&mut self, id: NodeId) {
+        let siblings = match self.parent(id) {
+            Some(parent) => &mut self.nodes[parent.0].children,
+            None => &mut self.children,
+        };
+        siblings.retain(|sibling| *sibling != id);
+        self.nodes[id.0].parent = None;
+    }
+
+    /// Puts a copy of a node of another document, along with its subtree, in place of the given
+    /// node, and returns the copy.
+    pub fn replace(&mut self, id: NodeId, other: &Document, other_id: NodeId) -> NodeId {
+        let parent = self.parent(id);
+        let copy = self.import(other, other_id, parent);
+        self.declare_namespaces(copy, other, other_id);
+        let siblings = match parent {
+            Some(parent) => &mut self.nodes[parent.0].children,
+            None => &mut self.children,
+        };
+        for sibling in siblings.iter_mut().filter(|sibling| **sibling == id) {
+            *sibling = copy;
+        }
+        self.nodes[id.0].parent = None;
+        copy
+    }
+
+    /// Puts a copy of a node of another document, along with its subtree, among the children of
+    /// `parent` at position `index`, and returns the copy.
+    pub fn insert(
+        &mut self,
+        parent: NodeId,
+        index: usize,
+        other: &Document,
+        other_id: NodeId,
+    ) -> NodeId {
+        let copy = self.import(other, other_id, Some(parent));
+        self.declare_namespaces(copy, other, other_id);
+        let siblings = &mut self.nodes[parent.0].children;
+        siblings.insert(index.min(siblings.len()), copy);
+        copy
+    }
+
+    /// Declares on an imported element the namespaces in scope at its origin that are not in
+    /// scope at its new place, since the parser drops the declarations it finds redundant. All of
+    /// them are declared, not only those of element and attribute names, as QName values such as
+    /// `xsi:type="xs:string"` need theirs too.
+    fn declare_namespaces(&mut self, copy: NodeId, other: &Document, other_id: NodeId) {
+        let origin = other.in_scope_namespaces(other_id);
+        let destination = self
+            .parent(copy)
+            .map(|parent| self.in_scope_namespaces(parent))
+            .unwrap_or_default();
+        let NodeKind::Element(element) = &mut self.nodes[copy.0].kind else {
+            return;
+        };
+        let bound = |scope: &BTreeMap<String, String>, prefix: &str| {
+            scope.get(prefix).cloned().unwrap_or_default()
+        };
+        let prefixes: BTreeSet<&String> = origin.keys().chain(destination.keys()).collect();
+        for prefix in prefixes {
+            let uri = bound(&origin, prefix);
+            let declared = element
+                .namespace_declarations
+                .iter()
+                .any(|(declared, _)| declared.as_deref().unwrap_or_default() == prefix.as_str());
+            // only the default namespace can be undeclared
+            if declared
+                || uri == bound(&destination, prefix)
+                || (uri.is_empty() && !prefix.is_empty())
+            {
+                continue;
+            }
+            element.namespace_declarations.push((
+                Some(prefix.clone()).filter(|prefix| !prefix.is_empty()),
+                uri,
+            ));
+        }
+    }
+
+    fn import(&mut self, other: &Document, other_id: NodeId, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(NodeData {
+            parent,
+            children: vec![],
+            kind: other.kind(other_id).clone(),
+        });
+        let children = other
+            .children(other_id)
+            .iter()
+            .map(|child| self.import(other, *child, Some(id)))
+            .collect();
+        self.nodes[id.0].children = children;
+        id
+    }
+
+    /// Serializes the document again, keeping the namespace declarations where they were made.
+    pub fn to_xml(&self) -> String {
+        let mut out = String::new();
+        for node in &self.children {
+            self.write_node(*node, &mut out);
+        }
+        out
+    }
+
+    /// Serializes an element and its descendants on their own, declaring on the element every
+    /// namespace in scope there.
+    pub fn element_to_xml(&self, id: NodeId) -> String {
+        let mut out = String::new();
+        if let Some(element) = self.element(id) {
+            let declarations: Vec<_> = self
+                .in_scope_namespaces(id)
+                .into_iter()
+                .map(|(prefix, uri)| (Some(prefix).filter(|prefix| !prefix.is_empty()), uri))
+                .collect();
+            self.write_element(id, element, &declarations, &mut out);
+        }
+        out
+    }
+
+    fn write_node(&self, id: NodeId, out: &mut String) {
+        match self.kind(id) {
+            NodeKind::Element(element) => {
+                self.write_element(id, element, &element.namespace_declarations, out)
+            }
+            NodeKind::Text(text) => out.push_str(&escape_text(text)),
+            NodeKind::Comment(text) => {
+                out.push_str("<!--");
+                out.push_str(text);
+                out.push_str("-->");
+            }
+            NodeKind::ProcessingInstruction { target, data } => {
+                out.push_str("<?");
+                out.push_str(target);
+                if let Some(data) = data {
+                    out.push(' ');
+                    out.push_str(data);
+                }
+                out.push_str("?>");
+            }
+        }
+    }
+
+    fn write_element(
+        &self,
+        id: NodeId,
+        element: &Element,
+        declarations: &[(Option<String>, String)],
+        out: &mut String,
+    ) {
+        let name = element.qualified_name();
+        out.push('<');
+        out.push_str(&name);
+        for (prefix, uri) in declarations {
+            match prefix {
+                Some(prefix) => out.push_str(&format!(" xmlns:{}=\"", prefix)),
+                None => out.push_str(" xmlns=\""),
+            }
+            out.push_str(&escape_attribute(uri));
+            out.push('"');
+        }
+        for attr in &element.attributes {
+            out.push(' ');
+            out.push_str(&attr.qualified_name());
+            out.push_str("=\"");
+            out.push_str(&escape_attribute(&attr.value));
+            out.push('"');
+        }
+        out.push('>');
+        for child in self.children(id) {
+            self.write_node(*child, out);
+        }
+        out.push_str("</");
+        out.push_str(&name);
+        out.push('>');
+    }
+}
+
+fn default_scope() -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (String::new(), String::new()),
+        ("xml".to_string(), XMLNS_XML.to_string()),
+        ("xmlns".to_string(), XMLNS_XMLNS.to_string()),
+    ])
+}
+
+pub(crate) fn escape_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+pub(crate) fn escape_attribute(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' => out.push_str("&#x9;"),
+            '\n' => out.push_str("&#xA;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,391 @@
+//!
+//! Native XML digital signature verification, built on the `c14n` module and OpenSSL
+//!
+use crate::c14n::{Algorithm, Canonicalization, EXCLUSIVE_C14N};
+use crate::crypto::{
+    Error, SignatureReport, VerificationReport, XMLNS_SAML_ASSERTION, XMLNS_SAML_PROTOCOL,
+};
+use crate::dom::{Document, NodeId};
+use base64::{engine::general_purpose, Engine as _};
+use openssl::bn::BigNum;
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkey::{Id, PKey, Public};
+use openssl::sign::Verifier;
+use std::collections::HashSet;
+
+pub const XMLNS_XML_DSIG: &str = "http://www.w3.org/2000/09/xmldsig#";
+
+pub const ENVELOPED_SIGNATURE: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+
+pub const DIGEST_SHA1: &str = "http://www.w3.org/2000/09/xmldsig#sha1";
+pub const DIGEST_SHA256: &str = "http://www.w3.org/2001/04/xmlenc#sha256";
+pub const DIGEST_SHA384: &str = "http://www.w3.org/2001/04/xmldsig-more#sha384";
+pub const DIGEST_SHA512: &str = "http://www.w3.org/2001/04/xmlenc#sha512";
+
+pub const RSA_SHA1: &str = "http://www.w3.org/2000/09/xmldsig#rsa-sha1";
+pub const RSA_SHA256: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256";
+pub const RSA_SHA384: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha384";
+pub const RSA_SHA512: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha512";
+pub const ECDSA_SHA1: &str = "http://www.w3.org/2001/04/xmldsig-more#ecdsa-sha1";
+pub const ECDSA_SHA256: &str = "http://www.w3.org/2001/04/xmldsig-more#ecdsa-sha256";
+pub const ECDSA_SHA384: &str = "http://www.w3.org/2001/04/xmldsig-more#ecdsa-sha384";
+pub const ECDSA_SHA512: &str = "http://www.w3.org/2001/04/xmldsig-more#ecdsa-sha512";
+
+/// Decodes base64 element content, which may be wrapped over several lines.
+fn decode_base64(text: &str) -> Result<Vec<u8>, Error> {
+    let stripped = text
+        .bytes()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect::<Vec<u8>>();
+    Ok(general_purpose::STANDARD.decode(stripped)?)
+}
+
+fn digest_method(algorithm: &str) -> Result<MessageDigest, Error> {
+    match algorithm {
+        DIGEST_SHA1 => Ok(MessageDigest::sha1()),
+        DIGEST_SHA256 => Ok(MessageDigest::sha256()),
+        DIGEST_SHA384 => Ok(MessageDigest::sha384()),
+        DIGEST_SHA512 => Ok(MessageDigest::sha512()),
+        _ => Err(Error::UnsupportedAlgorithm {
+            algorithm: algorithm.to_string(),
+        }),
+    }
+}
+
+/// Returns the digest of a signature method, and whether it is an ECDSA one.
+fn signature_method(algorithm: &str) -> Result<(MessageDigest, bool), Error> {
+    match algorithm {
+        RSA_SHA1 => Ok((MessageDigest::sha1(), false)),
+        RSA_SHA256 => Ok((MessageDigest::sha256(), false)),
+        RSA_SHA384 => Ok((MessageDigest::sha384(), false)),
+        RSA_SHA512 => Ok((MessageDigest::sha512(), false)),
+        ECDSA_SHA1 => Ok((MessageDigest::sha1(), true)),
+        ECDSA_SHA256 => Ok((MessageDigest::sha256(), true)),
+        ECDSA_SHA384 => Ok((MessageDigest::sha384(), true)),
+        ECDSA_SHA512 => Ok((MessageDigest::sha512(), true)),
+        _ => Err(Error::UnsupportedAlgorithm {
+            algorithm: algorithm.to_string(),
+        }),
+    }
+}
+
+fn required_child(document: &Document, node: NodeId, name: &str) -> Result<NodeId, Error> {
+    document
+        .first_child(node, name, XMLNS_XML_DSIG)
+        .ok_or_else(|| Error::MissingSignatureElement {
+            name: name.to_string(),
+        })
+}
+
+fn algorithm_attribute(document: &Document, node: NodeId) -> Result<&str, Error> {
+    document
+        .element(node)
+        .and_then(|element| element.attribute("Algorithm"))
+        .ok_or_else(|| Error::MissingSignatureElement {
+            name: "Algorithm".to_string(),
+        })
+}
+
+/// Reads the canonicalization method described by a `CanonicalizationMethod` or `Transform`
+/// element, including its `InclusiveNamespaces` prefix list.
+fn canonicalization(document: &Document, node: NodeId) -> Result<Canonicalization, Error> {
+    let algorithm = algorithm_attribute(document, node)?;
+    let mut method =
+        Canonicalization::from_uri(algorithm).ok_or_else(|| Error::UnsupportedAlgorithm {
+            algorithm: algorithm.to_string(),
+        })?;
+    if let Some(inclusive_namespaces) =
+        document.first_child(node, "InclusiveNamespaces", EXCLUSIVE_C14N)
+    {
+        method.inclusive_prefixes = document
+            .element(inclusive_namespaces)
+            .and_then(|element| element.attribute("PrefixList"))
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(String::from)
+            .collect();
+    }
+    Ok(method)
+}
+
+/// Finds the element referenced by a same-document URI: either the whole document (`""`) or the
+/// element whose `ID` attribute matches (`"#id"`).
+fn dereference(document: &Document, uri: &str) -> Result<NodeId, Error> {
+    let not_found = || Error::ReferenceNotFound {
+        uri: uri.to_string(),
+    };
+    if uri.is_empty() {
+        return document.root_element().ok_or_else(not_found);
+    }
+    let id = uri.strip_prefix('#').ok_or_else(not_found)?;
+    document
+        .elements()
+        .into_iter()
+        .find(|node| {
+            document
+                .element(*node)
+                .and_then(|element| element.attribute("ID"))
+                == Some(id)
+        })
+        .ok_or_else(not_found)
+}
+
+/// Rejects documents where several elements share an `ID`, so that a reference cannot be made to
+/// point to an element other than the one the application reads.
+fn check_unique_ids(document: &Document) -> Result<(), Error> {
+    let mut ids = HashSet::new();
+    for node in document.elements() {
+        if let Some(id) = document
+            .element(node)
+            .and_then(|element| element.attribute("ID"))
+        {
+            if !ids.insert(id) {
+                return Err(Error::DuplicateId { id: id.to_string() });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks the digest of a `Reference` and returns the element it points to.
+fn verify_reference(
+    document: &Document,
+    signature: NodeId,
+    reference: NodeId,
+) -> Result<NodeId, Error> {
+    let uri = document
+        .element(reference)
+        .and_then(|element| element.attribute("URI"))
+        .unwrap_or_default();
+    let target = dereference(document, uri)?;
+    // only accept enveloped signatures over a Response, an Assertion or an AuthnRequest
+    let is_saml_message = document
+        .element(target)
+        .map(|element| {
+            element.is("Response", XMLNS_SAML_PROTOCOL)
+                || element.is("Assertion", XMLNS_SAML_ASSERTION)
+                || element.is("AuthnRequest", XMLNS_SAML_PROTOCOL)
+        })
+        .unwrap_or(false);
+    if !is_saml_message || document.parent(signature) != Some(target) {
+        return Err(Error::InvalidReferenceTarget {
+            uri: uri.to_string(),
+        });
+    }
+
+    let mut excluded = vec![];
+    // same-document references are canonicalized with C14N 1.0 unless told otherwise
+    let mut method = None;
+    if let Some(transforms) = document.first_child(reference, "Transforms", XMLNS_XML_DSIG) {
+        for transform in document.child_elements(transforms) {
+            match algorithm_attribute(document, transform)? {
+                ENVELOPED_SIGNATURE => excluded.push(signature),
+                _ => method = Some(canonicalization(document, transform)?),
+            }
+        }
+    }
+    // comments are never part of the node-set selected by a same-document URI
+    let method = method
+        .unwrap_or_else(|| Canonicalization::new(Algorithm::Inclusive))
+        .with_comments(false);
+    let canonical = method.canonicalize_node(document, target, &excluded);
+
+    let digest = digest_method(algorithm_attribute(
+        document,
+        required_child(document, reference, "DigestMethod")?,
+    )?)?;
+    let expected =
+        decode_base64(&document.text(required_child(document, reference, "DigestValue")?))?;
+    let actual = hash(digest, canonical.as_bytes())?;
+
+    if actual.as_ref() != expected.as_slice() {
+        return Err(Error::DigestMismatch {
+            uri: uri.to_string(),
+        });
+    }
+    Ok(target)
+}
+
+/// XML DSig stores ECDSA signatures as the concatenation of `r` and `s`, OpenSSL wants DER.
+fn ecdsa_signature_to_der(signature: &[u8]) -> Result<Vec<u8>, Error> {
+    let (r, s) = signature.split_at(signature.len() / 2);
+    let signature =
+        EcdsaSig::from_private_components(BigNum::from_slice(r)?, BigNum::from_slice(s)?)?;
+    Ok(signature.to_der()?)
+}
+
+fn verify_signature_value(
+    key: &PKey<Public>,
+    algorithm: &str,
+    data: &[u8],
+    signature: &[u8],
+) -> Result<bool, Error> {
+    let (digest, is_ecdsa) = signature_method(algorithm)?;
+    let expected_key = if is_ecdsa { Id::EC } else { Id::RSA };
+    if key.id() != expected_key {
+        return Ok(false);
+    }
+    let signature = if is_ecdsa {
+        ecdsa_signature_to_der(signature)?
+    } else {
+        signature.to_vec()
+    };
+    let mut verifier = Verifier::new(digest, key)?;
+    verifier.update(data)?;
+    // OpenSSL reports malformed signatures as errors rather than as a mismatch
+    Ok(verifier.verify(&signature).unwrap_or(false))
+}
+
+/// Returns the qualified names of an element and its ancestors, such as
+/// `/samlp:Response/ds:Signature`.
+fn element_path(document: &Document, node: NodeId) -> String {
+    let mut names: Vec<_> = std::iter::once(node)
+        .chain(document.ancestors(node))
+        .filter_map(|ancestor| document.element(ancestor))
+        .map(|element| element.qualified_name())
+        .collect();
+    names.reverse();
+    names.iter().map(|name| format!("/{}", name)).collect()
+}
+
+/// Verifies a `<ds:Signature>` element against the given certificates and returns the elements
+/// covered by its references.
+fn verify_signature(
+    document: &Document,
+    signature: NodeId,
+    certs: &[openssl::x509::X509],
+) -> Result<(SignatureReport, Vec<NodeId>), Error> {
+    let signed_info = required_child(document, signature, "SignedInfo")?;
+
+    let mut signed_nodes = vec![];
+    let references: Vec<_> = document
+        .child_elements(signed_info)
+        .filter(|node| {
+            document
+                .element(*node)
+                .map(|element| element.is("Reference", XMLNS_XML_DSIG))
+                .unwrap_or(false)
+        })
+        .collect();
+    if references.is_empty() {
+        return Err(Error::MissingSignatureElement {
+            name: "Reference".to_string(),
+        });
+    }
+    let mut reference_uris = vec![];
+    for reference in references {
+        signed_nodes.push(verify_reference(document, signature, reference)?);
+        reference_uris.push(
+            document
+                .element(reference)
+                .and_then(|element| element.attribute("URI"))
+                .unwrap_or_default()
+                .to_string(),
+        );
+    }
+
+    let method = canonicalization(
+        document,
+        required_child(document, signed_info, "CanonicalizationMethod")?,
+    )?;
+    let canonical_signed_info = method.canonicalize_node(document, signed_info, &[]);
+    let signature_method = algorithm_attribute(
+        document,
+        required_child(document, signed_info, "SignatureMethod")?,
+    )?;
+    let signature_value =
+        decode_base64(&document.text(required_child(document, signature, "SignatureValue")?))?;
+
+    for cert in certs {
+        if verify_signature_value(
+            &cert.public_key()?,
+            signature_method,
+            canonical_signed_info.as_bytes(),
+            &signature_value,
+        )? {
+            let report = SignatureReport {
+                path: element_path(document, signature),
+                reference_uris,
+                certificate: cert.clone(),
+            };
+            return Ok((report, signed_nodes));
+        }
+    }
+    Err(Error::InvalidSignature)
+}
+
+/// Takes an XML document, parses it, verifies all XML digital signatures against the given
+/// certificates, and reports on a derived version of the document where all elements that are
+/// not covered by a digital signature have been removed.
+pub fn verify_signatures(
+    xml_str: &str,
+    certs: &[openssl::x509::X509],
+) -> Result<VerificationReport, Error> {
+    let mut document = Document::parse(xml_str)?;
+    check_unique_ids(&document)?;
+
+    let assertions: Vec<_> = document
+        .elements()
+        .into_iter()
+        .filter(|node| {
+            document
+                .element(*node)
+                .map(|element| {
+                    element.is("Assertion", XMLNS_SAML_ASSERTION)
+                        || element.is("EncryptedAssertion", XMLNS_SAML_ASSERTION)
+                })
+                .unwrap_or(false)
+        })
+        .collect();
+
+    let signatures: Vec<_> = document
+        .elements()
+        .into_iter()
+        .filter(|node| {
+            document
+                .element(*node)
+                .map(|element| element.is("Signature", XMLNS_XML_DSIG))
+                .unwrap_or(false)
+        })
+        .collect();
+
+    // keep the elements that are signed, a descendant of a signed element or an ancestor of a
+    // signed element
+    let mut report = VerificationReport::default();
+    let mut verified = HashSet::new();
+    let mut covered = HashSet::new();
+    for signature in signatures {
+        let (signature_report, signed_nodes) = verify_signature(&document, signature, certs)?;
+        for signed_node in signed_nodes {
+            covered.extend(document.descendants(signed_node));
+            verified.extend(document.ancestors(signed_node));
+        }
+        report.signatures.push(signature_report);
+    }
+    verified.extend(covered.iter().copied());
+
+    // several assertions are only accepted when each is covered by a signature, so that none can
+    // be smuggled in next to a signed one
+    if assertions.len() > 1 && !assertions.iter().all(|node| covered.contains(node)) {
+        return Err(Error::MultipleAssertions);
+    }
+
+    for node in document.elements() {
+        if verified.contains(&node) {
+            continue;
+        }
+        // the descendants of an unverified element are unverified too, only report the topmost
+        if document
+            .parent(node)
+            .map(|parent| verified.contains(&parent))
+            .unwrap_or(true)
+        {
+            report.removed_elements.push(element_path(&document, node));
+        }
+        document.detach(node);
+    }
+
+    report.reduced_xml = document.to_xml();
+    Ok(report)
+}
@@ -0,0 +1,391 @@
+//!
+//! Which attributes of the user the IdP releases to which SP
+//!
+use super::response_builder::ResponseAttribute;
+use super::sp_extractor::SPMetadataExtractor;
+use crate::attribute::{
+    AttributeValue, TypedValue, DISPLAY_NAME_URI, EDU_PERSON_PRINCIPAL_NAME_URI,
+    EDU_PERSON_SCOPED_AFFILIATION_URI, EDU_PERSON_TARGETED_ID_URI, GIVEN_NAME_URI, MAIL_URI,
+    PAIRWISE_ID_URI, SUBJECT_ID_URI, SURNAME_URI,
+};
+use crate::metadata::{
+    RequestedAttribute, GEANT_CODE_OF_CONDUCT, REFEDS_CODE_OF_CONDUCT, RESEARCH_AND_SCHOLARSHIP,
+};
+use std::fmt;
+
+/// The attribute bundle of the REFEDS Research and Scholarship entity category.
+pub static RESEARCH_AND_SCHOLARSHIP_ATTRIBUTES: &[&str] = &[
+    EDU_PERSON_PRINCIPAL_NAME_URI,
+    MAIL_URI,
+    DISPLAY_NAME_URI,
+    GIVEN_NAME_URI,
+    SURNAME_URI,
+    EDU_PERSON_SCOPED_AFFILIATION_URI,
+    EDU_PERSON_TARGETED_ID_URI,
+    PAIRWISE_ID_URI,
+    SUBJECT_ID_URI,
+];
+
+/// The SPs a rule applies to.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SpMatcher {
+    Any,
+    EntityId(String),
+    /// SPs whose metadata lists the entity category, such as [`RESEARCH_AND_SCHOLARSHIP`].
+    EntityCategory(String),
+    /// SPs whose metadata has an entity attribute with the given Name and value.
+    EntityAttribute {
+        name: String,
+        value: String,
+    },
+    /// SPs matched by any of the matchers.
+    AnyOf(Vec<SpMatcher>),
+}
+
+impl SpMatcher {
+    pub fn matches(&self, sp: &SPMetadataExtractor) -> bool {
+        match self {
+            SpMatcher::Any => true,
+            SpMatcher::EntityId(entity_id) => sp.issuer() == entity_id,
+            SpMatcher::EntityCategory(category) => sp
+                .entity_descriptor()
+                .entity_categories()
+                .contains(&category.as_str()),
+            SpMatcher::EntityAttribute { name, value } => sp
+                .entity_descriptor()
+                .entity_attribute_values(name)
+                .contains(&value.as_str()),
+            SpMatcher::AnyOf(matchers) => matchers.iter().any(|matcher| matcher.matches(sp)),
+        }
+    }
+}
+
+/// The attributes of the user a rule applies to.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AttributeMatcher {
+    Any,
+    /// Attributes with one of the Names or FriendlyNames.
+    Names(Vec<String>),
+}
+
+impl AttributeMatcher {
+    pub fn names<S: Into<String>>(names: impl IntoIterator<Item = S>) -> Self {
+        AttributeMatcher::Names(names.into_iter().map(Into::into).collect())
+    }
+
+    pub fn matches(&self, attribute: &ResponseAttribute) -> bool {
+        match self {
+            AttributeMatcher::Any => true,
+            AttributeMatcher::Names(names) => names.iter().any(|name| {
+                name == &attribute.required_attribute.name
+                    || attribute.friendly_name.as_ref() == Some(name)
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Effect {
+    Release,
+    Deny,
+}
+
+/// Releases or denies attributes to SPs. Denials win over releases.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReleaseRule {
+    /// Names the rule in the decisions.
+    pub id: String,
+    pub sp: SpMatcher,
+    pub attributes: AttributeMatcher,
+    pub effect: Effect,
+    /// Only release the attributes the SP requests in its metadata.
+    pub only_requested: bool,
+}
+
+impl ReleaseRule {
+    pub fn release(id: impl Into<String>, sp: SpMatcher, attributes: AttributeMatcher) -> Self {
+        ReleaseRule {
+            id: id.into(),
+            sp,
+            attributes,
+            effect: Effect::Release,
+            only_requested: false,
+        }
+    }
+
+    pub fn deny(id: impl Into<String>, sp: SpMatcher, attributes: AttributeMatcher) -> Self {
+        ReleaseRule {
+            effect: Effect::Deny,
+            ..Self::release(id, sp, attributes)
+        }
+    }
+
+    pub fn only_requested(mut self) -> Self {
+        self.only_requested = true;
+        self
+    }
+}
+
+/// Why an attribute was released or not.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Reason {
+    Released {
+        rule: String,
+    },
+    Denied {
+        rule: String,
+    },
+    /// No rule releases the attribute to the SP.
+    NoMatchingRule,
+    /// The rules releasing the attribute only release requested attributes, and the SP does not
+    /// request it.
+    NotRequested {
+        rule: String,
+    },
+    /// The SP requests the attribute with values the user has none of.
+    NoAcceptableValue {
+        rule: String,
+    },
+}
+
+/// The decision about one attribute of the user.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Decision {
+    /// The Name of the attribute.
+    pub attribute: String,
+    pub reason: Reason,
+    /// The number of values left out because the SP does not accept them.
+    pub filtered_values: usize,
+}
+
+impl Decision {
+    pub fn is_released(&self) -> bool {
+        matches!(self.reason, Reason::Released { .. })
+    }
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            Reason::Released { rule } => write!(f, "{} released by {}", self.attribute, rule)?,
+            Reason::Denied { rule } => write!(f, "{} denied by {}", self.attribute, rule)?,
+            Reason::NoMatchingRule => write!(f, "{} not released by any rule", self.attribute)?,
+            Reason::NotRequested { rule } => write!(
+                f,
+                "{} not requested by the SP, as {} requires",
+                self.attribute, rule
+            )?,
+            Reason::NoAcceptableValue { rule } => write!(
+                f,
+                "{} released by {} but no value is accepted by the SP",
+                self.attribute, rule
+            )?,
+        }
+        if self.filtered_values > 0 {
+            write!(f, " ({} values filtered)", self.filtered_values)?;
+        }
+        Ok(())
+    }
+}
+
+/// The attributes to put in the response, and the decision about every attribute of the user.
+#[derive(Clone, Debug)]
+pub struct Release {
+    pub attributes: Vec<ResponseAttribute>,
+    pub decisions: Vec<Decision>,
+    /// The Names of the attributes the SP requires but does not get, either because the user has
+    /// none or because they were not released.
+    pub missing_required: Vec<String>,
+}
+
+/// Decides which attributes of a user go to an SP, from the declarative rules, the attributes the
+/// SP requests and the entity categories of its metadata.
+///
+/// ```ignore
+/// let policy = AttributeReleasePolicy::new()
+///     .with_research_and_scholarship()
+///     .with_code_of_conduct()
+///     .with_rule(ReleaseRule::deny(
+///         "no-mail-to-tracker",
+///         SpMatcher::EntityId("https://tracker.example.com".to_string()),
+///         AttributeMatcher::names([MAIL_URI]),
+///     ));
+/// let release = policy.release(
+///     &sp,
+///     request.attribute_consuming_service_index,
+///     &user_attributes,
+/// );
+/// for decision in &release.decisions {
+///     println!("{}: {}", sp.issuer(), decision);
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct AttributeReleasePolicy {
+    pub rules: Vec<ReleaseRule>,
+}
+
+impl AttributeReleasePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_rule(mut self, rule: ReleaseRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Releases the Research and Scholarship attribute bundle to the SPs of that category.
+    pub fn with_research_and_scholarship(self) -> Self {
+        self.with_rule(ReleaseRule::release(
+            "research-and-scholarship",
+            SpMatcher::EntityCategory(RESEARCH_AND_SCHOLARSHIP.to_string()),
+            AttributeMatcher::names(RESEARCH_AND_SCHOLARSHIP_ATTRIBUTES.iter().copied()),
+        ))
+    }
+
+    /// Releases the attributes they request to the SPs committed to the GÉANT or REFEDS Data
+    /// Protection Code of Conduct.
+    pub fn with_code_of_conduct(self) -> Self {
+        self.with_rule(
+            ReleaseRule::release(
+                "code-of-conduct",
+                SpMatcher::AnyOf(vec![
+                    SpMatcher::EntityCategory(GEANT_CODE_OF_CONDUCT.to_string()),
+                    SpMatcher::EntityCategory(REFEDS_CODE_OF_CONDUCT.to_string()),
+                ]),
+                AttributeMatcher::Any,
+            )
+            .only_requested(),
+        )
+    }
+
+    /// Decides which of the attributes of the user are released to the SP. The requested
+    /// attributes are those of the AttributeConsumingService with the given index, or of the
+    /// default one. When the SP lists the values it accepts for an attribute, only those are
+    /// released.
+    pub fn release(
+        &self,
+        sp: &SPMetadataExtractor,
+        attribute_consuming_service_index: Option<u16>,
+        user_attributes: &[ResponseAttribute],
+    ) -> Release {
+        let requested = sp.requested_attributes(attribute_consuming_service_index);
+        let rules = self
+            .rules
+            .iter()
+            .filter(|rule| rule.sp.matches(sp))
+            .collect::<Vec<_>>();
+
+        let mut attributes = vec![];
+        let mut decisions = vec![];
+        for attribute in user_attributes {
+            let requested_attribute = requested
+                .iter()
+                .find(|ra| ra.name == attribute.required_attribute.name);
+            let (reason, released) = decide(&rules, attribute, requested_attribute);
+            let mut decision = Decision {
+                attribute: attribute.required_attribute.name.clone(),
+                reason,
+                filtered_values: 0,
+            };
+            if let Some(mut released) = released {
+                decision.filtered_values = attribute.values.len() - released.values.len();
+                if released.friendly_name.is_none() {
+                    released.friendly_name =
+                        requested_attribute.and_then(|ra| ra.friendly_name.clone());
+                }
+                attributes.push(released);
+            }
+            decisions.push(decision);
+        }
+
+        let missing_required = requested
+            .iter()
+            .filter(|ra| ra.is_required())
+            .filter(|ra| {
+                !attributes
+                    .iter()
+                    .any(|attribute| attribute.required_attribute.name == ra.name)
+            })
+            .map(|ra| ra.name.clone())
+            .collect();
+        Release {
+            attributes,
+            decisions,
+            missing_required,
+        }
+    }
+}
+
+/// The reason for the decision about an attribute, and the attribute with its acceptable values
+/// when it is released.
+fn decide(
+    rules: &[&ReleaseRule],
+    attribute: &ResponseAttribute,
+    requested_attribute: Option<&RequestedAttribute>,
+) -> (Reason, Option<ResponseAttribute>) {
+    let matching = rules
+        .iter()
+        .filter(|rule| rule.attributes.matches(attribute))
+        .collect::<Vec<_>>();
+    if let Some(rule) = matching.iter().find(|rule| rule.effect == Effect::Deny) {
+        return (
+            Reason::Denied {
+                rule: rule.id.clone(),
+            },
+            None,
+        );
+    }
+    let mut releasing = matching
+        .iter()
+        .filter(|rule| rule.effect == Effect::Release);
+    let rule = match releasing
+        .clone()
+        .find(|rule| !rule.only_requested || requested_attribute.is_some())
+    {
+        Some(rule) => rule,
+        None => {
+            return match releasing.next() {
+                Some(rule) => (
+                    Reason::NotRequested {
+                        rule: rule.id.clone(),
+                    },
+                    None,
+                ),
+                None => (Reason::NoMatchingRule, None),
+            }
+        }
+    };
+
+    let values = attribute
+        .values
+        .iter()
+        .filter(|value| requested_attribute.map_or(true, |ra| ra.accepts(&value_text(value))))
+        .cloned()
+        .collect::<Vec<_>>();
+    if values.is_empty() {
+        return (
+            Reason::NoAcceptableValue {
+                rule: rule.id.clone(),
+            },
+            None,
+        );
+    }
+    (
+        Reason::Released {
+            rule: rule.id.clone(),
+        },
+        Some(ResponseAttribute {
+            values,
+            ..attribute.clone()
+        }),
+    )
+}
+
+/// The value as written in the response, to compare with the values the SP accepts.
+fn value_text(value: &TypedValue) -> String {
+    match value {
+        TypedValue::NameId(name_id) => name_id.value.clone(),
+        value => AttributeValue::from(value).value.unwrap_or_default(),
+    }
+}
@@ -5,6 +5,8 @@ pub enum Error {
     NoSignature,
     NoKeyInfo,
     NoCertificate,
+    NoNextKey,
+    PrivateKeyNotExportable,
     NoSPSsoDescriptors,
     SignatureFailed,
     UnexpectedError,
@@ -13,11 +15,65 @@ pub enum Error {
 
     MissingAudience,
     MissingAcsUrl,
-    NonHttpPostBindingUnsupported,
+    MissingAssertion,
+    #[snafu(display("Unsupported binding: {}", binding))]
+    UnsupportedBinding {
+        binding: String,
+    },
 
     MissingAuthnRequestSubjectNameID,
     MissingAuthnRequestIssuer,
 
+    #[snafu(display("Cannot satisfy the NameIDPolicy: {}", reason))]
+    InvalidNameIDPolicy {
+        reason: String,
+    },
+
+    #[snafu(display("AuthnRequest from an unknown SP: {}", entity_id))]
+    UnknownServiceProvider {
+        entity_id: String,
+    },
+    #[snafu(display("AuthnRequest is not signed"))]
+    AuthnRequestNotSigned,
+    #[snafu(display("AuthnRequest has elements not covered by its signature"))]
+    AuthnRequestPartiallySigned,
+    #[snafu(display("Invalid HTTP-Redirect query: {}", reason))]
+    InvalidRedirectQuery {
+        reason: String,
+    },
+    #[snafu(display("Invalid HTTP-Redirect signature: {}", error))]
+    InvalidRedirectSignature {
+        error: crate::signer::Error,
+    },
+    #[snafu(display(
+        "AuthnRequest signatures cannot be verified without the xmlsec or native-xmldsig feature"
+    ))]
+    SignatureVerificationUnavailable,
+    #[snafu(display(
+        "AssertionConsumerService not registered in SP metadata: url {:?}, index {:?}",
+        url,
+        index
+    ))]
+    UnregisteredAcs {
+        url: Option<String>,
+        index: Option<u16>,
+    },
+    #[snafu(display("AuthnRequest expired at: {}", time))]
+    AuthnRequestExpired {
+        time: String,
+    },
+    #[snafu(display("AuthnRequest is not valid until: {}", time))]
+    AuthnRequestNotYetValid {
+        time: String,
+    },
+    #[snafu(display(
+        "AuthnRequest Destination is not an SSO URL of the IdP: {:?}",
+        destination
+    ))]
+    DestinationMismatch {
+        destination: Option<String>,
+    },
+
     #[snafu(display("Invalid AuthnRequest: {}", error))]
     InvalidAuthnRequest {
         error: crate::schema::authn_request::Error,
@@ -28,6 +84,11 @@ pub enum Error {
         stack: openssl::error::ErrorStack,
     },
 
+    #[snafu(display("Signing Error: {}", error))]
+    SigningError {
+        error: crate::signer::Error,
+    },
+
     #[snafu(display("Verification Error: {}", error))]
     VerificationError {
         error: crate::crypto::Error,
@@ -51,3 +112,9 @@ impl From<crate::schema::authn_request::Error> for Error {
         Error::InvalidAuthnRequest { error }
     }
 }
+
+impl From<crate::signer::Error> for Error {
+    fn from(error: crate::signer::Error) -> Self {
+        Error::SigningError { error }
+    }
+}
@@ -0,0 +1,139 @@
+use super::error::Error;
+use crate::key_info::{KeyInfo, X509Data};
+use crate::metadata::KeyDescriptor;
+use crate::signer::{Signer, SoftwareSigner};
+use base64::{engine::general_purpose, Engine as _};
+use openssl::pkey::{PKey, Private};
+use openssl::x509::X509;
+use std::sync::Arc;
+
+/// A key of the IdP, either held in memory or behind a [`Signer`] such as an HSM.
+#[derive(Clone)]
+pub struct SigningKey {
+    signer: Arc<dyn Signer>,
+    private_key: Option<PKey<Private>>,
+}
+
+impl SigningKey {
+    pub fn new(private_key: PKey<Private>, certificate: X509) -> Result<Self, Error> {
+        Self::from_private_key(private_key, Some(certificate))
+    }
+
+    pub fn from_private_key(
+        private_key: PKey<Private>,
+        certificate: Option<X509>,
+    ) -> Result<Self, Error> {
+        let signer = SoftwareSigner::new(private_key.clone(), certificate)?;
+        Ok(SigningKey {
+            signer: Arc::new(signer),
+            private_key: Some(private_key),
+        })
+    }
+
+    /// A key which never leaves the signer. Its certificate is the one of the signer.
+    pub fn from_signer(signer: Arc<dyn Signer>) -> Self {
+        SigningKey {
+            signer,
+            private_key: None,
+        }
+    }
+
+    pub fn signer(&self) -> &dyn Signer {
+        self.signer.as_ref()
+    }
+
+    pub fn certificate(&self) -> Option<X509> {
+        self.signer.certificate()
+    }
+
+    /// The private key, when it is held in memory.
+    pub fn private_key(&self) -> Option<&PKey<Private>> {
+        self.private_key.as_ref()
+    }
+}
+
+/// The signing keys of an IdP. The active key signs responses, while the certificates of the
+/// next and previous keys are published in metadata as well, so that SPs keep trusting the
+/// signatures made on either side of a key rollover.
+#[derive(Clone)]
+pub struct KeySet {
+    active: SigningKey,
+    next: Option<SigningKey>,
+    previous: Vec<SigningKey>,
+}
+
+impl KeySet {
+    pub fn new(active: SigningKey) -> Self {
+        KeySet {
+            active,
+            next: None,
+            previous: vec![],
+        }
+    }
+
+    pub fn with_next(mut self, next: SigningKey) -> Self {
+        self.next = Some(next);
+        self
+    }
+
+    pub fn with_previous(mut self, previous: SigningKey) -> Self {
+        self.previous.push(previous);
+        self
+    }
+
+    pub fn active(&self) -> &SigningKey {
+        &self.active
+    }
+
+    pub fn next(&self) -> Option<&SigningKey> {
+        self.next.as_ref()
+    }
+
+    /// The keys which were active before, most recent first.
+    pub fn previous(&self) -> &[SigningKey] {
+        &self.previous
+    }
+
+    /// The certificates to publish: the active one, the next one, then the previous ones.
+    pub fn certificates(&self) -> Vec<X509> {
+        std::iter::once(&self.active)
+            .chain(self.next.iter())
+            .chain(self.previous.iter())
+            .filter_map(SigningKey::certificate)
+            .collect()
+    }
+
+    /// Describes the published certificates as signing keys, for the IdP metadata.
+    pub fn key_descriptors(&self) -> Result<Vec<KeyDescriptor>, Error> {
+        self.certificates()
+            .into_iter()
+            .map(|certificate| {
+                Ok(KeyDescriptor {
+                    key_use: Some("signing".to_string()),
+                    key_info: KeyInfo {
+                        id: None,
+                        x509_data: Some(X509Data {
+                            certificates: vec![
+                                general_purpose::STANDARD.encode(certificate.to_der()?)
+                            ],
+                        }),
+                    },
+                    encryption_methods: vec![],
+                })
+            })
+            .collect()
+    }
+
+    /// Makes the next key the active one, and the active one the most recent previous key.
+    pub fn rotate(&mut self) -> Result<(), Error> {
+        let next = self.next.take().ok_or(Error::NoNextKey)?;
+        let previous = std::mem::replace(&mut self.active, next);
+        self.previous.insert(0, previous);
+        Ok(())
+    }
+
+    /// Stops publishing all but the `keep` most recent previous keys.
+    pub fn retire_previous(&mut self, keep: usize) {
+        self.previous.truncate(keep);
+    }
+}
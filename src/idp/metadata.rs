@@ -0,0 +1,98 @@
+use super::error::Error;
+use super::key_set::KeySet;
+use crate::attribute::Attribute;
+use crate::clock::Clock;
+use crate::key_info::{KeyInfo, X509Data};
+use crate::metadata::{
+    ContactPerson, Endpoint, EntityDescriptor, Extensions, IdpSsoDescriptor, KeyDescriptor,
+    Organization, Scope,
+};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Duration;
+use openssl::x509;
+
+/// What an IdP publishes about itself in metadata, besides its signing certificates which come
+/// from its [`KeySet`].
+#[derive(Builder, Clone, Default)]
+#[builder(default, setter(into))]
+pub struct IdpMetadata {
+    pub entity_id: String,
+    pub single_sign_on_services: Vec<Endpoint>,
+    pub single_logout_services: Vec<Endpoint>,
+    pub name_id_formats: Vec<String>,
+    pub want_authn_requests_signed: Option<bool>,
+    /// The attributes the IdP can release.
+    pub attributes: Vec<Attribute>,
+    /// The domains the IdP scopes attribute values with, published as `shibmd:Scope`.
+    pub scopes: Vec<Scope>,
+    /// Certificates SPs can encrypt for, such as for encrypted NameIDs.
+    pub encryption_certificates: Vec<x509::X509>,
+    pub organization: Option<Organization>,
+    pub contact_people: Vec<ContactPerson>,
+    /// How long the metadata is valid for, from the time it is generated.
+    pub valid_duration: Option<Duration>,
+    /// How long SPs may cache the metadata before fetching it again.
+    pub cache_duration: Option<Duration>,
+}
+
+impl IdpMetadata {
+    /// Describes the IdP with the certificates of the key set, valid for `valid_duration` from the
+    /// time of the clock.
+    pub fn to_entity_descriptor(
+        &self,
+        keys: &KeySet,
+        clock: &dyn Clock,
+    ) -> Result<EntityDescriptor, Error> {
+        let valid_until = self.valid_duration.map(|d| &clock.now() + d);
+
+        let mut key_descriptors = keys.key_descriptors()?;
+        for certificate in &self.encryption_certificates {
+            key_descriptors.push(KeyDescriptor {
+                key_use: Some("encryption".to_string()),
+                key_info: KeyInfo {
+                    id: None,
+                    x509_data: Some(X509Data {
+                        certificates: vec![general_purpose::STANDARD.encode(certificate.to_der()?)],
+                    }),
+                },
+                encryption_methods: vec![],
+            });
+        }
+
+        let idp_sso_descriptor = IdpSsoDescriptor {
+            protocol_support_enumeration: "urn:oasis:names:tc:SAML:2.0:protocol".to_string(),
+            valid_until: valid_until.clone(),
+            want_authn_requests_signed: self.want_authn_requests_signed,
+            extensions: (!self.scopes.is_empty()).then(|| Extensions {
+                scopes: self.scopes.clone(),
+                ..Extensions::default()
+            }),
+            key_descriptors,
+            single_logout_services: self.single_logout_services.clone(),
+            name_id_formats: self.name_id_formats.clone(),
+            single_sign_on_services: self.single_sign_on_services.clone(),
+            attributes: self.attributes.clone(),
+            ..IdpSsoDescriptor::default()
+        };
+
+        Ok(EntityDescriptor {
+            entity_id: self.entity_id.clone(),
+            valid_until,
+            cache_duration: self.cache_duration.map(xml_duration),
+            idp_sso_descriptors: vec![idp_sso_descriptor],
+            organization: self.organization.clone(),
+            contact_person: self.contact_people.clone(),
+            ..EntityDescriptor::default()
+        })
+    }
+}
+
+/// Formats a duration as an `xs:duration`, in seconds.
+fn xml_duration(duration: Duration) -> String {
+    let seconds = duration.num_seconds();
+    if seconds < 0 {
+        format!("-PT{}S", -seconds)
+    } else {
+        format!("PT{}S", seconds)
+    }
+}
@@ -1,25 +1,39 @@
+pub mod attribute_release;
 pub mod error;
 use self::error::Error;
 
+pub mod key_set;
+use self::key_set::{KeySet, SigningKey};
+
+pub mod metadata;
+pub mod name_id;
+use self::metadata::IdpMetadata;
+
+pub mod request_validator;
 pub mod response_builder;
 pub mod sp_extractor;
 pub mod verified_request;
 
-#[cfg(feature = "xmlsec")]
-use crate::crypto;
-#[cfg(feature = "xmlsec")]
-use crate::idp::response_builder::{build_response_template, ResponseAttribute};
-#[cfg(feature = "xmlsec")]
+use crate::clock::{Clock, SystemClock};
+use crate::idp::response_builder::{
+    response_for_request, with_signature_template, AuthnResponse, ErrorResponse, ResponseAttribute,
+};
+use crate::metadata::{Endpoint, EntityDescriptor, HTTP_POST_BINDING, HTTP_REDIRECT_BINDING};
 use crate::schema::Response;
+use crate::signer;
+use crate::utils;
 use openssl::bn::{BigNum, MsbOption};
 use openssl::nid::Nid;
 use openssl::pkey::Private;
 use openssl::{asn1::Asn1Time, pkey, rsa::Rsa, x509};
-#[cfg(feature = "xmlsec")]
 use std::str::FromStr;
+use std::sync::{Arc, RwLock, RwLockReadGuard};
 
+/// An IdP signing with the active key of its [`KeySet`]. The key set can be replaced or rotated
+/// through a shared reference, so that callers holding the IdP pick up the new key right away.
 pub struct IdentityProvider {
-    private_key: pkey::PKey<Private>,
+    keys: RwLock<KeySet>,
+    clock: Arc<dyn Clock>,
 }
 
 pub enum KeyType {
@@ -49,22 +63,120 @@ impl IdentityProvider {
         let rsa = Rsa::generate(key_type.bit_length())?;
         let private_key = pkey::PKey::from_rsa(rsa)?;
 
-        Ok(IdentityProvider { private_key })
+        Self::from_private_key(private_key)
     }
 
     pub fn from_private_key_der(der_bytes: &[u8]) -> Result<Self, Error> {
         let rsa = Rsa::private_key_from_der(der_bytes)?;
         let private_key = pkey::PKey::from_rsa(rsa)?;
 
-        Ok(IdentityProvider { private_key })
+        Self::from_private_key(private_key)
+    }
+
+    fn from_private_key(private_key: pkey::PKey<Private>) -> Result<Self, Error> {
+        Ok(Self::from_key_set(KeySet::new(
+            SigningKey::from_private_key(private_key, None)?,
+        )))
+    }
+
+    pub fn from_key_set(keys: KeySet) -> Self {
+        IdentityProvider {
+            keys: RwLock::new(keys),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Replaces the system clock, which dates responses and metadata.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    fn keys(&self) -> RwLockReadGuard<'_, KeySet> {
+        self.keys
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Returns a copy of the current key set.
+    pub fn key_set(&self) -> KeySet {
+        self.keys().clone()
+    }
+
+    /// Replaces the key set, for instance after reloading it from storage.
+    pub fn set_key_set(&self, keys: KeySet) {
+        *self
+            .keys
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = keys;
+    }
+
+    /// Makes the next key the active one, see [`KeySet::rotate`].
+    pub fn rotate_keys(&self) -> Result<(), Error> {
+        self.keys
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .rotate()
+    }
+
+    /// Describes the IdP with the certificates of all its keys, and a single sign-on service at
+    /// `sso_location` for the HTTP-Redirect and HTTP-POST bindings.
+    pub fn metadata(&self, entity_id: &str, sso_location: &str) -> Result<EntityDescriptor, Error> {
+        let single_sign_on_services = [HTTP_REDIRECT_BINDING, HTTP_POST_BINDING]
+            .iter()
+            .map(|binding| Endpoint {
+                binding: binding.to_string(),
+                location: sso_location.to_string(),
+                response_location: None,
+            })
+            .collect();
+        let metadata = IdpMetadata {
+            entity_id: entity_id.to_string(),
+            single_sign_on_services,
+            ..IdpMetadata::default()
+        };
+        self.build_metadata(&metadata)
+    }
+
+    /// Describes the IdP in metadata, with the certificates of all its keys.
+    pub fn build_metadata(&self, metadata: &IdpMetadata) -> Result<EntityDescriptor, Error> {
+        metadata.to_entity_descriptor(&self.keys(), self.clock())
+    }
+
+    /// Describes the IdP in metadata signed with the active key, which must have a certificate.
+    pub fn signed_metadata(
+        &self,
+        metadata: &IdpMetadata,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let keys = self.key_set();
+        keys.active().certificate().ok_or(Error::NoCertificate)?;
+        let mut entity_descriptor = metadata.to_entity_descriptor(&keys, self.clock())?;
+        entity_descriptor.id = Some(utils::gen_saml_response_id());
+
+        let xml = yaserde::ser::to_string(&entity_descriptor)?;
+        Ok(signer::sign_xml(&xml, keys.active().signer())?)
+    }
+
+    fn active_private_key(&self) -> Result<pkey::PKey<Private>, Error> {
+        self.keys()
+            .active()
+            .private_key()
+            .cloned()
+            .ok_or(Error::PrivateKeyNotExportable)
     }
 
     pub fn export_private_key_der(&self) -> Result<Vec<u8>, Error> {
-        let rsa: Rsa<Private> = self.private_key.rsa()?;
+        let rsa: Rsa<Private> = self.active_private_key()?.rsa()?;
         Ok(rsa.private_key_to_der()?)
     }
 
+    /// Creates a self-signed certificate for the active private key.
     pub fn create_certificate(&self, params: &CertificateParams) -> Result<Vec<u8>, Error> {
+        let private_key = self.active_private_key()?;
         let mut name = x509::X509Name::builder()?;
         name.append_entry_by_nid(Nid::COMMONNAME, params.common_name)?;
         let name = name.build();
@@ -85,7 +197,7 @@ impl IdentityProvider {
         builder.set_version(2)?;
         builder.set_subject_name(&name)?;
         builder.set_issuer_name(&iss)?;
-        builder.set_pubkey(&self.private_key)?;
+        builder.set_pubkey(&private_key)?;
 
         let starts = Asn1Time::days_from_now(0)?; // now
         builder.set_not_before(&starts)?;
@@ -93,14 +205,15 @@ impl IdentityProvider {
         let expires = Asn1Time::days_from_now(params.days_until_expiration)?;
         builder.set_not_after(&expires)?;
 
-        builder.sign(&self.private_key, openssl::hash::MessageDigest::sha256())?;
+        builder.sign(&private_key, openssl::hash::MessageDigest::sha256())?;
 
         let certificate: x509::X509 = builder.build();
         Ok(certificate.to_der()?)
     }
 
+    /// Builds a response and signs it with the active key. The certificate of the active key is
+    /// embedded in the signature; `idp_x509_cert_der` is only a fallback for keys without one.
     #[allow(clippy::too_many_arguments)]
-    #[cfg(feature = "xmlsec")]
     pub fn sign_authn_response(
         &self,
         idp_x509_cert_der: &[u8],
@@ -111,22 +224,86 @@ impl IdentityProvider {
         in_response_to_id: &str,
         attributes: &[ResponseAttribute],
     ) -> Result<Response, Box<dyn std::error::Error>> {
-        let response = build_response_template(
-            idp_x509_cert_der,
+        self.build_signed_response(
+            Some(idp_x509_cert_der),
+            subject_name_id,
+            audience,
+            acs_url,
+            issuer,
+            in_response_to_id,
+            attributes,
+        )
+    }
+
+    /// Builds a response and signs it with the active key, embedding the active certificate.
+    pub fn sign_response(
+        &self,
+        subject_name_id: &str,
+        audience: &str,
+        acs_url: &str,
+        issuer: &str,
+        in_response_to_id: &str,
+        attributes: &[ResponseAttribute],
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        self.build_signed_response(
+            None,
+            subject_name_id,
+            audience,
+            acs_url,
+            issuer,
+            in_response_to_id,
+            attributes,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn build_signed_response(
+        &self,
+        fallback_cert_der: Option<&[u8]>,
+        subject_name_id: &str,
+        audience: &str,
+        acs_url: &str,
+        issuer: &str,
+        in_response_to_id: &str,
+        attributes: &[ResponseAttribute],
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        let active = self.keys().active().clone();
+        let cert_der = match active.certificate() {
+            Some(certificate) => certificate.to_der()?,
+            None => fallback_cert_der.ok_or(Error::NoCertificate)?.to_vec(),
+        };
+        let response = response_for_request(
             subject_name_id,
             audience,
             issuer,
             acs_url,
             in_response_to_id,
             attributes,
-        );
+        )
+        .to_response(self.clock());
+        let response = with_signature_template(response, &cert_der);
 
         let response_xml_unsigned = yaserde::ser::to_string(&response)?;
-        let signed_xml = crypto::sign_xml(
-            response_xml_unsigned.as_str(),
-            self.export_private_key_der()?.as_slice(),
-        )?;
-        let signed_response = Response::from_str(signed_xml.as_str())?;
-        Ok(signed_response)
+        let signed_xml = signer::sign_xml(&response_xml_unsigned, active.signer())?;
+        Ok(Response::from_str(signed_xml.as_str())?)
+    }
+
+    /// Builds the response and signs the parts it asks for with the active key. The XML is
+    /// returned as signed, since serializing a parsed response again can break its signatures.
+    pub fn signed_response(
+        &self,
+        response: &AuthnResponse,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let active = self.keys().active().clone();
+        response.to_signed_xml(active.signer(), self.clock())
+    }
+
+    /// Builds an error response and signs it with the active key.
+    pub fn signed_error_response(
+        &self,
+        response: &ErrorResponse,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let active = self.keys().active().clone();
+        response.to_signed_xml(active.signer(), self.clock())
     }
 }
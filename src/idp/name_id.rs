@@ -0,0 +1,170 @@
+//!
+//! The NameIDs the IdP gives users, following the NameIDPolicy of the AuthnRequest
+//!
+use super::error::Error;
+use super::sp_extractor::SPMetadataExtractor;
+use crate::metadata::NameIdFormat;
+use crate::schema::{AuthnRequest, NameId, NameIdPolicy};
+use crate::utils;
+use base64::{engine::general_purpose, Engine as _};
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+
+const NAME_ID_FORMAT_UNSPECIFIED_2_0: &str =
+    "urn:oasis:names:tc:SAML:2.0:nameid-format:unspecified";
+
+/// The user a NameID is given to.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Principal {
+    /// The stable identifier of the user at the IdP, never sent as is except in the unspecified
+    /// format.
+    pub user_id: String,
+    pub email: Option<String>,
+    /// Whether the SP already knows the user by a persistent NameID. Persistent NameIDs are only
+    /// given to new users when the request allows them to be created.
+    pub has_persistent_id: bool,
+}
+
+/// Makes NameIDs in the transient, persistent, email address and unspecified formats.
+///
+/// Persistent NameIDs are pairwise: a keyed hash of the user ID and the SP entity ID, so that an
+/// SP always gets the same one for a user and SPs cannot correlate their users. The key must be
+/// kept secret and must not change, or the SPs will not recognize their users anymore.
+#[derive(Clone)]
+pub struct NameIdGenerator {
+    idp_entity_id: String,
+    persistent_key: Vec<u8>,
+    supported_formats: Vec<NameIdFormat>,
+    default_format: NameIdFormat,
+}
+
+impl NameIdGenerator {
+    pub fn new(idp_entity_id: impl Into<String>, persistent_key: impl Into<Vec<u8>>) -> Self {
+        NameIdGenerator {
+            idp_entity_id: idp_entity_id.into(),
+            persistent_key: persistent_key.into(),
+            supported_formats: vec![
+                NameIdFormat::PersistentNameIDFormat,
+                NameIdFormat::TransientNameIDFormat,
+                NameIdFormat::EmailAddressNameIDFormat,
+                NameIdFormat::UnspecifiedNameIDFormat,
+            ],
+            default_format: NameIdFormat::TransientNameIDFormat,
+        }
+    }
+
+    /// Restricts the formats the IdP gives out.
+    pub fn with_supported_formats(mut self, formats: Vec<NameIdFormat>) -> Self {
+        self.supported_formats = formats;
+        self
+    }
+
+    /// The format used when neither the request nor the SP metadata names a supported one.
+    pub fn with_default_format(mut self, format: NameIdFormat) -> Self {
+        self.default_format = format;
+        self
+    }
+
+    /// The NameID of the user for an AuthnRequest of the SP.
+    pub fn for_request(
+        &self,
+        principal: &Principal,
+        request: &AuthnRequest,
+        sp: &SPMetadataExtractor,
+    ) -> Result<NameId, Error> {
+        self.generate(
+            principal,
+            sp.issuer(),
+            request.name_id_policy.as_ref(),
+            &sp.name_id_formats(),
+        )
+    }
+
+    /// The NameID of the user for the SP. The format is the one of the policy; when it leaves the
+    /// format unspecified, the first supported one of `sp_formats`, from the SP metadata, or else
+    /// the default format. The unspecified format, which gives out the user ID, is only used as
+    /// the default format.
+    pub fn generate(
+        &self,
+        principal: &Principal,
+        sp_entity_id: &str,
+        policy: Option<&NameIdPolicy>,
+        sp_formats: &[&str],
+    ) -> Result<NameId, Error> {
+        let invalid = |reason: String| Error::InvalidNameIDPolicy { reason };
+        if let Some(sp_name_qualifier) = policy.and_then(|p| p.sp_name_qualifier.as_deref()) {
+            if sp_name_qualifier != sp_entity_id {
+                return Err(invalid(format!(
+                    "SPNameQualifier {} is not the requester",
+                    sp_name_qualifier
+                )));
+            }
+        }
+
+        let format = match policy
+            .and_then(|p| p.format.as_deref())
+            .filter(|format| !is_unspecified(format))
+        {
+            Some(requested) => self
+                .supported_format(requested)
+                .ok_or_else(|| invalid(format!("format {} is not supported", requested)))?,
+            None => sp_formats
+                .iter()
+                .filter(|format| !is_unspecified(format))
+                .find_map(|format| self.supported_format(format))
+                .unwrap_or_else(|| self.default_format.clone()),
+        };
+
+        let value = match format {
+            NameIdFormat::TransientNameIDFormat => utils::gen_saml_assertion_id(),
+            NameIdFormat::PersistentNameIDFormat => {
+                let allow_create = policy.and_then(|p| p.allow_create).unwrap_or(true);
+                if !allow_create && !principal.has_persistent_id {
+                    return Err(invalid(
+                        "AllowCreate is false and the user has no persistent NameID".to_string(),
+                    ));
+                }
+                self.persistent_id(&principal.user_id, sp_entity_id)?
+            }
+            NameIdFormat::EmailAddressNameIDFormat => principal
+                .email
+                .clone()
+                .ok_or_else(|| invalid("the user has no email address".to_string()))?,
+            NameIdFormat::UnspecifiedNameIDFormat => principal.user_id.clone(),
+        };
+        let qualified = matches!(
+            format,
+            NameIdFormat::TransientNameIDFormat | NameIdFormat::PersistentNameIDFormat
+        );
+        Ok(NameId {
+            name_qualifier: qualified.then(|| self.idp_entity_id.clone()),
+            sp_name_qualifier: qualified.then(|| sp_entity_id.to_string()),
+            format: Some(format.value().to_string()),
+            sp_provided_id: None,
+            value,
+        })
+    }
+
+    /// The persistent NameID of a user for an SP: the HMAC-SHA256 of both, base64 encoded.
+    pub fn persistent_id(&self, user_id: &str, sp_entity_id: &str) -> Result<String, Error> {
+        let key = PKey::hmac(&self.persistent_key)?;
+        let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+        signer.update(user_id.as_bytes())?;
+        signer.update(b"!")?;
+        signer.update(sp_entity_id.as_bytes())?;
+        Ok(general_purpose::STANDARD.encode(signer.sign_to_vec()?))
+    }
+
+    fn supported_format(&self, format: &str) -> Option<NameIdFormat> {
+        self.supported_formats
+            .iter()
+            .find(|supported| supported.value() == format)
+            .cloned()
+    }
+}
+
+fn is_unspecified(format: &str) -> bool {
+    format == NameIdFormat::UnspecifiedNameIDFormat.value()
+        || format == NAME_ID_FORMAT_UNSPECIFIED_2_0
+}
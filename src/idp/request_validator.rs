@@ -0,0 +1,221 @@
+//!
+//! Validation of the AuthnRequests received by the IdP, against the metadata of the SPs it trusts
+//!
+use super::error::Error;
+use super::sp_extractor::{Acs, SPMetadataExtractor};
+use crate::clock::{Clock, SystemClock};
+#[cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
+use crate::crypto;
+use crate::schema::AuthnRequest;
+use crate::signer::RedirectQuery;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{Duration, SecondsFormat};
+use flate2::read::DeflateDecoder;
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Arc;
+
+/// The largest inflated AuthnRequest of the HTTP-Redirect binding, against decompression bombs.
+const MAX_INFLATED_REQUEST_SIZE: u64 = 1024 * 1024;
+
+/// Looks up the metadata of a trusted SP by entity ID.
+pub trait ServiceProviderStore {
+    fn find(&self, entity_id: &str) -> Option<&SPMetadataExtractor>;
+}
+
+impl ServiceProviderStore for SPMetadataExtractor {
+    fn find(&self, entity_id: &str) -> Option<&SPMetadataExtractor> {
+        Some(self).filter(|sp| sp.issuer() == entity_id)
+    }
+}
+
+impl ServiceProviderStore for Vec<SPMetadataExtractor> {
+    fn find(&self, entity_id: &str) -> Option<&SPMetadataExtractor> {
+        self.iter().find(|sp| sp.issuer() == entity_id)
+    }
+}
+
+impl ServiceProviderStore for HashMap<String, SPMetadataExtractor> {
+    fn find(&self, entity_id: &str) -> Option<&SPMetadataExtractor> {
+        self.get(entity_id)
+    }
+}
+
+/// An AuthnRequest which passed [`AuthnRequestValidator::validate_xml`] or
+/// [`AuthnRequestValidator::validate_redirect`], along with the AssertionConsumerService the
+/// response must be sent to.
+#[derive(Clone, Debug)]
+pub struct ValidatedAuthnRequest {
+    pub request: AuthnRequest,
+    /// The entity ID of the SP which issued the request.
+    pub sp_entity_id: String,
+    pub acs: Acs,
+    /// Whether the request carried a valid signature.
+    pub signed: bool,
+    /// The RelayState of a request of the HTTP-Redirect binding, covered by its signature when
+    /// `signed`. The HTTP-POST binding carries it in a form field of its own.
+    pub relay_state: Option<String>,
+}
+
+/// Checks that an AuthnRequest comes from a known SP, is signed with a key of its metadata when
+/// required, asks for a registered AssertionConsumerService, is recent and is meant for this IdP.
+///
+/// Requests of the HTTP-POST binding carry an enveloped signature and go through
+/// [`AuthnRequestValidator::validate_xml`]. Those of the HTTP-Redirect binding are signed in their
+/// query string and go through [`AuthnRequestValidator::validate_redirect`].
+pub struct AuthnRequestValidator<S> {
+    pub service_providers: S,
+    /// The SSO URLs of the IdP, one of which the Destination of a request must be.
+    pub sso_urls: Vec<String>,
+    /// Require signed requests even from SPs whose metadata does not set `AuthnRequestsSigned`.
+    pub want_authn_requests_signed: bool,
+    pub max_issue_delay: Duration,
+    pub max_clock_skew: Duration,
+    pub clock: Arc<dyn Clock>,
+}
+
+impl<S: ServiceProviderStore> AuthnRequestValidator<S> {
+    pub fn new(service_providers: S, sso_urls: Vec<String>) -> Self {
+        AuthnRequestValidator {
+            service_providers,
+            sso_urls,
+            want_authn_requests_signed: false,
+            max_issue_delay: Duration::seconds(90),
+            max_clock_skew: Duration::seconds(180),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn validate_xml(&self, xml: &str) -> Result<ValidatedAuthnRequest, Error> {
+        self.validate(xml, None)
+    }
+
+    /// Validates an AuthnRequest of the HTTP-Redirect binding, given the query string of the URL
+    /// it was received on. Its signature, if any, is in the `SigAlg` and `Signature` parameters.
+    pub fn validate_redirect(&self, query: &str) -> Result<ValidatedAuthnRequest, Error> {
+        let invalid = |reason: &str| Error::InvalidRedirectQuery {
+            reason: reason.to_string(),
+        };
+        let query = RedirectQuery::parse(query).map_err(|error| invalid(&error.to_string()))?;
+        let encoded = query
+            .message
+            .as_ref()
+            .filter(|message| message.name == "SAMLRequest")
+            .ok_or_else(|| invalid("no SAMLRequest parameter"))?;
+        let compressed = general_purpose::STANDARD
+            .decode(encoded.value.replace(char::is_whitespace, ""))
+            .map_err(|_| invalid("SAMLRequest is not base64"))?;
+        let mut xml = String::new();
+        DeflateDecoder::new(compressed.as_slice())
+            .take(MAX_INFLATED_REQUEST_SIZE)
+            .read_to_string(&mut xml)
+            .map_err(|_| invalid("SAMLRequest cannot be inflated"))?;
+        self.validate(&xml, Some(&query))
+    }
+
+    fn validate(
+        &self,
+        xml: &str,
+        redirect_query: Option<&RedirectQuery>,
+    ) -> Result<ValidatedAuthnRequest, Error> {
+        let request: AuthnRequest = xml.parse()?;
+        let sp_entity_id = request
+            .issuer_value()
+            .ok_or(Error::MissingAuthnRequestIssuer)?;
+        let sp = self.service_providers.find(&sp_entity_id).ok_or_else(|| {
+            Error::UnknownServiceProvider {
+                entity_id: sp_entity_id.clone(),
+            }
+        })?;
+
+        let query_signed = redirect_query.is_some_and(|query| query.signature.is_some());
+        let signed = query_signed || request.signature.is_some();
+        if !signed && (self.want_authn_requests_signed || sp.authn_requests_signed()) {
+            return Err(Error::AuthnRequestNotSigned);
+        }
+        match redirect_query {
+            Some(query) if query_signed => verify_redirect_signature(query, sp)?,
+            _ if signed => verify_signature(xml, sp)?,
+            _ => {}
+        }
+
+        self.check_issue_instant(&request)?;
+        self.check_destination(&request, signed)?;
+        let acs = sp.acs_for_request(&request)?;
+
+        Ok(ValidatedAuthnRequest {
+            sp_entity_id,
+            acs,
+            request,
+            signed,
+            relay_state: redirect_query
+                .and_then(|query| query.relay_state.as_ref())
+                .map(|relay_state| relay_state.value.clone()),
+        })
+    }
+
+    fn check_issue_instant(&self, request: &AuthnRequest) -> Result<(), Error> {
+        let now = self.clock.now();
+        if &request.issue_instant + self.max_issue_delay < now {
+            return Err(Error::AuthnRequestExpired {
+                time: (request.issue_instant.0 + self.max_issue_delay)
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+            });
+        }
+        if &now + self.max_clock_skew < request.issue_instant {
+            return Err(Error::AuthnRequestNotYetValid {
+                time: (request.issue_instant.0 - self.max_clock_skew)
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+            });
+        }
+        Ok(())
+    }
+
+    /// A signed request must name its Destination, so that it cannot be replayed to another IdP.
+    fn check_destination(&self, request: &AuthnRequest, signed: bool) -> Result<(), Error> {
+        let matches = match &request.destination {
+            Some(destination) => self.sso_urls.iter().any(|url| url == destination),
+            None => !signed,
+        };
+        if !matches {
+            return Err(Error::DestinationMismatch {
+                destination: request.destination.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The signature of the query string must verify with a signing key of the SP.
+fn verify_redirect_signature(query: &RedirectQuery, sp: &SPMetadataExtractor) -> Result<(), Error> {
+    let certs = sp.signing_certificates()?;
+    if certs.is_empty() {
+        return Err(Error::NoCertificate);
+    }
+    query
+        .verify(&certs)
+        .map_err(|error| Error::InvalidRedirectSignature { error })?;
+    Ok(())
+}
+
+/// The enveloped signature must verify with a signing key of the SP and cover the whole request.
+#[cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
+fn verify_signature(xml: &str, sp: &SPMetadataExtractor) -> Result<(), Error> {
+    let certs = sp.signing_certificates()?;
+    if certs.is_empty() {
+        return Err(Error::NoCertificate);
+    }
+    let report = crypto::verify_signatures(xml, &certs)?;
+    if report.signatures.is_empty() {
+        return Err(Error::AuthnRequestNotSigned);
+    }
+    if !report.removed_elements.is_empty() {
+        return Err(Error::AuthnRequestPartiallySigned);
+    }
+    Ok(())
+}
+
+#[cfg(not(any(feature = "xmlsec", feature = "native-xmldsig")))]
+fn verify_signature(_xml: &str, _sp: &SPMetadataExtractor) -> Result<(), Error> {
+    Err(Error::SignatureVerificationUnavailable)
+}
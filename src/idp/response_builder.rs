@@ -1,148 +1,352 @@
+use super::error::Error;
 use super::sp_extractor::RequiredAttribute;
-use crate::attribute::{Attribute, AttributeValue};
+use crate::attribute::{Attribute, AttributeValue, TypedValue};
+use crate::clock::{Clock, SystemClock};
+use crate::crypto::XMLNS_SAML_ASSERTION;
+use crate::dom::Document;
 use crate::schema::{
     Assertion, AttributeStatement, AudienceRestriction, AuthnContext, AuthnContextClassRef,
-    AuthnStatement, Conditions, Issuer, NameId, Response, Status, StatusCode, Subject,
-    SubjectConfirmation, SubjectConfirmationData,
+    AuthnRequest, AuthnStatement, Conditions, Issuer, NameId, Response, Status, StatusCode,
+    StatusMessage, Subject, SubjectConfirmation, SubjectConfirmationData, SubjectLocality,
+    STATUS_RESPONDER, STATUS_SUCCESS,
 };
 use crate::signature::Signature;
+use crate::signer::{self, Signer};
 use crate::utils::{self, UtcDateTime};
 
-fn build_conditions(audience: &str) -> Conditions {
-    Conditions {
-        not_before: None,
-        not_on_or_after: None,
-        audience_restrictions: vec![AudienceRestriction {
-            audience: vec![audience.to_string()],
-        }],
-        one_time_use: None,
-        proxy_restriction: None,
+pub const AUTHN_CONTEXT_UNSPECIFIED: &str = "urn:oasis:names:tc:SAML:2.0:ac:classes:unspecified";
+pub const AUTHN_CONTEXT_PASSWORD_PROTECTED_TRANSPORT: &str =
+    "urn:oasis:names:tc:SAML:2.0:ac:classes:PasswordProtectedTransport";
+const NAME_ID_FORMAT_UNSPECIFIED: &str = "urn:oasis:names:tc:SAML:2.0:nameid-format:unspecified";
+
+/// Which elements of a response the IdP signs.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SignedParts {
+    #[default]
+    Response,
+    Assertion,
+    Both,
+}
+
+/// A successful response to an authentication request, with a single assertion.
+///
+/// ```ignore
+/// let response = AuthnResponseBuilder::default()
+///     .issuer("https://idp.example.com")
+///     .destination("https://sp.example.com/acs")
+///     .in_response_to(request.id.clone())
+///     .name_id("jdoe")
+///     .audiences(vec!["https://sp.example.com".to_string()])
+///     .session_index("session-1")
+///     .signed_parts(SignedParts::Both)
+///     .build()?;
+/// let xml = idp.signed_response(&response)?;
+/// ```
+#[derive(Builder, Clone, Default)]
+#[builder(default, setter(into))]
+pub struct AuthnResponse {
+    pub issuer: String,
+    /// The assertion consumer service URL, also the `Recipient` of the subject confirmation.
+    pub destination: String,
+    /// The ID of the authentication request, none for IdP-initiated SSO.
+    pub in_response_to: Option<String>,
+    pub name_id: String,
+    /// Defaults to `urn:oasis:names:tc:SAML:2.0:nameid-format:unspecified`.
+    pub name_id_format: Option<String>,
+    pub name_qualifier: Option<String>,
+    pub sp_name_qualifier: Option<String>,
+    /// The SPs the assertion is meant for, in a single `AudienceRestriction`.
+    pub audiences: Vec<String>,
+    pub not_before: Option<UtcDateTime>,
+    /// Also the `NotOnOrAfter` of the bearer subject confirmation.
+    pub not_on_or_after: Option<UtcDateTime>,
+    /// Defaults to the time the response is built.
+    pub authn_instant: Option<UtcDateTime>,
+    /// Defaults to [`AUTHN_CONTEXT_UNSPECIFIED`].
+    pub authn_context_class: Option<String>,
+    pub session_index: Option<String>,
+    pub session_not_on_or_after: Option<UtcDateTime>,
+    pub subject_locality: Option<SubjectLocality>,
+    pub attributes: Vec<Attribute>,
+    pub signed_parts: SignedParts,
+}
+
+impl AuthnResponseBuilder {
+    /// Sets the NameID of the subject along with its format and qualifiers, such as one made by
+    /// [`super::name_id::NameIdGenerator`].
+    pub fn subject_name_id(&mut self, name_id: &NameId) -> &mut Self {
+        self.name_id(name_id.value.clone())
+            .name_id_format(name_id.format.clone())
+            .name_qualifier(name_id.name_qualifier.clone())
+            .sp_name_qualifier(name_id.sp_name_qualifier.clone())
     }
 }
 
-fn build_authn_statement(class: &str) -> AuthnStatement {
-    AuthnStatement {
-        authn_instant: Some(UtcDateTime::now()),
-        session_index: None,
-        session_not_on_or_after: None,
-        subject_locality: None,
-        authn_context: Some(AuthnContext {
-            value: Some(AuthnContextClassRef {
-                value: Some(class.to_string()),
+impl AuthnResponse {
+    /// Builds the unsigned response, with new response and assertion IDs, issued at the time of
+    /// the clock.
+    pub fn to_response(&self, clock: &dyn Clock) -> Response {
+        let issuer = Issuer {
+            value: Some(self.issuer.clone()),
+            ..Default::default()
+        };
+        let now = clock.now();
+        let assertion = Assertion {
+            id: utils::gen_saml_assertion_id(),
+            issue_instant: now.clone(),
+            version: "2.0".to_string(),
+            issuer: issuer.clone(),
+            signature: None,
+            subject: Some(Subject {
+                name_id: Some(NameId {
+                    format: Some(
+                        self.name_id_format
+                            .clone()
+                            .unwrap_or_else(|| NAME_ID_FORMAT_UNSPECIFIED.to_string()),
+                    ),
+                    name_qualifier: self.name_qualifier.clone(),
+                    sp_name_qualifier: self.sp_name_qualifier.clone(),
+                    value: self.name_id.clone(),
+                    ..Default::default()
+                }),
+                subject_confirmations: vec![SubjectConfirmation {
+                    method: Some("urn:oasis:names:tc:SAML:2.0:cm:bearer".to_string()),
+                    name_id: None,
+                    subject_confirmation_data: Some(SubjectConfirmationData {
+                        not_before: None,
+                        not_on_or_after: self.not_on_or_after.clone(),
+                        recipient: Some(self.destination.clone()),
+                        in_response_to: self.in_response_to.clone(),
+                        address: None,
+                        content: None,
+                    }),
+                }],
             }),
-        }),
+            conditions: Some(Conditions {
+                not_before: self.not_before.clone(),
+                not_on_or_after: self.not_on_or_after.clone(),
+                audience_restrictions: vec![AudienceRestriction {
+                    audience: self.audiences.clone(),
+                }],
+                one_time_use: None,
+                proxy_restriction: None,
+            }),
+            authn_statements: vec![AuthnStatement {
+                authn_instant: Some(self.authn_instant.clone().unwrap_or_else(|| now.clone())),
+                session_index: self.session_index.clone(),
+                session_not_on_or_after: self.session_not_on_or_after.clone(),
+                subject_locality: self.subject_locality.clone(),
+                authn_context: Some(AuthnContext {
+                    value: Some(AuthnContextClassRef {
+                        value: Some(
+                            self.authn_context_class
+                                .clone()
+                                .unwrap_or_else(|| AUTHN_CONTEXT_UNSPECIFIED.to_string()),
+                        ),
+                    }),
+                }),
+            }],
+            attribute_statements: vec![AttributeStatement {
+                attributes: self.attributes.clone(),
+            }],
+        };
+
+        Response {
+            assertions: vec![assertion],
+            ..new_response(
+                issuer,
+                &self.destination,
+                &self.in_response_to,
+                now,
+                StatusCode::new(STATUS_SUCCESS),
+                None,
+            )
+        }
     }
-}
 
-pub struct ResponseAttribute<'a> {
-    pub required_attribute: RequiredAttribute,
-    pub value: &'a str,
+    /// Builds the response and signs the parts [`AuthnResponse::signed_parts`] asks for. The
+    /// assertion is signed first, so that the signature of the response covers it.
+    pub fn to_signed_xml(
+        &self,
+        signer: &dyn Signer,
+        clock: &dyn Clock,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let xml = yaserde::ser::to_string(&self.to_response(clock))?;
+        let mut document = Document::parse(&xml)?;
+        let root = document
+            .root_element()
+            .ok_or(crate::dom::Error::XmlMissingRootElement)?;
+        if self.signed_parts != SignedParts::Response {
+            let assertion = document
+                .first_child(root, "Assertion", XMLNS_SAML_ASSERTION)
+                .ok_or(Error::MissingAssertion)?;
+            signer::sign_element(&mut document, assertion, signer)?;
+        }
+        if self.signed_parts != SignedParts::Assertion {
+            signer::sign_element(&mut document, root, signer)?;
+        }
+        Ok(document.to_xml())
+    }
 }
 
-fn build_attributes(formats_names_values: &[ResponseAttribute]) -> Vec<Attribute> {
-    formats_names_values
-        .iter()
-        .map(|attr| Attribute {
-            friendly_name: None,
-            name: Some(attr.required_attribute.name.clone()),
-            name_format: attr.required_attribute.format.clone(),
-            values: vec![AttributeValue {
-                attribute_type: Some("xs:string".to_string()),
-                value: Some(attr.value.to_string()),
-            }],
-        })
-        .collect()
+/// A response telling the SP that the IdP could not authenticate the user or serve the request,
+/// without any assertion.
+///
+/// ```ignore
+/// let response = ErrorResponseBuilder::default()
+///     .issuer("https://idp.example.com")
+///     .destination("https://sp.example.com/acs")
+///     .in_response_to(request.id.clone())
+///     .status_code(StatusCode::with_sub_code(STATUS_REQUESTER, STATUS_NO_PASSIVE))
+///     .status_message("The user is not logged in".to_string())
+///     .build()?;
+/// let xml = idp.signed_error_response(&response)?;
+/// ```
+#[derive(Builder, Clone, Default)]
+#[builder(default, setter(into))]
+pub struct ErrorResponse {
+    pub issuer: String,
+    /// The assertion consumer service URL.
+    pub destination: String,
+    /// The ID of the authentication request, none for IdP-initiated SSO.
+    pub in_response_to: Option<String>,
+    /// Requester, Responder or VersionMismatch, refined by a second-level code such as
+    /// `AuthnFailed`. Defaults to Responder.
+    #[builder(default = "StatusCode::new(STATUS_RESPONDER)")]
+    pub status_code: StatusCode,
+    pub status_message: Option<String>,
 }
 
-fn build_assertion(
-    name_id: &str,
-    request_id: &str,
-    issuer: Issuer,
-    recipient: &str,
-    audience: &str,
-    attributes: &[ResponseAttribute],
-) -> Assertion {
-    let assertion_id = utils::gen_saml_assertion_id();
+impl ErrorResponse {
+    /// An error response to an authentication request, sent to the assertion consumer service
+    /// URL the request asked for.
+    pub fn for_request(request: &AuthnRequest, issuer: &str, status_code: StatusCode) -> Self {
+        ErrorResponse {
+            issuer: issuer.to_string(),
+            destination: request
+                .assertion_consumer_service_url
+                .clone()
+                .unwrap_or_default(),
+            in_response_to: Some(request.id.clone()),
+            status_code,
+            status_message: None,
+        }
+    }
 
-    Assertion {
-        id: assertion_id,
-        issue_instant: UtcDateTime::now(),
-        version: "2.0".to_string(),
-        issuer,
-        signature: None,
-        subject: Some(Subject {
-            name_id: Some(NameId {
-                format: Some("urn:oasis:names:tc:SAML:2.0:nameid-format:unspecified".to_string()),
-                value: name_id.to_owned(),
-                ..Default::default()
-            }),
-            subject_confirmations: vec![SubjectConfirmation {
-                method: Some("urn:oasis:names:tc:SAML:2.0:cm:bearer".to_string()),
-                name_id: None,
-                subject_confirmation_data: Some(SubjectConfirmationData {
-                    not_before: None,
-                    not_on_or_after: None,
-                    recipient: Some(recipient.to_owned()),
-                    in_response_to: Some(request_id.to_owned()),
-                    address: None,
-                    content: None,
-                }),
-            }],
-        }),
-        conditions: Some(build_conditions(audience)),
-        authn_statements: vec![build_authn_statement(
-            "urn:oasis:names:tc:SAML:2.0:ac:classes:unspecified",
-        )],
-        attribute_statements: vec![AttributeStatement {
-            attributes: build_attributes(attributes),
-        }],
+    /// Builds the unsigned response, with a new ID, issued at the time of the clock.
+    pub fn to_response(&self, clock: &dyn Clock) -> Response {
+        let issuer = Issuer {
+            value: Some(self.issuer.clone()),
+            ..Default::default()
+        };
+        new_response(
+            issuer,
+            &self.destination,
+            &self.in_response_to,
+            clock.now(),
+            self.status_code.clone(),
+            self.status_message.clone(),
+        )
+    }
+
+    /// Builds the response and signs it as a whole.
+    pub fn to_signed_xml(
+        &self,
+        signer: &dyn Signer,
+        clock: &dyn Clock,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let xml = yaserde::ser::to_string(&self.to_response(clock))?;
+        Ok(signer::sign_xml(&xml, signer)?)
     }
 }
 
-fn build_response(
-    name_id: &str,
-    issuer: &str,
-    request_id: &str,
-    attributes: &[ResponseAttribute],
+fn new_response(
+    issuer: Issuer,
     destination: &str,
-    audience: &str,
-    x509_cert: &[u8],
+    in_response_to: &Option<String>,
+    issue_instant: UtcDateTime,
+    status_code: StatusCode,
+    status_message: Option<String>,
 ) -> Response {
-    let issuer = Issuer {
-        value: Some(issuer.to_string()),
-        ..Default::default()
-    };
-
-    let response_id = utils::gen_saml_response_id();
-
     Response {
-        id: response_id.clone(),
-        in_response_to: Some(request_id.to_owned()),
+        id: utils::gen_saml_response_id(),
+        in_response_to: in_response_to.clone(),
         version: "2.0".to_string(),
-        issue_instant: UtcDateTime::now(),
+        issue_instant,
         destination: Some(destination.to_string()),
         consent: None,
-        issuer: Some(issuer.clone()),
-        signature: Some(Signature::template(&response_id, x509_cert)),
+        issuer: Some(issuer),
+        signature: None,
         status: Status {
-            status_code: StatusCode {
-                value: "urn:oasis:names:tc:SAML:2.0:status:Success".to_string(),
-            },
-            status_message: None,
+            status_code,
+            status_message: status_message.map(|message| StatusMessage {
+                value: Some(message),
+            }),
             status_detail: None,
         },
-        encrypted_assertion: None,
-        assertion: Some(build_assertion(
-            name_id,
-            request_id,
-            issuer,
-            destination,
-            audience,
-            attributes,
-        )),
+        encrypted_assertions: vec![],
+        assertions: vec![],
+    }
+}
+
+/// An attribute released to the SP, with one or more typed values.
+#[derive(Clone, Debug)]
+pub struct ResponseAttribute {
+    pub required_attribute: RequiredAttribute,
+    pub friendly_name: Option<String>,
+    pub values: Vec<TypedValue>,
+}
+
+impl ResponseAttribute {
+    pub fn new(required_attribute: RequiredAttribute, value: impl Into<TypedValue>) -> Self {
+        ResponseAttribute {
+            required_attribute,
+            friendly_name: None,
+            values: vec![value.into()],
+        }
+    }
+}
+
+impl From<&ResponseAttribute> for Attribute {
+    fn from(attribute: &ResponseAttribute) -> Self {
+        Attribute {
+            friendly_name: attribute.friendly_name.clone(),
+            name: Some(attribute.required_attribute.name.clone()),
+            name_format: attribute.required_attribute.format.clone(),
+            values: attribute.values.iter().map(AttributeValue::from).collect(),
+        }
+    }
+}
+
+/// The response of [`build_response_template`], for a request from the SP.
+pub(crate) fn response_for_request(
+    name_id: &str,
+    audience: &str,
+    issuer: &str,
+    acs_url: &str,
+    request_id: &str,
+    attributes: &[ResponseAttribute],
+) -> AuthnResponse {
+    AuthnResponse {
+        issuer: issuer.to_string(),
+        destination: acs_url.to_string(),
+        in_response_to: Some(request_id.to_string()),
+        name_id: name_id.to_string(),
+        audiences: vec![audience.to_string()],
+        attributes: attributes.iter().map(Attribute::from).collect(),
+        ..AuthnResponse::default()
     }
 }
 
+/// Adds a signature template for the whole response, with the given certificate.
+pub(crate) fn with_signature_template(mut response: Response, cert_der: &[u8]) -> Response {
+    response.signature = Some(Signature::template(&response.id, cert_der));
+    response
+}
+
+/// Builds a response issued now, to be signed as a whole with the given certificate in the
+/// signature template. See [`AuthnResponse`] to set the conditions, session and authentication
+/// context too.
 pub fn build_response_template(
     cert_der: &[u8],
     name_id: &str,
@@ -152,7 +356,7 @@ pub fn build_response_template(
     request_id: &str,
     attributes: &[ResponseAttribute],
 ) -> Response {
-    build_response(
-        name_id, issuer, request_id, attributes, acs_url, audience, cert_der,
-    )
+    let response = response_for_request(name_id, audience, issuer, acs_url, request_id, attributes)
+        .to_response(&SystemClock);
+    with_signature_template(response, cert_der)
 }
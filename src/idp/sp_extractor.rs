@@ -1,21 +1,78 @@
 use super::error::Error;
 use crate::crypto;
-use crate::metadata::EntityDescriptor;
+use crate::metadata::{
+    AttributeConsumingService, Endpoint, EntityDescriptor, IndexedEndpoint, KeyDescriptor,
+    RequestedAttribute, HTTP_ARTIFACT_BINDING, HTTP_POST_BINDING, HTTP_REDIRECT_BINDING,
+    PAOS_BINDING,
+};
+use crate::schema::AuthnRequest;
+use openssl::x509::X509;
 
 pub struct SPMetadataExtractor(EntityDescriptor);
 
+#[derive(Clone, Debug)]
 pub struct RequiredAttribute {
     pub name: String,
     pub format: Option<String>,
 }
 
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Acs {
     pub bind_type: BindType,
     pub url: String,
+    pub index: u16,
+    pub is_default: bool,
 }
 
+impl Acs {
+    fn from_endpoint(endpoint: &IndexedEndpoint) -> Result<Self, Error> {
+        Ok(Acs {
+            bind_type: BindType::from_binding(&endpoint.binding).ok_or_else(|| {
+                Error::UnsupportedBinding {
+                    binding: endpoint.binding.clone(),
+                }
+            })?,
+            url: endpoint.location.clone(),
+            index: endpoint.index,
+            is_default: endpoint.is_default == Some(true),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum BindType {
     Post,
+    Redirect,
+    Artifact,
+    /// The reverse SOAP binding of the Enhanced Client or Proxy profile.
+    Paos,
+}
+
+impl BindType {
+    pub fn from_binding(binding: &str) -> Option<Self> {
+        match binding {
+            HTTP_POST_BINDING => Some(BindType::Post),
+            HTTP_REDIRECT_BINDING => Some(BindType::Redirect),
+            HTTP_ARTIFACT_BINDING => Some(BindType::Artifact),
+            PAOS_BINDING => Some(BindType::Paos),
+            _ => None,
+        }
+    }
+
+    pub fn binding(&self) -> &'static str {
+        match self {
+            BindType::Post => HTTP_POST_BINDING,
+            BindType::Redirect => HTTP_REDIRECT_BINDING,
+            BindType::Artifact => HTTP_ARTIFACT_BINDING,
+            BindType::Paos => PAOS_BINDING,
+        }
+    }
+}
+
+impl From<EntityDescriptor> for SPMetadataExtractor {
+    fn from(entity_descriptor: EntityDescriptor) -> Self {
+        Self(entity_descriptor)
+    }
 }
 
 impl SPMetadataExtractor {
@@ -27,34 +84,178 @@ impl SPMetadataExtractor {
         &self.0.entity_id
     }
 
+    pub fn entity_descriptor(&self) -> &EntityDescriptor {
+        &self.0
+    }
+
+    /// Whether the SP declares in its metadata that it signs its AuthnRequests.
+    pub fn authn_requests_signed(&self) -> bool {
+        self.0
+            .sp_sso_descriptors
+            .iter()
+            .any(|sd| sd.authn_requests_signed == Some(true))
+    }
+
+    /// Whether the SP declares in its metadata that it wants the assertions signed.
+    pub fn want_assertions_signed(&self) -> bool {
+        self.0
+            .sp_sso_descriptors
+            .iter()
+            .any(|sd| sd.want_assertions_signed == Some(true))
+    }
+
+    /// The NameID formats the SP supports, in order of preference.
+    pub fn name_id_formats(&self) -> Vec<&str> {
+        self.0
+            .sp_sso_descriptors
+            .iter()
+            .flat_map(|sd| sd.name_id_formats.iter())
+            .map(|format| format.trim())
+            .collect()
+    }
+
+    pub fn single_logout_services(&self) -> Vec<&Endpoint> {
+        self.0
+            .sp_sso_descriptors
+            .iter()
+            .flat_map(|sd| sd.single_logout_services.iter())
+            .collect()
+    }
+
+    /// The first SingleLogoutService with the given binding.
+    pub fn single_logout_service(&self, binding: &str) -> Option<&Endpoint> {
+        self.single_logout_services()
+            .into_iter()
+            .find(|slo| slo.binding == binding)
+    }
+
+    /// The certificates of every key the SP may sign with: those marked for signing, and those
+    /// without a `use`, which serve both purposes.
+    pub fn signing_certificates(&self) -> Result<Vec<X509>, Error> {
+        self.0
+            .sp_sso_descriptors
+            .iter()
+            .flat_map(|sd| sd.key_descriptors.iter())
+            .filter(|kd| kd.is_signing() || kd.key_use.is_none())
+            .flat_map(certificates)
+            .map(|cert| {
+                crypto::decode_x509_cert(cert)
+                    .ok()
+                    .and_then(|der| X509::from_der(&der).ok())
+                    .ok_or(Error::InvalidCertificateEncoding)
+            })
+            .collect()
+    }
+
+    /// The certificate to encrypt assertions for: that of the first key marked for encryption, or
+    /// else of the first key without a `use`.
+    pub fn encryption_certificate(&self) -> Result<X509, Error> {
+        let cert = self
+            .encryption_key_descriptor()
+            .and_then(|kd| certificates(kd).next())
+            .ok_or(Error::NoCertificate)?;
+        crypto::decode_x509_cert(cert)
+            .ok()
+            .and_then(|der| X509::from_der(&der).ok())
+            .ok_or(Error::InvalidCertificateEncoding)
+    }
+
+    /// The algorithms the SP accepts for the key of [`Self::encryption_certificate`], in order
+    /// of preference. Empty when the SP does not say.
+    pub fn encryption_methods(&self) -> Vec<&str> {
+        self.encryption_key_descriptor()
+            .map(|kd| {
+                kd.encryption_methods
+                    .iter()
+                    .map(|method| method.algorithm.as_str())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The default AssertionConsumerService of the SP.
     pub fn acs(&self) -> Result<Acs, Error> {
-        let (binding, location) = self
+        let endpoints = self.acs_endpoints().collect::<Vec<_>>();
+        Acs::from_endpoint(
+            default_indexed(&endpoints, |acs| acs.is_default).ok_or(Error::MissingAcsUrl)?,
+        )
+    }
+
+    pub fn acs_by_index(&self, index: u16) -> Result<Acs, Error> {
+        self.acs_endpoints()
+            .find(|acs| acs.index == index)
+            .ok_or(Error::UnregisteredAcs {
+                url: None,
+                index: Some(index),
+            })
+            .and_then(Acs::from_endpoint)
+    }
+
+    /// The AssertionConsumerService at `url`, with the given binding if any.
+    pub fn acs_by_url(&self, url: &str, binding: Option<&str>) -> Result<Acs, Error> {
+        self.acs_endpoints()
+            .find(|acs| {
+                acs.location == url && binding.map_or(true, |binding| acs.binding == binding)
+            })
+            .ok_or_else(|| Error::UnregisteredAcs {
+                url: Some(url.to_string()),
+                index: None,
+            })
+            .and_then(Acs::from_endpoint)
+    }
+
+    /// The AssertionConsumerService an AuthnRequest asks for, by URL and ProtocolBinding or by
+    /// index, or the default one when it names none.
+    pub fn acs_for_request(&self, request: &AuthnRequest) -> Result<Acs, Error> {
+        match (
+            &request.assertion_consumer_service_url,
+            request.assertion_consumer_service_index,
+        ) {
+            (Some(url), _) => self.acs_by_url(url, request.protocol_binding.as_deref()),
+            (None, Some(index)) => self.acs_by_index(index),
+            (None, None) => self.acs(),
+        }
+    }
+
+    /// The AttributeConsumingService with the given index, or the default one.
+    pub fn attribute_consuming_service(
+        &self,
+        index: Option<u16>,
+    ) -> Option<&AttributeConsumingService> {
+        let services = self
             .0
             .sp_sso_descriptors
-            .first()
-            .and_then(|sd| sd.assertion_consumer_services.first())
-            .map(|acs| (acs.binding.as_str(), acs.location.as_str()))
-            .ok_or(Error::MissingAcsUrl)?;
-
-        if binding != "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST" {
-            return Err(Error::NonHttpPostBindingUnsupported);
+            .iter()
+            .flat_map(|sd| sd.attribute_consuming_services.iter());
+        match index {
+            Some(index) => services.clone().find(|acs| acs.index == index),
+            None => default_indexed(&services.collect::<Vec<_>>(), |acs| acs.is_default),
         }
+    }
 
-        Ok(Acs {
-            bind_type: BindType::Post,
-            url: location.to_string(),
-        })
+    /// The attributes requested by the AttributeConsumingService with the given index, or by the
+    /// default one, with their friendly names and the values the SP accepts.
+    pub fn requested_attributes(&self, index: Option<u16>) -> &[RequestedAttribute] {
+        self.attribute_consuming_service(index)
+            .map(|acs| acs.request_attributes.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The attributes the default AttributeConsumingService requests without requiring them.
+    pub fn optional_attributes(&self) -> Vec<&RequestedAttribute> {
+        self.requested_attributes(None)
+            .iter()
+            .filter(|ra| !ra.is_required())
+            .collect()
     }
 
+    /// The required attributes of the default AttributeConsumingService.
     pub fn required_attributes(&self) -> Vec<RequiredAttribute> {
-        self.0
-            .sp_sso_descriptors
-            .first()
-            .and_then(|sd| sd.attribute_consuming_services.first())
+        self.attribute_consuming_service(None)
             .map(|acs| {
                 acs.request_attributes
                     .iter()
-                    .filter(|ra| ra.is_required == Some(true))
+                    .filter(|ra| ra.is_required())
                     .map(|ra| RequiredAttribute {
                         name: ra.name.clone(),
                         format: ra.name_format.clone(),
@@ -97,4 +298,46 @@ impl SPMetadataExtractor {
 
         Err(Error::NoCertificate.into())
     }
+
+    fn encryption_key_descriptor(&self) -> Option<&KeyDescriptor> {
+        let key_descriptors = self
+            .0
+            .sp_sso_descriptors
+            .iter()
+            .flat_map(|sd| sd.key_descriptors.iter())
+            .filter(|kd| certificates(kd).next().is_some());
+        key_descriptors
+            .clone()
+            .find(|kd| kd.key_use.as_deref() == Some("encryption"))
+            .or_else(|| key_descriptors.clone().find(|kd| kd.key_use.is_none()))
+    }
+
+    fn acs_endpoints(&self) -> impl Iterator<Item = &IndexedEndpoint> {
+        self.0
+            .sp_sso_descriptors
+            .iter()
+            .flat_map(|sd| sd.assertion_consumer_services.iter())
+    }
+}
+
+/// The element marked `isDefault="true"`, or else the first one not marked `isDefault="false"`, or
+/// else the first one, as the metadata specification has it.
+fn default_indexed<'a, T>(
+    items: &[&'a T],
+    is_default: impl Fn(&T) -> Option<bool>,
+) -> Option<&'a T> {
+    items
+        .iter()
+        .find(|item| is_default(item) == Some(true))
+        .or_else(|| items.iter().find(|item| is_default(item).is_none()))
+        .or_else(|| items.first())
+        .copied()
+}
+
+fn certificates(key_descriptor: &KeyDescriptor) -> impl Iterator<Item = &String> {
+    key_descriptor
+        .key_info
+        .x509_data
+        .iter()
+        .flat_map(|data| data.certificates.iter())
 }
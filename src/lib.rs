@@ -1,14 +1,26 @@
 pub mod attribute;
+pub mod attribute_mapping;
 #[cfg(feature = "xmlsec")]
 mod bindings;
+pub mod c14n;
+pub mod clock;
 pub mod crypto;
+pub mod dom;
+// the xmlsec backend takes precedence when both signature backends are enabled
+#[cfg(feature = "native-xmldsig")]
+#[cfg_attr(feature = "xmlsec", allow(dead_code))]
+mod dsig;
 pub mod idp;
 pub mod key_info;
+pub mod login;
 pub mod metadata;
 pub mod schema;
 pub mod service_provider;
 pub mod signature;
+pub mod signer;
+pub mod trust;
 pub mod utils;
+pub mod xmlenc;
 #[cfg(feature = "xmlsec")]
 mod xmlsec;
 
@@ -0,0 +1,93 @@
+//!
+//! The outcome of a validated SSO response, to keep in the session of the application
+//!
+use crate::clock::Clock;
+use crate::schema::{Assertion, NameId, Response};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+
+/// A successful login, as asserted by the IdP. Everything is owned, so that the login can be kept
+/// in the session of the user, and its NameID and SessionIndex sent back in a LogoutRequest.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Login {
+    /// The entity ID of the IdP which issued the assertion.
+    pub idp_entity_id: String,
+    /// The NameID of the subject, along with its format and qualifiers.
+    pub name_id: Option<NameId>,
+    pub session_index: Option<String>,
+    /// When the IdP wants the session to end.
+    pub session_not_on_or_after: Option<DateTime<Utc>>,
+    pub authn_instant: Option<DateTime<Utc>>,
+    /// The AuthnContextClassRef of the authentication, such as PasswordProtectedTransport.
+    pub authn_context_class: Option<String>,
+    /// The ID of the AuthnRequest the response answers, if it was not IdP-initiated.
+    pub in_response_to: Option<String>,
+    pub relay_state: Option<String>,
+    /// The string values of the attributes, by Name.
+    pub attributes: BTreeMap<String, Vec<String>>,
+    /// The validated assertion, with the attribute statements of every assertion of the
+    /// response, for typed lookups with [`Assertion::attribute`].
+    pub assertion: Assertion,
+    /// The response as received.
+    pub response_xml: String,
+}
+
+impl Login {
+    pub(crate) fn new(
+        response: &Response,
+        assertion: Assertion,
+        response_xml: &str,
+        relay_state: Option<&str>,
+    ) -> Self {
+        let authn_statement = assertion.authn_statements.first();
+        let mut attributes = BTreeMap::<String, Vec<String>>::new();
+        for attribute in assertion.attributes() {
+            if let Some(name) = &attribute.name {
+                attributes.entry(name.clone()).or_default().extend(
+                    attribute
+                        .values
+                        .iter()
+                        .filter(|value| value.is_string())
+                        .filter_map(|value| value.value.clone()),
+                );
+            }
+        }
+        Login {
+            idp_entity_id: assertion.issuer.value.clone().unwrap_or_default(),
+            name_id: assertion
+                .subject
+                .as_ref()
+                .and_then(|subject| subject.name_id.clone()),
+            session_index: authn_statement.and_then(|statement| statement.session_index.clone()),
+            session_not_on_or_after: authn_statement
+                .and_then(|statement| statement.session_not_on_or_after.as_ref())
+                .map(|time| time.0),
+            authn_instant: authn_statement
+                .and_then(|statement| statement.authn_instant.as_ref())
+                .map(|time| time.0),
+            authn_context_class: authn_statement
+                .and_then(|statement| statement.authn_context.as_ref())
+                .and_then(|context| context.value.as_ref())
+                .and_then(|class_ref| class_ref.value.clone()),
+            in_response_to: response.in_response_to.clone(),
+            relay_state: relay_state.map(str::to_string),
+            attributes,
+            assertion,
+            response_xml: response_xml.to_string(),
+        }
+    }
+
+    /// The values of an attribute by Name, empty when the IdP did not send it.
+    pub fn attribute_values(&self, name: &str) -> &[String] {
+        self.attributes
+            .get(name)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Whether the session the IdP granted is over.
+    pub fn is_session_expired(&self, clock: &dyn Clock) -> bool {
+        self.session_not_on_or_after
+            .is_some_and(|not_on_or_after| not_on_or_after <= clock.now().0)
+    }
+}
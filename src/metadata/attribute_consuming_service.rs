@@ -31,3 +31,19 @@ pub struct RequestedAttribute {
     #[yaserde(rename = "AttributeValue", default)]
     pub values: Vec<AttributeValue>,
 }
+
+impl RequestedAttribute {
+    pub fn is_required(&self) -> bool {
+        self.is_required == Some(true)
+    }
+
+    /// Whether the SP accepts the value: any value when it lists none, otherwise one of those it
+    /// lists.
+    pub fn accepts(&self, value: &str) -> bool {
+        self.values.is_empty()
+            || self
+                .values
+                .iter()
+                .any(|accepted| accepted.value.as_deref() == Some(value))
+    }
+}
@@ -1,6 +1,7 @@
 use crate::metadata::{
     AffiliationDescriptor, AttributeAuthorityDescriptors, AuthnAuthorityDescriptors, ContactPerson,
-    IdpSsoDescriptor, Organization, PdpDescriptor, RoleDescriptor, SpSsoDescriptor,
+    Extensions, IdpSsoDescriptor, Organization, PdpDescriptor, RoleDescriptor, Scope,
+    SpSsoDescriptor, ENTITY_CATEGORY,
 };
 use crate::signature::Signature;
 use crate::utils::UtcDateTime;
@@ -26,6 +27,8 @@ pub struct EntityDescriptor {
     pub id: Option<String>,
     #[yaserde(rename = "Signature", prefix = "ds")]
     pub signature: Option<Signature>,
+    #[yaserde(rename = "Extensions", prefix = "md")]
+    pub extensions: Option<Extensions>,
     #[yaserde(rename = "RoleDescriptor", prefix = "md", default)]
     pub role_descriptors: Vec<RoleDescriptor>,
     #[yaserde(rename = "IDPSSODescriptor", prefix = "md", default)]
@@ -62,6 +65,36 @@ impl FromStr for EntityDescriptor {
     }
 }
 
+impl EntityDescriptor {
+    /// The `shibmd:Scope`s of the IdP roles of the entity.
+    pub fn idp_scopes(&self) -> Vec<&Scope> {
+        self.idp_sso_descriptors
+            .iter()
+            .filter_map(|descriptor| descriptor.extensions.as_ref())
+            .flat_map(|extensions| extensions.scopes.iter())
+            .collect()
+    }
+
+    /// The string values of the entity attribute with the given Name, from the `Extensions` of
+    /// the entity.
+    pub fn entity_attribute_values(&self, name: &str) -> Vec<&str> {
+        self.extensions
+            .iter()
+            .filter_map(|extensions| extensions.entity_attributes.as_ref())
+            .flat_map(|entity_attributes| entity_attributes.attributes.iter())
+            .filter(|attribute| attribute.name.as_deref() == Some(name))
+            .flat_map(|attribute| attribute.values.iter())
+            .filter_map(|value| value.value.as_deref())
+            .map(str::trim)
+            .collect()
+    }
+
+    /// The entity categories the entity belongs to, such as [`crate::metadata::RESEARCH_AND_SCHOLARSHIP`].
+    pub fn entity_categories(&self) -> Vec<&str> {
+        self.entity_attribute_values(ENTITY_CATEGORY)
+    }
+}
+
 #[derive(Clone, Debug, Default, Eq, PartialEq, YaDeserialize, YaSerialize)]
 pub struct AdditionalMetadataLocation {
     #[yaserde(attribute)]
@@ -120,4 +153,27 @@ mod test {
 
         assert_eq!(reparsed_entity_descriptor, entity_descriptor);
     }
+
+    #[test]
+    fn test_idp_scopes() {
+        let input_xml = include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/test_vectors/samltest_id_metadata.xml"
+        ));
+        let entity_descriptor: EntityDescriptor = input_xml
+            .parse()
+            .expect("Failed to parse samltest_id_metadata.xml into an EntityDescriptor");
+        let scopes = entity_descriptor.idp_scopes();
+        assert_eq!(scopes.len(), 1);
+        assert_eq!(scopes[0].regexp, Some(false));
+        assert!(scopes[0].matches("samltest.id"));
+        assert!(!scopes[0].matches("evil.samltest.id"));
+
+        let output_xml = yaserde::ser::to_string(&entity_descriptor)
+            .expect("Failed to convert EntityDescriptor to xml");
+        let reparsed_entity_descriptor: EntityDescriptor = output_xml
+            .parse()
+            .expect("Failed to parse EntityDescriptor");
+        assert_eq!(reparsed_entity_descriptor.idp_scopes(), scopes);
+    }
 }
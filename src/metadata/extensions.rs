@@ -0,0 +1,73 @@
+use crate::attribute::Attribute;
+use regex::RegexBuilder;
+use yaserde_derive::{YaDeserialize, YaSerialize};
+
+pub const XMLNS_SHIBMD: &str = "urn:mace:shibboleth:metadata:1.0";
+pub const XMLNS_MDATTR: &str = "urn:oasis:names:tc:SAML:metadata:attribute";
+
+/// The entity attribute listing the categories an entity belongs to.
+pub const ENTITY_CATEGORY: &str = "http://macedir.org/entity-category";
+/// The entity attribute listing the categories an IdP supports.
+pub const ENTITY_CATEGORY_SUPPORT: &str = "http://macedir.org/entity-category-support";
+/// The REFEDS Research and Scholarship entity category.
+pub const RESEARCH_AND_SCHOLARSHIP: &str = "http://refeds.org/category/research-and-scholarship";
+/// The GÉANT Data Protection Code of Conduct entity category.
+pub const GEANT_CODE_OF_CONDUCT: &str =
+    "http://www.geant.net/uri/dataprotection-code-of-conduct/v1";
+/// The REFEDS Data Protection Code of Conduct entity category, version 2 of the GÉANT one.
+pub const REFEDS_CODE_OF_CONDUCT: &str = "https://refeds.org/category/code-of-conduct/v2";
+
+/// The `Extensions` of an entity or a role descriptor. Only the extensions samael makes use of
+/// are kept.
+#[derive(Clone, Debug, Default, Eq, PartialEq, YaDeserialize, YaSerialize)]
+#[yaserde(
+    namespace = "md: urn:oasis:names:tc:SAML:2.0:metadata",
+    namespace = "shibmd: urn:mace:shibboleth:metadata:1.0",
+    namespace = "mdattr: urn:oasis:names:tc:SAML:metadata:attribute"
+)]
+pub struct Extensions {
+    #[yaserde(rename = "Scope", prefix = "shibmd", default)]
+    pub scopes: Vec<Scope>,
+    #[yaserde(rename = "EntityAttributes", prefix = "mdattr")]
+    pub entity_attributes: Option<EntityAttributes>,
+}
+
+/// Attributes of the entity itself, such as its entity categories, as opposed to those of a user.
+#[derive(Clone, Debug, Default, Eq, PartialEq, YaDeserialize, YaSerialize)]
+#[yaserde(
+    namespace = "mdattr: urn:oasis:names:tc:SAML:metadata:attribute",
+    namespace = "saml: urn:oasis:names:tc:SAML:2.0:assertion"
+)]
+pub struct EntityAttributes {
+    #[yaserde(rename = "Attribute", prefix = "saml", default)]
+    pub attributes: Vec<Attribute>,
+}
+
+/// A security domain an IdP is authoritative for, the part after the `@` of scoped attribute
+/// values such as eduPersonPrincipalName.
+#[derive(Clone, Debug, Default, Eq, PartialEq, YaDeserialize, YaSerialize)]
+#[yaserde(namespace = "shibmd: urn:mace:shibboleth:metadata:1.0")]
+pub struct Scope {
+    #[yaserde(attribute, rename = "regexp")]
+    pub regexp: Option<bool>,
+    #[yaserde(text)]
+    pub value: String,
+}
+
+impl Scope {
+    /// Whether the scope of an attribute value is this scope, or matches it as a whole when it is
+    /// a regular expression. Invalid expressions match nothing. Scopes are DNS domains, so case
+    /// does not matter.
+    pub fn matches(&self, scope: &str) -> bool {
+        let value = self.value.trim();
+        if self.regexp.unwrap_or(false) {
+            RegexBuilder::new(&format!("^(?:{})$", value))
+                .case_insensitive(true)
+                .build()
+                .map(|regex| regex.is_match(scope))
+                .unwrap_or(false)
+        } else {
+            value.eq_ignore_ascii_case(scope)
+        }
+    }
+}
@@ -4,16 +4,18 @@ mod contact_person;
 mod encryption_method;
 mod endpoint;
 mod entity_descriptor;
+mod extensions;
 mod key_descriptor;
 mod organization;
 mod sp_sso_descriptor;
 
 pub use affiliation_descriptor::*;
-pub use attribute_consuming_service::AttributeConsumingService;
+pub use attribute_consuming_service::{AttributeConsumingService, RequestedAttribute};
 pub use contact_person::*;
 pub use encryption_method::EncryptionMethod;
 pub use endpoint::*;
 pub use entity_descriptor::{EntitiesDescriptor, EntityDescriptor};
+pub use extensions::*;
 pub use key_descriptor::KeyDescriptor;
 pub use organization::Organization;
 pub use sp_sso_descriptor::SpSsoDescriptor;
@@ -29,6 +31,12 @@ pub const HTTP_POST_BINDING: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-P
 // HTTP_REDIRECT_BINDING is the official URN for the HTTP-Redirect binding (transport)
 pub const HTTP_REDIRECT_BINDING: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-Redirect";
 
+// HTTP_ARTIFACT_BINDING is the official URN for the HTTP-Artifact binding (transport)
+pub const HTTP_ARTIFACT_BINDING: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-Artifact";
+
+// PAOS_BINDING is the official URN for the reverse SOAP (PAOS) binding of the ECP profile
+pub const PAOS_BINDING: &str = "urn:oasis:names:tc:SAML:2.0:bindings:PAOS";
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum NameIdFormat {
     UnspecifiedNameIDFormat,
@@ -100,6 +108,8 @@ pub struct IdpSsoDescriptor {
     pub want_authn_requests_signed: Option<bool>,
     #[yaserde(rename = "Signature", namespace = "ds")]
     pub signature: Option<String>,
+    #[yaserde(rename = "Extensions", prefix = "md")]
+    pub extensions: Option<Extensions>,
     #[yaserde(rename = "KeyDescriptor", prefix = "md", default)]
     pub key_descriptors: Vec<KeyDescriptor>,
     #[yaserde(rename = "Organization", prefix = "md")]
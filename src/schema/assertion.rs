@@ -1,6 +1,9 @@
-use super::{attribute_statement::AttributeStatement, AuthnStatement, Conditions, Issuer, Subject};
+use super::{
+    attribute_statement::AttributeStatement, AttributeValues, AuthnStatement, Conditions, Issuer,
+    Subject,
+};
 use crate::{
-    attribute::{Attribute, NAME_FORMAT_URI},
+    attribute::{self, Attribute, TypedValue, NAME_FORMAT_URI},
     signature::Signature,
     utils::UtcDateTime,
 };
@@ -47,6 +50,41 @@ impl Assertion {
             .collect()
     }
 
+    pub(crate) fn attributes(&self) -> impl Iterator<Item = &Attribute> {
+        self.attribute_statements
+            .iter()
+            .flat_map(|attribute_statement| attribute_statement.attributes.iter())
+    }
+
+    pub fn attributes_by_name(&self, name: &str) -> Vec<&Attribute> {
+        self.attributes()
+            .filter(|attr| attr.name.as_deref() == Some(name))
+            .collect()
+    }
+
+    pub fn attributes_by_friendly_name(&self, friendly_name: &str) -> Vec<&Attribute> {
+        self.attributes()
+            .filter(|attr| attr.friendly_name.as_deref() == Some(friendly_name))
+            .collect()
+    }
+
+    /// The values of the attributes whose Name, in any NameFormat, or FriendlyName is `key`, such
+    /// as [`crate::attribute::MAIL_URI`] or `"mail"`. Attributes found by Name take precedence
+    /// over the ones found by FriendlyName.
+    pub fn attribute(&self, key: &str) -> AttributeValues<'_> {
+        let mut attributes = self.attributes_by_name(key);
+        if attributes.is_empty() {
+            attributes = self.attributes_by_friendly_name(key);
+        }
+        AttributeValues::new(
+            key,
+            attributes
+                .into_iter()
+                .flat_map(|attr| attr.values.iter())
+                .collect(),
+        )
+    }
+
     pub fn attributes_by_uri(&self, uri: &str) -> Vec<&Attribute> {
         self.attributes_by_name_and_format(uri, NAME_FORMAT_URI)
     }
@@ -57,17 +95,41 @@ impl Assertion {
             .flat_map(|attr| {
                 attr.values
                     .iter()
-                    .filter(|v| {
-                        v.attribute_type
-                            .as_deref()
-                            .map(|t| t == "XSString")
-                            .unwrap_or(true)
-                    })
+                    .filter(|v| v.is_string())
                     .flat_map(|v| v.value.as_deref())
             })
             .collect()
     }
 
+    /// The values of the attributes with the given URI, of any type, parsed according to their
+    /// `xsi:type`.
+    pub fn typed_attribute_values(&self, uri: &str) -> Result<Vec<TypedValue>, Error> {
+        self.attributes_by_uri(uri)
+            .into_iter()
+            .flat_map(|attr| attr.values.iter())
+            .map(|value| {
+                value.typed().map_err(|error| Error::InvalidValue {
+                    uri: uri.to_owned(),
+                    error,
+                })
+            })
+            .collect()
+    }
+
+    pub fn typed_attribute_value(&self, uri: &str) -> Result<TypedValue, Error> {
+        let mut values = self.typed_attribute_values(uri)?;
+        match values.len() {
+            1 => Ok(values.remove(0)),
+            0 => Err(Error::NotFound {
+                uri: uri.to_owned(),
+            }),
+            count => Err(Error::NotUnique {
+                uri: uri.to_owned(),
+                count,
+            }),
+        }
+    }
+
     pub fn attribute_value(&self, uri: &str) -> Result<&str, Error> {
         match &self.attribute_values(uri)[..] {
             &[v] => Ok(v),
@@ -84,16 +146,23 @@ impl Assertion {
 
 #[derive(Debug, Snafu)]
 pub enum Error {
-    #[snafu(display("Cannot find attribute {uri} by uri"))]
+    #[snafu(display("Cannot find attribute {uri}"))]
     NotFound { uri: String },
-    #[snafu(display("Multiple ({count}) attributes found for uri {uri}"))]
+    #[snafu(display("Multiple ({count}) values found for attribute {uri}"))]
     NotUnique { uri: String, count: usize },
+    #[snafu(display("Invalid value of attribute {uri}: {error}"))]
+    InvalidValue {
+        uri: String,
+        error: attribute::Error,
+    },
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::attribute::*;
+    use crate::schema::NameId;
+    use chrono::{TimeZone, Utc};
 
     #[test]
     fn attribute_value() {
@@ -105,6 +174,7 @@ mod tests {
                     values: vec![AttributeValue {
                         attribute_type: Some("XSInteger".to_owned()),
                         value: Some("23".to_owned()),
+                        name_id: None,
                     }],
                     ..Default::default()
                 },
@@ -114,6 +184,7 @@ mod tests {
                     values: vec![AttributeValue {
                         attribute_type: Some("XSString".to_owned()),
                         value: Some("foo@example.com".to_owned()),
+                        name_id: None,
                     }],
                     ..Default::default()
                 },
@@ -160,12 +231,12 @@ mod tests {
         // With explicit xsi:type=XSInteger
         assert!(matches!(
             assertion.attribute_value("urn:dummy"),
-            Err(Error::NotFound { .. })
+            Err(super::Error::NotFound { .. })
         ));
         // With multiple results
         assert!(matches!(
             assertion.attribute_value(GIVEN_NAME_URI),
-            Err(Error::NotUnique { count: 2, .. })
+            Err(super::Error::NotUnique { count: 2, .. })
         ));
         // With multiple results
         assert_eq!(
@@ -173,4 +244,92 @@ mod tests {
             vec!["john", "colin"],
         );
     }
+
+    #[test]
+    fn typed_attribute_value() {
+        let value = |attribute_type: &str, value: &str| AttributeValue {
+            attribute_type: Some(attribute_type.to_owned()),
+            value: Some(value.to_owned()),
+            name_id: None,
+        };
+        let targeted_id = NameId {
+            format: Some("urn:oasis:names:tc:SAML:2.0:nameid-format:persistent".to_owned()),
+            value: "5f3a".to_owned(),
+            ..Default::default()
+        };
+        let attribute = |name: &str, values| Attribute {
+            name: Some(name.to_owned()),
+            name_format: Some(NAME_FORMAT_URI.to_owned()),
+            values,
+            ..Default::default()
+        };
+        let assertion = Assertion {
+            attribute_statements: vec![AttributeStatement {
+                attributes: vec![
+                    attribute("urn:active", vec![value("xs:boolean", "true")]),
+                    attribute("urn:age", vec![value("xsd:integer", "42")]),
+                    attribute("urn:invalid", vec![value("xs:integer", "many")]),
+                    attribute(
+                        "urn:expires",
+                        vec![value("xs:dateTime", "2024-01-01T12:00:00+01:00")],
+                    ),
+                    attribute(
+                        EDU_PERSON_TARGETED_ID_URI,
+                        vec![AttributeValue {
+                            name_id: Some(targeted_id.clone()),
+                            ..Default::default()
+                        }],
+                    ),
+                    attribute(
+                        EDU_PERSON_ENTITLEMENT_URI,
+                        vec![value("xs:string", "staff"), value("xs:anyURI", "urn:x")],
+                    ),
+                ],
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            assertion.typed_attribute_value("urn:active").unwrap(),
+            TypedValue::Boolean(true)
+        );
+        assert_eq!(
+            assertion.typed_attribute_value("urn:age").unwrap(),
+            TypedValue::Integer(42)
+        );
+        assert!(matches!(
+            assertion.typed_attribute_value("urn:invalid"),
+            Err(super::Error::InvalidValue { .. })
+        ));
+        assert_eq!(
+            assertion
+                .typed_attribute_value("urn:expires")
+                .unwrap()
+                .as_date_time(),
+            Some(Utc.with_ymd_and_hms(2024, 1, 1, 11, 0, 0).unwrap())
+        );
+        assert_eq!(
+            assertion
+                .typed_attribute_value(EDU_PERSON_TARGETED_ID_URI)
+                .unwrap(),
+            TypedValue::NameId(targeted_id)
+        );
+        // the string accessors skip values of other types
+        assert_eq!(
+            assertion
+                .typed_attribute_values(EDU_PERSON_ENTITLEMENT_URI)
+                .unwrap(),
+            vec![
+                TypedValue::String("staff".to_owned()),
+                TypedValue::Other {
+                    attribute_type: "xs:anyURI".to_owned(),
+                    value: "urn:x".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(
+            assertion.attribute_values(EDU_PERSON_ENTITLEMENT_URI),
+            vec!["staff"]
+        );
+    }
 }
@@ -0,0 +1,208 @@
+use super::assertion::{Assertion, Error};
+use crate::attribute::{AttributeValue, FromAttributeValue, ScopedValue, TypedValue};
+use crate::metadata::Scope;
+use crate::schema::NameId;
+use chrono::{DateTime, Utc};
+
+/// The values of the attributes of an assertion with a given Name or FriendlyName, see
+/// [`Assertion::attribute`].
+#[derive(Clone, Debug)]
+pub struct AttributeValues<'a> {
+    key: String,
+    values: Vec<&'a AttributeValue>,
+}
+
+impl<'a> AttributeValues<'a> {
+    pub(crate) fn new(key: &str, values: Vec<&'a AttributeValue>) -> Self {
+        AttributeValues {
+            key: key.to_owned(),
+            values,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn values(&self) -> &[&'a AttributeValue] {
+        &self.values
+    }
+
+    /// Converts the values to a single value, an optional one or a `Vec`, as the field of a
+    /// [`FromAssertion`] struct would.
+    pub fn get<T: FromAttributeValues>(&self) -> Result<T, Error> {
+        T::from_attribute_values(self)
+    }
+
+    /// Converts every value.
+    pub fn all<T: FromAttributeValue>(&self) -> Result<Vec<T>, Error> {
+        self.values
+            .iter()
+            .map(|value| {
+                T::from_attribute_value(value).map_err(|error| Error::InvalidValue {
+                    uri: self.key.clone(),
+                    error,
+                })
+            })
+            .collect()
+    }
+
+    /// Converts the only value, failing if there is none or several.
+    pub fn single<T: FromAttributeValue>(&self) -> Result<T, Error> {
+        self.optional()?.ok_or_else(|| Error::NotFound {
+            uri: self.key.clone(),
+        })
+    }
+
+    /// Converts the value if there is one, failing if there are several.
+    pub fn optional<T: FromAttributeValue>(&self) -> Result<Option<T>, Error> {
+        match self.values.len() {
+            0 => Ok(None),
+            1 => self.all().map(|mut values| values.pop()),
+            count => Err(Error::NotUnique {
+                uri: self.key.clone(),
+                count,
+            }),
+        }
+    }
+
+    pub fn strings(&self) -> Result<Vec<String>, Error> {
+        self.all()
+    }
+
+    pub fn string(&self) -> Result<String, Error> {
+        self.single()
+    }
+
+    pub fn boolean(&self) -> Result<bool, Error> {
+        self.single()
+    }
+
+    pub fn integer(&self) -> Result<i64, Error> {
+        self.single()
+    }
+
+    pub fn date_time(&self) -> Result<DateTime<Utc>, Error> {
+        self.single()
+    }
+
+    /// Parses the values as `value@scope`, all of which must be in the scopes of the IdP: a single
+    /// value outside of them fails the whole call with
+    /// [`crate::attribute::Error::ScopeNotAllowed`].
+    pub fn scoped(&self, scopes: &[&Scope]) -> Result<Vec<ScopedValue>, Error> {
+        let values = self.all::<ScopedValue>()?;
+        for value in &values {
+            value
+                .check_scope(scopes)
+                .map_err(|error| Error::InvalidValue {
+                    uri: self.key.clone(),
+                    error,
+                })?;
+        }
+        Ok(values)
+    }
+}
+
+/// Conversion of all the values of an attribute into the field of a [`FromAssertion`] struct: a
+/// value when there must be exactly one, an `Option` when there may be none, or a `Vec`.
+pub trait FromAttributeValues: Sized {
+    fn from_attribute_values(values: &AttributeValues) -> Result<Self, Error>;
+}
+
+impl<T: FromAttributeValue> FromAttributeValues for Option<T> {
+    fn from_attribute_values(values: &AttributeValues) -> Result<Self, Error> {
+        values.optional()
+    }
+}
+
+impl<T: FromAttributeValue> FromAttributeValues for Vec<T> {
+    fn from_attribute_values(values: &AttributeValues) -> Result<Self, Error> {
+        values.all()
+    }
+}
+
+macro_rules! single_attribute_value {
+    ($($ty:ty),*) => {
+        $(
+            impl FromAttributeValues for $ty {
+                fn from_attribute_values(values: &AttributeValues) -> Result<Self, Error> {
+                    values.single()
+                }
+            }
+        )*
+    };
+}
+
+single_attribute_value!(
+    String,
+    bool,
+    i64,
+    DateTime<Utc>,
+    NameId,
+    ScopedValue,
+    TypedValue
+);
+
+/// A user struct built from the attributes of an assertion, usually through
+/// [`from_assertion!`](crate::from_assertion!).
+pub trait FromAssertion: Sized {
+    fn from_assertion(assertion: &Assertion) -> Result<Self, Error>;
+}
+
+/// Declares a struct along with its [`FromAssertion`] implementation, each field taken from the
+/// attribute with the given Name or FriendlyName.
+///
+/// ```
+/// use samael::attribute::{EDU_PERSON_ENTITLEMENT_URI, MAIL_URI};
+/// use samael::schema::FromAssertion;
+///
+/// samael::from_assertion! {
+///     #[derive(Debug)]
+///     pub struct User {
+///         #[attribute = MAIL_URI]
+///         pub mail: String,
+///         #[attribute = "displayName"]
+///         pub display_name: Option<String>,
+///         #[attribute = EDU_PERSON_ENTITLEMENT_URI]
+///         pub entitlements: Vec<String>,
+///     }
+/// }
+///
+/// let user = User::from_assertion(&samael::schema::Assertion::default());
+/// assert!(user.is_err());
+/// ```
+#[macro_export]
+macro_rules! from_assertion {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                #[attribute = $key:expr]
+                $(#[$field_meta:meta])*
+                $field_vis:vis $field:ident : $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $(
+                $(#[$field_meta])*
+                $field_vis $field: $ty,
+            )*
+        }
+
+        impl $crate::schema::FromAssertion for $name {
+            fn from_assertion(
+                assertion: &$crate::schema::Assertion,
+            ) -> ::std::result::Result<Self, $crate::schema::AttributeError> {
+                ::std::result::Result::Ok($name {
+                    $($field: assertion.attribute($key).get()?,)*
+                })
+            }
+        }
+    };
+}
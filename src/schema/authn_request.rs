@@ -1,14 +1,12 @@
 use crate::schema::{Conditions, Issuer, Subject};
 use crate::signature::Signature;
+use crate::signer::{self, Signer, SoftwareSigner};
 use crate::utils::{self, UtcDateTime};
 use chrono::prelude::*;
 use snafu::Snafu;
 use std::str::FromStr;
 use yaserde_derive::{YaDeserialize, YaSerialize};
 
-#[cfg(feature = "xmlsec")]
-use crate::crypto;
-
 use super::NameIdPolicy;
 
 #[derive(Clone, Debug, Eq, PartialEq, YaDeserialize, YaSerialize)]
@@ -133,13 +131,17 @@ impl AuthnRequest {
         yaserde::ser::to_string(self)
     }
 
-    #[cfg(feature = "xmlsec")]
+    /// Serializes the request with an enveloped signature made by `signer`, for the HTTP-POST
+    /// binding.
+    pub fn sign(&self, signer: &dyn Signer) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(signer::sign_xml(&self.as_xml()?, signer)?)
+    }
+
     pub fn to_signed_xml(
         &self,
         private_key_der: &[u8],
     ) -> Result<String, Box<dyn std::error::Error>> {
-        crypto::sign_xml(self.as_xml()?, private_key_der)
-            .map_err(|crypto_error| Box::new(crypto_error) as Box<dyn std::error::Error>)
+        self.sign(&SoftwareSigner::from_rsa_der(private_key_der, None)?)
     }
 }
 
@@ -1,5 +1,6 @@
 mod assertion;
 pub mod attribute_statement;
+mod attribute_values;
 pub mod authn_request;
 mod conditions;
 pub mod encrypted_assertion;
@@ -9,8 +10,9 @@ mod name_id_policy;
 mod response;
 mod subject;
 
-pub use assertion::Assertion;
+pub use assertion::{Assertion, Error as AttributeError};
 pub use attribute_statement::AttributeStatement;
+pub use attribute_values::{AttributeValues, FromAssertion, FromAttributeValues};
 pub use authn_request::AuthnRequest;
 pub use conditions::*;
 pub use encrypted_assertion::EncryptedAssertion;
@@ -93,6 +95,40 @@ pub struct AuthnContextClassRef {
     pub value: Option<String>,
 }
 
+pub const STATUS_SUCCESS: &str = "urn:oasis:names:tc:SAML:2.0:status:Success";
+pub const STATUS_REQUESTER: &str = "urn:oasis:names:tc:SAML:2.0:status:Requester";
+pub const STATUS_RESPONDER: &str = "urn:oasis:names:tc:SAML:2.0:status:Responder";
+pub const STATUS_VERSION_MISMATCH: &str = "urn:oasis:names:tc:SAML:2.0:status:VersionMismatch";
+pub const STATUS_AUTHN_FAILED: &str = "urn:oasis:names:tc:SAML:2.0:status:AuthnFailed";
+pub const STATUS_INVALID_ATTR_NAME_OR_VALUE: &str =
+    "urn:oasis:names:tc:SAML:2.0:status:InvalidAttrNameOrValue";
+pub const STATUS_INVALID_NAME_ID_POLICY: &str =
+    "urn:oasis:names:tc:SAML:2.0:status:InvalidNameIDPolicy";
+pub const STATUS_NO_AUTHN_CONTEXT: &str = "urn:oasis:names:tc:SAML:2.0:status:NoAuthnContext";
+pub const STATUS_NO_AVAILABLE_IDP: &str = "urn:oasis:names:tc:SAML:2.0:status:NoAvailableIDP";
+pub const STATUS_NO_PASSIVE: &str = "urn:oasis:names:tc:SAML:2.0:status:NoPassive";
+pub const STATUS_NO_SUPPORTED_IDP: &str = "urn:oasis:names:tc:SAML:2.0:status:NoSupportedIDP";
+pub const STATUS_PARTIAL_LOGOUT: &str = "urn:oasis:names:tc:SAML:2.0:status:PartialLogout";
+pub const STATUS_PROXY_COUNT_EXCEEDED: &str =
+    "urn:oasis:names:tc:SAML:2.0:status:ProxyCountExceeded";
+pub const STATUS_REQUEST_DENIED: &str = "urn:oasis:names:tc:SAML:2.0:status:RequestDenied";
+pub const STATUS_REQUEST_UNSUPPORTED: &str =
+    "urn:oasis:names:tc:SAML:2.0:status:RequestUnsupported";
+pub const STATUS_REQUEST_VERSION_DEPRECATED: &str =
+    "urn:oasis:names:tc:SAML:2.0:status:RequestVersionDeprecated";
+pub const STATUS_REQUEST_VERSION_TOO_HIGH: &str =
+    "urn:oasis:names:tc:SAML:2.0:status:RequestVersionTooHigh";
+pub const STATUS_REQUEST_VERSION_TOO_LOW: &str =
+    "urn:oasis:names:tc:SAML:2.0:status:RequestVersionTooLow";
+pub const STATUS_RESOURCE_NOT_RECOGNIZED: &str =
+    "urn:oasis:names:tc:SAML:2.0:status:ResourceNotRecognized";
+pub const STATUS_TOO_MANY_RESPONSES: &str = "urn:oasis:names:tc:SAML:2.0:status:TooManyResponses";
+pub const STATUS_UNKNOWN_ATTR_PROFILE: &str =
+    "urn:oasis:names:tc:SAML:2.0:status:UnknownAttrProfile";
+pub const STATUS_UNKNOWN_PRINCIPAL: &str = "urn:oasis:names:tc:SAML:2.0:status:UnknownPrincipal";
+pub const STATUS_UNSUPPORTED_BINDING: &str =
+    "urn:oasis:names:tc:SAML:2.0:status:UnsupportedBinding";
+
 #[derive(Clone, Debug, Default, Eq, PartialEq, YaDeserialize, YaSerialize)]
 #[yaserde(namespace = "samlp: urn:oasis:names:tc:SAML:2.0:protocol")]
 pub struct Status {
@@ -104,10 +140,62 @@ pub struct Status {
     pub status_detail: Option<StatusDetail>,
 }
 
+impl Status {
+    pub fn is_success(&self) -> bool {
+        self.status_code.value == STATUS_SUCCESS
+    }
+
+    /// Whether the top-level or any nested status code is `code`.
+    pub fn has_code(&self, code: &str) -> bool {
+        self.status_code.codes().contains(&code)
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.status_message.as_ref()?.value.as_deref()
+    }
+}
+
 #[derive(Clone, Debug, Default, Eq, PartialEq, YaDeserialize, YaSerialize)]
+#[yaserde(namespace = "samlp: urn:oasis:names:tc:SAML:2.0:protocol")]
 pub struct StatusCode {
     #[yaserde(attribute, rename = "Value")]
     pub value: String,
+    /// The more specific status code nested in this one. yaserde cannot box a recursive field,
+    /// so it is kept in a `Vec`, which holds one code at most.
+    #[yaserde(rename = "StatusCode", prefix = "samlp")]
+    pub status_code: Vec<StatusCode>,
+}
+
+impl StatusCode {
+    pub fn new(value: &str) -> Self {
+        StatusCode {
+            value: value.to_string(),
+            status_code: vec![],
+        }
+    }
+
+    /// A top-level code, such as Requester or Responder, refined by a second-level one.
+    pub fn with_sub_code(value: &str, sub_code: &str) -> Self {
+        StatusCode {
+            value: value.to_string(),
+            status_code: vec![StatusCode::new(sub_code)],
+        }
+    }
+
+    pub fn sub_code(&self) -> Option<&StatusCode> {
+        self.status_code.first()
+    }
+
+    /// This code, then the nested ones from the outermost.
+    pub fn codes(&self) -> Vec<&str> {
+        let mut codes = vec![self.value.as_str()];
+        let mut code = self;
+        while let Some(sub_code) = code.sub_code() {
+            codes.push(&sub_code.value);
+            code = sub_code;
+        }
+        codes
+    }
 }
 
 #[derive(Clone, Debug, Default, Eq, PartialEq, YaDeserialize, YaSerialize)]
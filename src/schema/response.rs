@@ -33,9 +33,9 @@ pub struct Response {
     #[yaserde(rename = "Status", prefix = "samlp")]
     pub status: Status,
     #[yaserde(rename = "Assertion", prefix = "saml")]
-    pub assertion: Option<Assertion>,
+    pub assertions: Vec<Assertion>,
     #[yaserde(rename = "EncryptedAssertion", prefix = "saml")]
-    pub encrypted_assertion: Option<EncryptedAssertion>,
+    pub encrypted_assertions: Vec<EncryptedAssertion>,
 }
 
 #[derive(Debug, Snafu)]
@@ -1,7 +1,17 @@
-use crate::crypto;
+use crate::clock::{Clock, SystemClock};
+use crate::crypto::{self, XMLNS_SAML_PROTOCOL};
+use crate::dom::Document;
+use crate::login::Login;
 use crate::metadata::{Endpoint, IndexedEndpoint, KeyDescriptor, NameIdFormat, SpSsoDescriptor};
-use crate::schema::{Assertion, NameIdPolicy, Response};
-use crate::utils::UtcDateTime;
+use crate::schema::{
+    Assertion, LogoutRequest, NameId, NameIdPolicy, Response, Status, STATUS_AUTHN_FAILED,
+    STATUS_NO_AUTHN_CONTEXT, STATUS_NO_PASSIVE, STATUS_REQUESTER, STATUS_REQUEST_DENIED,
+    STATUS_RESPONDER,
+};
+use crate::signer::{self, Signer, SoftwareSigner};
+use crate::trust::{self, CertificateTrust};
+use crate::utils;
+use crate::xmlenc::{self, DecryptionKeyProvider, SoftwareKeyProvider};
 use crate::{
     key_info::{KeyInfo, X509Data},
     metadata::{ContactPerson, EncryptionMethod, EntityDescriptor, HTTP_POST_BINDING},
@@ -10,21 +20,14 @@ use crate::{
 use base64::{engine::general_purpose, Engine as _};
 use chrono::{Duration, SecondsFormat};
 use flate2::{write::DeflateEncoder, Compression};
-use openssl::pkey::Private;
+use openssl::pkey::{PKey, Private};
 use openssl::{rsa, x509};
 use snafu::Snafu;
 use std::fmt::Debug;
 use std::io::Write;
+use std::sync::Arc;
 use url::Url;
 
-#[cfg(feature = "xmlsec")]
-use crate::crypto::reduce_xml_to_signed;
-
-#[cfg(not(feature = "xmlsec"))]
-fn reduce_xml_to_signed<T>(xml_str: &str, _keys: &[T]) -> Result<String, Error> {
-    Ok(String::from(xml_str))
-}
-
 #[derive(Debug, Snafu)]
 pub enum Error {
     #[snafu(display(
@@ -84,20 +87,52 @@ pub enum Error {
     ResponseExpired {
         time: String,
     },
-    #[snafu(display("SAML Response StatusCode is not successful: {}", code))]
+    #[snafu(display("SAML Response StatusCode is not successful: {}", status))]
     ResponseBadStatusCode {
-        code: String,
+        status: ResponseStatus,
+    },
+    /// A failed response which no signature of the IdP covers, as IdPs often leave them unsigned.
+    /// Anyone can forge one: its status must neither be shown as coming from the IdP nor be acted
+    /// upon like that of [`Error::ResponseBadStatusCode`].
+    #[snafu(display("Unverified SAML Response StatusCode is not successful: {}", status))]
+    UnverifiedResponseBadStatusCode {
+        status: ResponseStatus,
+    },
+    #[snafu(display("Failed to decrypt SAML Assertion: {}", error))]
+    FailedToDecryptAssertion {
+        error: xmlenc::Error,
     },
-    #[snafu(display("Encrypted SAML Assertions are not yet supported"))]
-    EncryptedAssertionsNotYetSupported,
-    #[snafu(display("SAML Response and all assertions must be signed"))]
-    FailedToValidateSignature,
+    #[snafu(display("SAML Response and all assertions must be signed: {}", error))]
+    FailedToValidateSignature {
+        error: crypto::Error,
+    },
+    #[snafu(display(
+        "Signature verification unavailable: samael was built without a signature backend"
+    ))]
+    SignatureVerificationUnavailable,
     #[snafu(display("Failed to deserialize SAML response."))]
     DeserializeResponseError,
+    #[snafu(display("IdP signing certificate is not trusted: {}", error))]
+    UntrustedSigningCertificate {
+        error: trust::Error,
+    },
+    #[snafu(display(
+        "No IdP signing certificate: the IdP metadata lists none and the response signatures embed none"
+    ))]
+    MissingSigningCertificate,
     #[snafu(display("Failed to parse cert '{}'. Assumed DER format.", cert))]
     FailedToParseCert {
         cert: String,
     },
+    #[snafu(display("SAML Response has no bearer assertion with an AuthnStatement"))]
+    MissingAuthnAssertion,
+    #[snafu(display(
+        "SAML Assertion {} is not about the subject of the authentication",
+        assertion_id
+    ))]
+    AssertionSubjectMismatch {
+        assertion_id: String,
+    },
     #[snafu(display("Unexpected Error Occurred!"))]
     UnexpectedError,
 
@@ -106,14 +141,138 @@ pub enum Error {
 
     MissingAcsUrl,
     MissingSloUrl,
+    MissingPrivateKey,
+}
+
+/// The status of a response that is not successful, as the IdP reported it.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ResponseStatus {
+    /// The top-level code: Requester, Responder or VersionMismatch.
+    pub code: String,
+    /// The nested codes, such as NoPassive or AuthnFailed, from the outermost.
+    pub sub_codes: Vec<String>,
+    pub message: Option<String>,
+    /// The StatusDetail element, whose content is specific to the IdP.
+    pub detail: Option<String>,
+}
+
+impl ResponseStatus {
+    fn new(status: &Status, response_xml: &str) -> Self {
+        let mut codes = status.status_code.codes().into_iter().map(str::to_string);
+        ResponseStatus {
+            code: codes.next().unwrap_or_default(),
+            sub_codes: codes.collect(),
+            message: status.message().map(str::to_string),
+            detail: status
+                .status_detail
+                .as_ref()
+                .and_then(|_| status_detail_xml(response_xml)),
+        }
+    }
+
+    /// Whether the top-level or any nested code is `code`, one of the `schema::STATUS_*`
+    /// constants.
+    pub fn has_code(&self, code: &str) -> bool {
+        self.code == code || self.sub_codes.iter().any(|sub_code| sub_code == code)
+    }
+
+    /// The request was wrong, such as asking for an unsupported NameID format.
+    pub fn is_requester_error(&self) -> bool {
+        self.code == STATUS_REQUESTER
+    }
+
+    /// The IdP failed, or refused to authenticate the user.
+    pub fn is_responder_error(&self) -> bool {
+        self.code == STATUS_RESPONDER
+    }
+
+    /// A passive request could not be fulfilled without interacting with the user, who is not
+    /// logged in at the IdP.
+    pub fn is_no_passive(&self) -> bool {
+        self.has_code(STATUS_NO_PASSIVE)
+    }
+
+    /// The user failed to authenticate, or cancelled.
+    pub fn is_authn_failed(&self) -> bool {
+        self.has_code(STATUS_AUTHN_FAILED)
+    }
+
+    pub fn is_request_denied(&self) -> bool {
+        self.has_code(STATUS_REQUEST_DENIED)
+    }
+
+    /// The IdP cannot authenticate the user with the requested authentication context.
+    pub fn is_no_authn_context(&self) -> bool {
+        self.has_code(STATUS_NO_AUTHN_CONTEXT)
+    }
+}
+
+impl std::fmt::Display for ResponseStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code)?;
+        for sub_code in &self.sub_codes {
+            write!(f, " / {}", sub_code)?;
+        }
+        if let Some(message) = &self.message {
+            write!(f, ": {}", message)?;
+        }
+        Ok(())
+    }
+}
+
+fn status_detail_xml(response_xml: &str) -> Option<String> {
+    let document = Document::parse(response_xml).ok()?;
+    let status = document.first_child(document.root_element()?, "Status", XMLNS_SAML_PROTOCOL)?;
+    let detail = document.first_child(status, "StatusDetail", XMLNS_SAML_PROTOCOL)?;
+    Some(document.element_to_xml(detail))
+}
+
+fn check_status(response: &Response, response_xml: &str) -> Result<(), Error> {
+    if response.status.is_success() {
+        Ok(())
+    } else {
+        Err(Error::ResponseBadStatusCode {
+            status: ResponseStatus::new(&response.status, response_xml),
+        })
+    }
+}
+
+/// Reports the status of a failed response which did not pass signature verification, rather than
+/// the verification error, since IdPs often leave failed responses unsigned.
+fn unverified_status(response_xml: &str) -> Option<Error> {
+    let response: Response = response_xml.parse().ok()?;
+    (!response.status.is_success()).then(|| Error::UnverifiedResponseBadStatusCode {
+        status: ResponseStatus::new(&response.status, response_xml),
+    })
+}
+
+/// A private key along with the certificate published for it.
+#[derive(Clone)]
+pub struct KeyPair {
+    pub key: rsa::Rsa<Private>,
+    pub certificate: x509::X509,
 }
 
 #[derive(Builder, Clone)]
 #[builder(default, setter(into))]
 pub struct ServiceProvider {
     pub entity_id: String,
+    /// The active private key, used to sign requests and to decrypt assertions.
     pub key: Option<rsa::Rsa<Private>>,
+    /// The certificate of the active private key.
     pub certificate: Option<x509::X509>,
+    /// Signs requests in place of `key`, for keys held in an HSM or a KMS.
+    pub signer: Option<Arc<dyn Signer>>,
+    /// Unwraps the keys of encrypted assertions in place of the key pairs, for keys held in an
+    /// HSM.
+    pub decryption_key_provider: Option<Arc<dyn DecryptionKeyProvider>>,
+    /// The key pair to roll over to. Its certificate is published in metadata next to the active
+    /// one, and assertions encrypted for it are decrypted already, so that IdPs can switch to it
+    /// at their own pace. See [`ServiceProvider::promote_next_key_pair`].
+    pub next_key_pair: Option<KeyPair>,
+    /// Key pairs which have been rolled over, most recent first. They are no longer published but
+    /// still decrypt assertions from IdPs which have not refreshed the metadata yet.
+    pub previous_key_pairs: Vec<KeyPair>,
     pub intermediates: Option<Vec<x509::X509>>,
     pub metadata_url: Option<String>,
     pub acs_url: Option<String>,
@@ -126,6 +285,18 @@ pub struct ServiceProvider {
     pub contact_person: Option<ContactPerson>,
     pub max_issue_delay: Duration,
     pub max_clock_skew: Duration,
+    /// Accept responses without checking their signatures when samael is built without the
+    /// `xmlsec` and `native-xmldsig` features. This is insecure and only meant for tests: any
+    /// attacker can then forge a response. Has no effect when a signature backend is enabled.
+    pub dangerously_accept_unverified_responses: bool,
+    /// How the IdP signing certificates found in metadata are trusted. With
+    /// [`CertificateTrust::Pki`], `intermediates` and the other certificates of the metadata are
+    /// used to build their chains. When the metadata lists no certificates, the PKI must trust a
+    /// certificate embedded in the signatures of the response, or the response is refused.
+    pub idp_certificate_trust: CertificateTrust,
+    /// The source of the current time, to check validity windows and to date requests and
+    /// metadata.
+    pub clock: Arc<dyn Clock>,
 }
 
 impl Default for ServiceProvider {
@@ -134,6 +305,10 @@ impl Default for ServiceProvider {
             entity_id: String::from("<unset>"),
             key: None,
             certificate: None,
+            signer: None,
+            decryption_key_provider: None,
+            next_key_pair: None,
+            previous_key_pairs: vec![],
             intermediates: None,
             metadata_url: Some("http://localhost:8080/saml/metadata".to_string()),
             acs_url: Some("http://localhost:8080/saml/acs".to_string()),
@@ -146,6 +321,9 @@ impl Default for ServiceProvider {
             contact_person: None,
             max_issue_delay: Duration::seconds(90),
             max_clock_skew: Duration::seconds(180),
+            dangerously_accept_unverified_responses: false,
+            idp_certificate_trust: CertificateTrust::Metadata,
+            clock: Arc::new(SystemClock),
         }
     }
 }
@@ -158,49 +336,14 @@ impl ServiceProvider {
             Some(chrono::Duration::hours(48))
         };
 
-        let valid_until = valid_duration.map(|d| &UtcDateTime::now() + d);
+        let valid_until = valid_duration.map(|d| &self.clock.now() + d);
 
         let mut key_descriptors = vec![];
         if let Some(cert) = &self.certificate {
-            let mut cert_bytes: Vec<u8> = cert.to_der()?;
-            if let Some(intermediates) = &self.intermediates {
-                for intermediate in intermediates {
-                    cert_bytes.append(&mut intermediate.to_der()?);
-                }
-            }
-            key_descriptors.push(KeyDescriptor {
-                encryption_methods: vec![],
-                key_use: Some("signing".to_string()),
-                key_info: KeyInfo {
-                    id: None,
-                    x509_data: Some(X509Data {
-                        certificates: vec![general_purpose::STANDARD.encode(&cert_bytes)],
-                    }),
-                },
-            });
-            key_descriptors.push(KeyDescriptor {
-                key_use: Some("signing".to_string()),
-                key_info: KeyInfo {
-                    id: None,
-                    x509_data: Some(X509Data {
-                        certificates: vec![general_purpose::STANDARD.encode(&cert_bytes)],
-                    }),
-                },
-                encryption_methods: vec![
-                    EncryptionMethod {
-                        algorithm: "http://www.w3.org/2001/04/xmlenc#aes128-cbc".to_string(),
-                    },
-                    EncryptionMethod {
-                        algorithm: "http://www.w3.org/2001/04/xmlenc#aes192-cbc".to_string(),
-                    },
-                    EncryptionMethod {
-                        algorithm: "http://www.w3.org/2001/04/xmlenc#aes256-cbc".to_string(),
-                    },
-                    EncryptionMethod {
-                        algorithm: "http://www.w3.org/2001/04/xmlenc#rsa-oaep-mgf1p".to_string(),
-                    },
-                ],
-            })
+            key_descriptors.append(&mut self.key_descriptors(cert)?);
+        }
+        if let Some(next_key_pair) = &self.next_key_pair {
+            key_descriptors.append(&mut self.key_descriptors(&next_key_pair.certificate)?);
         }
 
         let sso_sp_descriptor = SpSsoDescriptor {
@@ -232,6 +375,86 @@ impl ServiceProvider {
         })
     }
 
+    /// Describes a certificate, along with the intermediates, for signing and for encryption.
+    fn key_descriptors(
+        &self,
+        cert: &x509::X509,
+    ) -> Result<Vec<KeyDescriptor>, Box<dyn std::error::Error>> {
+        let mut cert_bytes: Vec<u8> = cert.to_der()?;
+        if let Some(intermediates) = &self.intermediates {
+            for intermediate in intermediates {
+                cert_bytes.append(&mut intermediate.to_der()?);
+            }
+        }
+        let key_info = KeyInfo {
+            id: None,
+            x509_data: Some(X509Data {
+                certificates: vec![general_purpose::STANDARD.encode(&cert_bytes)],
+            }),
+        };
+        Ok(vec![
+            KeyDescriptor {
+                encryption_methods: vec![],
+                key_use: Some("signing".to_string()),
+                key_info: key_info.clone(),
+            },
+            KeyDescriptor {
+                key_use: Some("encryption".to_string()),
+                key_info,
+                encryption_methods: [
+                    xmlenc::AES128_GCM,
+                    xmlenc::AES256_GCM,
+                    xmlenc::AES128_CBC,
+                    xmlenc::AES192_CBC,
+                    xmlenc::AES256_CBC,
+                    xmlenc::RSA_OAEP_MGF1P,
+                ]
+                .iter()
+                .map(|algorithm| EncryptionMethod {
+                    algorithm: algorithm.to_string(),
+                })
+                .collect(),
+            },
+        ])
+    }
+
+    /// Makes the next key pair the active one, and keeps the previously active one around to
+    /// decrypt assertions. Does nothing when there is no next key pair.
+    pub fn promote_next_key_pair(&mut self) {
+        if let Some(next_key_pair) = self.next_key_pair.take() {
+            let key = self.key.replace(next_key_pair.key);
+            let certificate = self.certificate.replace(next_key_pair.certificate);
+            if let (Some(key), Some(certificate)) = (key, certificate) {
+                self.previous_key_pairs
+                    .insert(0, KeyPair { key, certificate });
+            }
+        }
+    }
+
+    /// The private keys to decrypt assertions with: the active one, the next one, then the
+    /// previous ones.
+    fn decryption_keys(&self) -> Vec<rsa::Rsa<Private>> {
+        self.key
+            .iter()
+            .cloned()
+            .chain(self.next_key_pair.iter().map(|pair| pair.key.clone()))
+            .chain(self.previous_key_pairs.iter().map(|pair| pair.key.clone()))
+            .collect()
+    }
+
+    /// Replaces the encrypted assertions of a response with the assertions they hold. Returns
+    /// `None` when there is no encrypted assertion.
+    fn decrypt_assertions(&self, response_xml: &str) -> Result<Option<String>, Error> {
+        let decrypted = match &self.decryption_key_provider {
+            Some(provider) => xmlenc::decrypt_assertions(response_xml, provider.as_ref()),
+            None => xmlenc::decrypt_assertions(
+                response_xml,
+                &SoftwareKeyProvider::new(self.decryption_keys()),
+            ),
+        };
+        decrypted.map_err(|error| Error::FailedToDecryptAssertion { error })
+    }
+
     fn name_id_format(&self) -> Option<String> {
         self.authn_name_id_format
             .clone()
@@ -295,6 +518,9 @@ impl ServiceProvider {
                 }
             }
         }
+        if let CertificateTrust::Pki(pki) = &self.idp_certificate_trust {
+            result = self.trusted_certs(pki, result)?;
+        }
         Ok(if result.is_empty() {
             None
         } else {
@@ -302,6 +528,49 @@ impl ServiceProvider {
         })
     }
 
+    /// Keeps the certificates which the PKI trusts, and fails if there is none.
+    fn trusted_certs(
+        &self,
+        pki: &trust::PkiTrust,
+        certs: Vec<x509::X509>,
+    ) -> Result<Vec<x509::X509>, Error> {
+        let mut untrusted = self.intermediates.clone().unwrap_or_default();
+        untrusted.extend(certs.iter().cloned());
+        let mut trusted = vec![];
+        let mut last_error = None;
+        for cert in certs {
+            match pki.validate(&cert, &untrusted, self.max_clock_skew, self.clock.as_ref()) {
+                Ok(()) => trusted.push(cert),
+                Err(error) => last_error = Some(error),
+            }
+        }
+        match last_error {
+            Some(error) if trusted.is_empty() => Err(Error::UntrustedSigningCertificate { error }),
+            _ => Ok(trusted),
+        }
+    }
+
+    /// The certificates to verify the signatures of a response with: those of the IdP metadata,
+    /// or, when it lists none, the ones embedded in the signatures of the response which the PKI
+    /// trusts. `None` means that the signatures are not checked, which only happens without
+    /// PKI trust.
+    fn response_signing_certs(&self, response_xml: &str) -> Result<Option<Vec<x509::X509>>, Error> {
+        if let Some(certs) = self.idp_signing_certs()? {
+            return Ok(Some(certs));
+        }
+        let CertificateTrust::Pki(pki) = &self.idp_certificate_trust else {
+            return Ok(None);
+        };
+        // the signature of an encrypted assertion is only visible once it is decrypted
+        let decrypted_xml = self.decrypt_assertions(response_xml)?;
+        let embedded = embedded_certificates(decrypted_xml.as_deref().unwrap_or(response_xml))?;
+        let trusted = self.trusted_certs(pki, embedded)?;
+        if trusted.is_empty() {
+            return Err(Error::MissingSigningCertificate);
+        }
+        Ok(Some(trusted))
+    }
+
     pub fn parse_response<AsStr: AsRef<str> + Debug>(
         &self,
         encoded_resp: &str,
@@ -313,20 +582,115 @@ impl ServiceProvider {
         Ok(assertion)
     }
 
+    /// Verifies the signatures of a SAML response against the IdP signing certificates, and
+    /// reports which signatures were found, what they cover, and which elements were removed
+    /// because no signature covers them.
+    #[cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
+    pub fn verify_response_signatures(
+        &self,
+        response_xml: &str,
+    ) -> Result<crypto::VerificationReport, Error> {
+        let sign_certs = self
+            .response_signing_certs(response_xml)?
+            .unwrap_or_default();
+        crypto::verify_signatures(response_xml, &sign_certs)
+            .map_err(|error| Error::FailedToValidateSignature { error })
+    }
+
+    #[cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
+    fn reduce_xml_to_signed(
+        &self,
+        response_xml: &str,
+        sign_certs: &[x509::X509],
+    ) -> Result<String, Error> {
+        let verify = |xml: &str| {
+            crypto::verify_signatures(xml, sign_certs)
+                .map_err(|error| Error::FailedToValidateSignature { error })
+        };
+        let report = verify(response_xml)?;
+        if report.signatures.is_empty() || !report.removed_elements.is_empty() {
+            // the response is not signed as a whole, so a decrypted assertion must be signed
+            // itself
+            match self.decrypt_assertions(response_xml)? {
+                Some(decrypted_xml) => Ok(verify(&decrypted_xml)?.reduced_xml),
+                None => Ok(report.reduced_xml),
+            }
+        } else {
+            // the signature of the response covers the encrypted assertion
+            Ok(self
+                .decrypt_assertions(&report.reduced_xml)?
+                .unwrap_or(report.reduced_xml))
+        }
+    }
+
+    #[cfg(not(any(feature = "xmlsec", feature = "native-xmldsig")))]
+    fn reduce_xml_to_signed(
+        &self,
+        response_xml: &str,
+        _sign_certs: &[x509::X509],
+    ) -> Result<String, Error> {
+        if self.dangerously_accept_unverified_responses {
+            Ok(self
+                .decrypt_assertions(response_xml)?
+                .unwrap_or_else(|| String::from(response_xml)))
+        } else {
+            Err(Error::SignatureVerificationUnavailable)
+        }
+    }
+
+    /// Validates a base64 encoded SAML response, and returns the login it asserts along with the
+    /// RelayState posted with it.
+    pub fn parse_login<AsStr: AsRef<str> + Debug>(
+        &self,
+        encoded_resp: &str,
+        relay_state: Option<&str>,
+        possible_request_ids: &[AsStr],
+    ) -> Result<Login, Box<dyn std::error::Error>> {
+        let bytes = general_purpose::STANDARD.decode(encoded_resp)?;
+        let decoded = std::str::from_utf8(&bytes)?;
+        let login = self.parse_xml_login(decoded, relay_state, possible_request_ids)?;
+        Ok(login)
+    }
+
+    pub fn parse_xml_login<AsStr: AsRef<str> + Debug>(
+        &self,
+        response_xml: &str,
+        relay_state: Option<&str>,
+        possible_request_ids: &[AsStr],
+    ) -> Result<Login, Error> {
+        let (response, assertion) = self.validate_response(response_xml, possible_request_ids)?;
+        Ok(Login::new(&response, assertion, response_xml, relay_state))
+    }
+
     pub fn parse_xml_response<AsStr: AsRef<str> + Debug>(
         &self,
         response_xml: &str,
         possible_request_ids: &[AsStr],
     ) -> Result<Assertion, Error> {
-        let reduced_xml = if let Some(sign_certs) = self.idp_signing_certs()? {
-            reduce_xml_to_signed(response_xml, &sign_certs)
-                .map_err(|_e| Error::FailedToValidateSignature)?
+        let (_, assertion) = self.validate_response(response_xml, possible_request_ids)?;
+        Ok(assertion)
+    }
+
+    /// Validates the response and its assertions, and returns the response along with its
+    /// authentication assertion, taken out of it.
+    fn validate_response<AsStr: AsRef<str> + Debug>(
+        &self,
+        response_xml: &str,
+        possible_request_ids: &[AsStr],
+    ) -> Result<(Response, Assertion), Error> {
+        let reduced_xml = if let Some(sign_certs) = self
+            .response_signing_certs(response_xml)
+            .map_err(|error| unverified_status(response_xml).unwrap_or(error))?
+        {
+            self.reduce_xml_to_signed(response_xml, &sign_certs)
+                .map_err(|error| unverified_status(response_xml).unwrap_or(error))?
         } else {
-            String::from(response_xml)
+            self.decrypt_assertions(response_xml)?
+                .unwrap_or_else(|| String::from(response_xml))
         };
-        let response: Response = reduced_xml
-            .parse()
-            .map_err(|_e| Error::FailedToParseSamlResponse)?;
+        let mut response: Response = reduced_xml.parse().map_err(|_e| {
+            unverified_status(response_xml).unwrap_or(Error::FailedToParseSamlResponse)
+        })?;
         self.validate_destination(&response)?;
         let mut request_id_valid = false;
         if self.allow_idp_initiated {
@@ -346,7 +710,7 @@ impl ServiceProvider {
                     .collect(),
             });
         }
-        if &response.issue_instant + self.max_issue_delay < UtcDateTime::now() {
+        if &response.issue_instant + self.max_issue_delay < self.clock.now() {
             return Err(Error::ResponseExpired {
                 time: (response.issue_instant.0 + self.max_issue_delay)
                     .to_rfc3339_opts(SecondsFormat::Secs, true),
@@ -360,21 +724,13 @@ impl ServiceProvider {
                 });
             }
         }
-        let status = &response.status.status_code.value;
-        if status != "urn:oasis:names:tc:SAML:2.0:status:Success" {
-            return Err(Error::ResponseBadStatusCode {
-                code: status.clone(),
-            });
-        }
+        check_status(&response, &reduced_xml)?;
 
-        if let Some(_encrypted_assertion) = &response.encrypted_assertion {
-            Err(Error::EncryptedAssertionsNotYetSupported)
-        } else if let Some(assertion) = &response.assertion {
+        for assertion in &response.assertions {
             self.validate_assertion(assertion, possible_request_ids)?;
-            Ok(assertion.clone())
-        } else {
-            Err(Error::UnexpectedError)
         }
+        let assertion = merge_assertions(std::mem::take(&mut response.assertions))?;
+        Ok((response, assertion))
     }
 
     fn validate_assertion<AsStr: AsRef<str> + Debug>(
@@ -382,7 +738,7 @@ impl ServiceProvider {
         assertion: &Assertion,
         _possible_request_ids: &[AsStr],
     ) -> Result<(), Error> {
-        if &assertion.issue_instant + self.max_issue_delay < UtcDateTime::now() {
+        if &assertion.issue_instant + self.max_issue_delay < self.clock.now() {
             return Err(Error::AssertionExpired {
                 time: (assertion.issue_instant.0 + self.max_issue_delay)
                     .to_rfc3339_opts(SecondsFormat::Secs, true),
@@ -396,7 +752,7 @@ impl ServiceProvider {
         }
         if let Some(conditions) = &assertion.conditions {
             if let Some(not_before) = &conditions.not_before {
-                if &UtcDateTime::now() + self.max_clock_skew < *not_before {
+                if &self.clock.now() + self.max_clock_skew < *not_before {
                     return Err(Error::AssertionConditionExpiredBefore {
                         time: (not_before.0 - self.max_clock_skew)
                             .to_rfc3339_opts(SecondsFormat::Secs, true),
@@ -404,7 +760,7 @@ impl ServiceProvider {
                 }
             }
             if let Some(not_on_or_after) = &conditions.not_on_or_after {
-                if not_on_or_after + self.max_clock_skew < UtcDateTime::now() {
+                if not_on_or_after + self.max_clock_skew < self.clock.now() {
                     return Err(Error::AssertionConditionExpired {
                         time: (not_on_or_after.0 + self.max_clock_skew)
                             .to_rfc3339_opts(SecondsFormat::Secs, true),
@@ -439,6 +795,33 @@ impl ServiceProvider {
         Ok(())
     }
 
+    /// The signer of requests: `signer` when set, otherwise the active private key.
+    pub fn request_signer(&self) -> Result<Arc<dyn Signer>, Box<dyn std::error::Error>> {
+        if let Some(signer) = &self.signer {
+            return Ok(signer.clone());
+        }
+        let key = self.key.as_ref().ok_or(Error::MissingPrivateKey)?;
+        let signer = SoftwareSigner::new(PKey::from_rsa(key.clone())?, self.certificate.clone())?;
+        Ok(Arc::new(signer))
+    }
+
+    /// Signs an authentication request for the HTTP-POST binding.
+    pub fn sign_authentication_request(
+        &self,
+        request: &AuthnRequest,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        request.sign(self.request_signer()?.as_ref())
+    }
+
+    /// Builds the signed URL of an authentication request for the HTTP-Redirect binding.
+    pub fn signed_redirect(
+        &self,
+        request: &AuthnRequest,
+        relay_state: Option<&str>,
+    ) -> Result<Url, Box<dyn std::error::Error>> {
+        request.signed_redirect(relay_state, self.request_signer()?.as_ref())
+    }
+
     pub fn make_authentication_request(
         &self,
         idp_url: &str,
@@ -458,9 +841,28 @@ impl ServiceProvider {
                 ..NameIdPolicy::default()
             }),
             force_authn: Some(self.force_authn),
+            issue_instant: self.clock.now(),
             ..AuthnRequest::default()
         })
     }
+
+    /// Builds a LogoutRequest ending the session of a login at the IdP.
+    pub fn make_logout_request(&self, login: &Login, idp_url: &str) -> LogoutRequest {
+        LogoutRequest {
+            id: utils::gen_saml_assertion_id(),
+            version: String::from("2.0"),
+            issue_instant: self.clock.now(),
+            destination: Some(idp_url.to_string()),
+            issuer: Some(Issuer {
+                format: Some("urn:oasis:names:tc:SAML:2.0:nameid-format:entity".to_string()),
+                value: Some(self.entity_id.clone()),
+                ..Issuer::default()
+            }),
+            name_id: login.name_id.clone(),
+            session_index: login.session_index.clone(),
+            ..LogoutRequest::default()
+        }
+    }
 }
 
 fn parse_certificates(key_descriptor: &KeyDescriptor) -> Result<Vec<x509::X509>, Error> {
@@ -484,6 +886,84 @@ fn parse_certificates(key_descriptor: &KeyDescriptor) -> Result<Vec<x509::X509>,
         .unwrap_or(Ok(vec![]))
 }
 
+const XMLNS_XML_DSIG: &str = "http://www.w3.org/2000/09/xmldsig#";
+
+/// The certificates in the `KeyInfo` of the signatures of a document.
+fn embedded_certificates(xml: &str) -> Result<Vec<x509::X509>, Error> {
+    let document = Document::parse(xml).map_err(|_| Error::FailedToParseSamlResponse)?;
+    document
+        .elements()
+        .into_iter()
+        .filter(|node| {
+            document
+                .element(*node)
+                .is_some_and(|element| element.is("X509Certificate", XMLNS_XML_DSIG))
+                && document.ancestors(*node).any(|ancestor| {
+                    document
+                        .element(ancestor)
+                        .is_some_and(|element| element.is("Signature", XMLNS_XML_DSIG))
+                })
+        })
+        .map(|node| {
+            let cert = document.text(node);
+            crypto::decode_x509_cert(&cert)
+                .ok()
+                .and_then(|decoded| x509::X509::from_der(&decoded).ok())
+                .ok_or(Error::FailedToParseCert { cert })
+        })
+        .collect()
+}
+
+const BEARER_CONFIRMATION_METHOD: &str = "urn:oasis:names:tc:SAML:2.0:cm:bearer";
+
+fn is_bearer_authn_assertion(assertion: &Assertion) -> bool {
+    !assertion.authn_statements.is_empty()
+        && assertion.subject.as_ref().is_some_and(|subject| {
+            subject.subject_confirmations.iter().any(|confirmation| {
+                confirmation.method.as_deref() == Some(BEARER_CONFIRMATION_METHOD)
+            })
+        })
+}
+
+fn subject_name_id(assertion: &Assertion) -> Option<&NameId> {
+    assertion.subject.as_ref()?.name_id.as_ref()
+}
+
+/// Picks the first bearer assertion with an AuthnStatement, and appends the attribute statements
+/// of the other assertions to its own. Attributes from other assertions are only accepted about
+/// the same subject, and are looked up after those of the authentication assertion.
+fn merge_assertions(assertions: Vec<Assertion>) -> Result<Assertion, Error> {
+    let position = assertions
+        .iter()
+        .position(is_bearer_authn_assertion)
+        .ok_or(Error::MissingAuthnAssertion)?;
+    let mut others = assertions;
+    let mut authn_assertion = others.remove(position);
+    for assertion in others {
+        if assertion.attribute_statements.is_empty() {
+            continue;
+        }
+        let same_subject = match (
+            subject_name_id(&assertion),
+            subject_name_id(&authn_assertion),
+        ) {
+            (Some(name_id), Some(authn_name_id)) => {
+                name_id.value == authn_name_id.value && name_id.format == authn_name_id.format
+            }
+            _ => false,
+        };
+        if !same_subject {
+            return Err(Error::AssertionSubjectMismatch {
+                assertion_id: assertion.id,
+            });
+        }
+        authn_assertion
+            .attribute_statements
+            .extend(assertion.attribute_statements);
+    }
+    Ok(authn_assertion)
+}
+
 impl AuthnRequest {
     pub const DESTINATION_PLACEHOLDER: &'static str = "https://replace.me";
 
@@ -535,4 +1015,17 @@ impl AuthnRequest {
         }
         Ok(url)
     }
+
+    /// Like [`AuthnRequest::redirect`], with the `SigAlg` and `Signature` parameters of a
+    /// request signed by `signer`.
+    pub fn signed_redirect(
+        &self,
+        relay_state: Option<&str>,
+        signer: &dyn Signer,
+    ) -> Result<Url, Box<dyn std::error::Error>> {
+        let mut url = self.redirect(relay_state)?;
+        let query = signer::sign_redirect_query(url.query().unwrap_or_default(), signer)?;
+        url.set_query(Some(&query));
+        Ok(url)
+    }
 }
@@ -0,0 +1,527 @@
+//!
+//! Signing of SAML messages and metadata through a [`Signer`], so that private keys can stay in
+//! an HSM or a KMS
+//!
+use crate::c14n::{Algorithm, Canonicalization, EXCLUSIVE_C14N};
+use crate::crypto::XMLNS_SAML_ASSERTION;
+use crate::dom::{self, escape_attribute, Document, NodeId};
+use base64::{engine::general_purpose, Engine as _};
+use openssl::bn::{BigNum, BigNumRef};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkey::{Id, PKey, Private};
+use openssl::rsa::Padding;
+use openssl::sign::Verifier;
+use openssl::x509::X509;
+use snafu::Snafu;
+use url::form_urlencoded;
+
+const XMLNS_XML_DSIG: &str = "http://www.w3.org/2000/09/xmldsig#";
+const ENVELOPED_SIGNATURE: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("OpenSSL Error: {}", stack))]
+    OpenSSLError { stack: openssl::error::ErrorStack },
+
+    #[snafu(display("Failed to parse XML: {}", error))]
+    XmlDomError { error: dom::Error },
+
+    #[snafu(display("The element to sign has no ID attribute"))]
+    MissingId,
+
+    #[snafu(display("Keys of type {:?} cannot sign", key_type))]
+    UnsupportedKey { key_type: Id },
+
+    #[snafu(display("Signing failed: {}", message))]
+    SigningFailed { message: String },
+
+    #[snafu(display("The query has no {} parameter", name))]
+    MissingParameter { name: String },
+
+    #[snafu(display("The query has more than one {} parameter", name))]
+    DuplicateParameter { name: String },
+
+    #[snafu(display("Unsupported signature algorithm: {}", uri))]
+    UnsupportedAlgorithm { uri: String },
+
+    #[snafu(display("The signature does not verify with any of the certificates"))]
+    InvalidSignature,
+}
+
+impl From<openssl::error::ErrorStack> for Error {
+    fn from(error: openssl::error::ErrorStack) -> Self {
+        Error::OpenSSLError { stack: error }
+    }
+}
+
+impl From<dom::Error> for Error {
+    fn from(error: dom::Error) -> Self {
+        Error::XmlDomError { error }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SignatureAlgorithm {
+    RsaSha256,
+    RsaSha384,
+    RsaSha512,
+    EcdsaSha256,
+    EcdsaSha384,
+    EcdsaSha512,
+}
+
+impl SignatureAlgorithm {
+    pub fn from_uri(uri: &str) -> Option<Self> {
+        [
+            SignatureAlgorithm::RsaSha256,
+            SignatureAlgorithm::RsaSha384,
+            SignatureAlgorithm::RsaSha512,
+            SignatureAlgorithm::EcdsaSha256,
+            SignatureAlgorithm::EcdsaSha384,
+            SignatureAlgorithm::EcdsaSha512,
+        ]
+        .into_iter()
+        .find(|algorithm| algorithm.uri() == uri)
+    }
+
+    /// The XML DSig `SignatureMethod`, also used as `SigAlg` by the HTTP-Redirect binding.
+    pub fn uri(&self) -> &'static str {
+        match self {
+            SignatureAlgorithm::RsaSha256 => "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256",
+            SignatureAlgorithm::RsaSha384 => "http://www.w3.org/2001/04/xmldsig-more#rsa-sha384",
+            SignatureAlgorithm::RsaSha512 => "http://www.w3.org/2001/04/xmldsig-more#rsa-sha512",
+            SignatureAlgorithm::EcdsaSha256 => {
+                "http://www.w3.org/2001/04/xmldsig-more#ecdsa-sha256"
+            }
+            SignatureAlgorithm::EcdsaSha384 => {
+                "http://www.w3.org/2001/04/xmldsig-more#ecdsa-sha384"
+            }
+            SignatureAlgorithm::EcdsaSha512 => {
+                "http://www.w3.org/2001/04/xmldsig-more#ecdsa-sha512"
+            }
+        }
+    }
+
+    /// The XML DSig `DigestMethod` of the same hash function, used for references.
+    pub fn digest_uri(&self) -> &'static str {
+        match self {
+            SignatureAlgorithm::RsaSha256 | SignatureAlgorithm::EcdsaSha256 => {
+                "http://www.w3.org/2001/04/xmlenc#sha256"
+            }
+            SignatureAlgorithm::RsaSha384 | SignatureAlgorithm::EcdsaSha384 => {
+                "http://www.w3.org/2001/04/xmldsig-more#sha384"
+            }
+            SignatureAlgorithm::RsaSha512 | SignatureAlgorithm::EcdsaSha512 => {
+                "http://www.w3.org/2001/04/xmlenc#sha512"
+            }
+        }
+    }
+
+    pub fn message_digest(&self) -> MessageDigest {
+        match self {
+            SignatureAlgorithm::RsaSha256 | SignatureAlgorithm::EcdsaSha256 => {
+                MessageDigest::sha256()
+            }
+            SignatureAlgorithm::RsaSha384 | SignatureAlgorithm::EcdsaSha384 => {
+                MessageDigest::sha384()
+            }
+            SignatureAlgorithm::RsaSha512 | SignatureAlgorithm::EcdsaSha512 => {
+                MessageDigest::sha512()
+            }
+        }
+    }
+
+    pub fn is_ecdsa(&self) -> bool {
+        matches!(
+            self,
+            SignatureAlgorithm::EcdsaSha256
+                | SignatureAlgorithm::EcdsaSha384
+                | SignatureAlgorithm::EcdsaSha512
+        )
+    }
+
+    /// Wraps a digest in the DER `DigestInfo` that RSA PKCS#1 v1.5 signs, for signers which only
+    /// apply the padding, such as the `CKM_RSA_PKCS` mechanism of PKCS#11.
+    pub fn digest_info(&self, digest: &[u8]) -> Vec<u8> {
+        let prefix: &[u8] = match self.message_digest().size() {
+            48 => &[
+                0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+                0x02, 0x05, 0x00, 0x04, 0x30,
+            ],
+            64 => &[
+                0x30, 0x51, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+                0x03, 0x05, 0x00, 0x04, 0x40,
+            ],
+            _ => &[
+                0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+                0x01, 0x05, 0x00, 0x04, 0x20,
+            ],
+        };
+        [prefix, digest].concat()
+    }
+}
+
+/// Something which signs with a private key it does not need to reveal, such as an HSM or a KMS.
+pub trait Signer: Send + Sync {
+    fn algorithm(&self) -> SignatureAlgorithm;
+
+    /// The certificate of the key, embedded in the `KeyInfo` of XML signatures when present.
+    fn certificate(&self) -> Option<X509>;
+
+    /// Signs a digest made with the hash function of [`Signer::algorithm`]. RSA signatures are
+    /// PKCS#1 v1.5 ones, ECDSA signatures the concatenation of `r` and `s` as XML DSig wants.
+    fn sign_digest(&self, digest: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Hashes the data and signs the digest.
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let digest = hash(self.algorithm().message_digest(), data)?;
+        self.sign_digest(&digest)
+    }
+}
+
+/// A [`Signer`] holding an OpenSSL private key in memory.
+#[derive(Clone)]
+pub struct SoftwareSigner {
+    private_key: PKey<Private>,
+    certificate: Option<X509>,
+    algorithm: SignatureAlgorithm,
+}
+
+impl SoftwareSigner {
+    /// Signs with SHA-256, using RSA or ECDSA depending on the key.
+    pub fn new(private_key: PKey<Private>, certificate: Option<X509>) -> Result<Self, Error> {
+        let algorithm = match private_key.id() {
+            Id::RSA => SignatureAlgorithm::RsaSha256,
+            Id::EC => SignatureAlgorithm::EcdsaSha256,
+            key_type => return Err(Error::UnsupportedKey { key_type }),
+        };
+        Ok(SoftwareSigner {
+            private_key,
+            certificate,
+            algorithm,
+        })
+    }
+
+    pub fn from_rsa_der(private_key_der: &[u8], certificate: Option<X509>) -> Result<Self, Error> {
+        let rsa = openssl::rsa::Rsa::private_key_from_der(private_key_der)?;
+        Self::new(PKey::from_rsa(rsa)?, certificate)
+    }
+
+    pub fn with_algorithm(mut self, algorithm: SignatureAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    pub fn private_key(&self) -> &PKey<Private> {
+        &self.private_key
+    }
+}
+
+/// Left-pads a big-endian integer to the given length.
+fn pad_integer(number: &BigNumRef, length: usize) -> Vec<u8> {
+    let bytes = number.to_vec();
+    let mut padded = vec![0; length.saturating_sub(bytes.len())];
+    padded.extend(bytes);
+    padded
+}
+
+impl Signer for SoftwareSigner {
+    fn algorithm(&self) -> SignatureAlgorithm {
+        self.algorithm
+    }
+
+    fn certificate(&self) -> Option<X509> {
+        self.certificate.clone()
+    }
+
+    fn sign_digest(&self, digest: &[u8]) -> Result<Vec<u8>, Error> {
+        if self.algorithm.is_ecdsa() {
+            let key = self.private_key.ec_key()?;
+            let signature = EcdsaSig::sign(digest, &key)?;
+            let length = (key.group().degree() as usize + 7) / 8;
+            Ok([
+                pad_integer(signature.r(), length),
+                pad_integer(signature.s(), length),
+            ]
+            .concat())
+        } else {
+            let key = self.private_key.rsa()?;
+            let mut signature = vec![0; key.size() as usize];
+            let length = key.private_encrypt(
+                &self.algorithm.digest_info(digest),
+                &mut signature,
+                Padding::PKCS1,
+            )?;
+            signature.truncate(length);
+            Ok(signature)
+        }
+    }
+}
+
+/// Where the signature goes among the children of the signed element: in place of an existing
+/// `ds:Signature` template, otherwise after the `saml:Issuer` as SAML requires, otherwise first.
+fn signature_position(document: &mut Document, root: NodeId) -> (usize, Option<Document>) {
+    let position = |node: NodeId| {
+        document
+            .children(root)
+            .iter()
+            .position(|child| *child == node)
+            .unwrap_or_default()
+    };
+    if let Some(template) = document.first_child(root, "Signature", XMLNS_XML_DSIG) {
+        let index = position(template);
+        // keep the KeyInfo of the template for signers without a certificate
+        let key_info = document
+            .first_child(template, "KeyInfo", XMLNS_XML_DSIG)
+            .map(|key_info| {
+                // exclusive canonicalization declares the namespaces the subtree uses
+                Canonicalization::new(Algorithm::Exclusive).canonicalize_node(
+                    document,
+                    key_info,
+                    &[],
+                )
+            })
+            .and_then(|key_info| Document::parse(&key_info).ok());
+        document.detach(template);
+        return (index, key_info);
+    }
+    let index = document
+        .first_child(root, "Issuer", XMLNS_SAML_ASSERTION)
+        .map(|issuer| position(issuer) + 1)
+        .unwrap_or_default();
+    (index, None)
+}
+
+/// Signs the root element of `xml`, which needs an `ID` attribute, with an enveloped signature
+/// using Exclusive XML Canonicalization.
+pub fn sign_xml(xml: &str, signer: &dyn Signer) -> Result<String, Error> {
+    let mut document = Document::parse(xml)?;
+    let root = document
+        .root_element()
+        .ok_or(dom::Error::XmlMissingRootElement)?;
+    sign_element(&mut document, root, signer)?;
+    Ok(document.to_xml())
+}
+
+/// Signs an element of a document, which needs an `ID` attribute, with an enveloped signature
+/// using Exclusive XML Canonicalization, such as an assertion within a response.
+pub fn sign_element(
+    document: &mut Document,
+    root: NodeId,
+    signer: &dyn Signer,
+) -> Result<(), Error> {
+    let id = document
+        .element(root)
+        .and_then(|element| element.attribute("ID"))
+        .ok_or(Error::MissingId)?
+        .to_string();
+    let (index, template_key_info) = signature_position(document, root);
+
+    let algorithm = signer.algorithm();
+    let canonicalization = Canonicalization::new(Algorithm::Exclusive);
+    let digest = hash(
+        algorithm.message_digest(),
+        canonicalization
+            .canonicalize_node(document, root, &[])
+            .as_bytes(),
+    )?;
+
+    let signed_info = format!(
+        concat!(
+            "<ds:SignedInfo>",
+            "<ds:CanonicalizationMethod Algorithm=\"{c14n}\"/>",
+            "<ds:SignatureMethod Algorithm=\"{signature_method}\"/>",
+            "<ds:Reference URI=\"#{id}\">",
+            "<ds:Transforms>",
+            "<ds:Transform Algorithm=\"{enveloped}\"/>",
+            "<ds:Transform Algorithm=\"{c14n}\"/>",
+            "</ds:Transforms>",
+            "<ds:DigestMethod Algorithm=\"{digest_method}\"/>",
+            "<ds:DigestValue>{digest}</ds:DigestValue>",
+            "</ds:Reference>",
+            "</ds:SignedInfo>"
+        ),
+        c14n = EXCLUSIVE_C14N,
+        signature_method = algorithm.uri(),
+        id = escape_attribute(&id),
+        enveloped = ENVELOPED_SIGNATURE,
+        digest_method = algorithm.digest_uri(),
+        digest = general_purpose::STANDARD.encode(digest),
+    );
+    let unsigned = Document::parse(&format!(
+        "<ds:Signature xmlns:ds=\"{}\">{}</ds:Signature>",
+        XMLNS_XML_DSIG, signed_info
+    ))?;
+    let signed_info_node = unsigned
+        .root_element()
+        .and_then(|signature| unsigned.first_child(signature, "SignedInfo", XMLNS_XML_DSIG))
+        .ok_or(dom::Error::XmlMissingRootElement)?;
+    let signature_value = signer.sign(
+        canonicalization
+            .canonicalize_node(&unsigned, signed_info_node, &[])
+            .as_bytes(),
+    )?;
+
+    let key_info = match signer.certificate() {
+        Some(certificate) => format!(
+            "<ds:KeyInfo><ds:X509Data><ds:X509Certificate>{}</ds:X509Certificate></ds:X509Data></ds:KeyInfo>",
+            general_purpose::STANDARD.encode(certificate.to_der()?)
+        ),
+        None => String::new(),
+    };
+    let signature = Document::parse(&format!(
+        "<ds:Signature xmlns:ds=\"{}\">{}<ds:SignatureValue>{}</ds:SignatureValue>{}</ds:Signature>",
+        XMLNS_XML_DSIG,
+        signed_info,
+        general_purpose::STANDARD.encode(signature_value),
+        key_info
+    ))?;
+    let signature_root = signature
+        .root_element()
+        .ok_or(dom::Error::XmlMissingRootElement)?;
+    let inserted = document.insert(root, index, &signature, signature_root);
+    if let (true, Some(template_key_info)) = (key_info.is_empty(), template_key_info) {
+        if let Some(key_info_root) = template_key_info.root_element() {
+            let position = document.children(inserted).len();
+            document.insert(inserted, position, &template_key_info, key_info_root);
+        }
+    }
+    Ok(())
+}
+
+/// Signs a query string of the HTTP-Redirect binding, such as `SAMLRequest=...&RelayState=...`,
+/// by appending the `SigAlg` and `Signature` parameters.
+pub fn sign_redirect_query(query: &str, signer: &dyn Signer) -> Result<String, Error> {
+    let signed = form_urlencoded::Serializer::new(query.to_string())
+        .append_pair("SigAlg", signer.algorithm().uri())
+        .finish();
+    let signature = general_purpose::STANDARD.encode(signer.sign(signed.as_bytes())?);
+    Ok(form_urlencoded::Serializer::new(signed)
+        .append_pair("Signature", &signature)
+        .finish())
+}
+
+/// A parameter of a query string of the HTTP-Redirect binding.
+#[derive(Clone, Debug)]
+pub struct RedirectParameter<'a> {
+    /// The URL-decoded name.
+    pub name: String,
+    /// The value as received, still URL-encoded.
+    pub raw_value: &'a str,
+    /// The URL-decoded value.
+    pub value: String,
+}
+
+/// The SAML parameters of a query string of the HTTP-Redirect binding.
+///
+/// The query is split once, so that the signed string and the message are read from the same
+/// parameters. A parameter given twice, under any encoding of its name, is rejected.
+#[derive(Clone, Debug, Default)]
+pub struct RedirectQuery<'a> {
+    /// The `SAMLRequest` or `SAMLResponse` parameter.
+    pub message: Option<RedirectParameter<'a>>,
+    pub relay_state: Option<RedirectParameter<'a>>,
+    pub sig_alg: Option<RedirectParameter<'a>>,
+    pub signature: Option<RedirectParameter<'a>>,
+}
+
+impl<'a> RedirectQuery<'a> {
+    pub fn parse(query: &'a str) -> Result<Self, Error> {
+        let mut parsed = RedirectQuery::default();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let Some((name, value)) = form_urlencoded::parse(pair.as_bytes()).next() else {
+                continue;
+            };
+            let parameter = match name.as_ref() {
+                "SAMLRequest" | "SAMLResponse" => &mut parsed.message,
+                "RelayState" => &mut parsed.relay_state,
+                "SigAlg" => &mut parsed.sig_alg,
+                "Signature" => &mut parsed.signature,
+                _ => continue,
+            };
+            if parameter.is_some() {
+                return Err(Error::DuplicateParameter {
+                    name: name.into_owned(),
+                });
+            }
+            *parameter = Some(RedirectParameter {
+                name: name.into_owned(),
+                raw_value: pair.split_once('=').map_or("", |(_, value)| value),
+                value: value.into_owned(),
+            });
+        }
+        Ok(parsed)
+    }
+
+    /// Verifies the signature of the query, and returns the certificate it verifies with. The
+    /// signed string is rebuilt from the `SAMLRequest` or `SAMLResponse`, `RelayState` and `SigAlg`
+    /// values as they were received, still URL-encoded, since the SP may encode them differently
+    /// than [`sign_redirect_query`] does.
+    pub fn verify(&self, certificates: &[X509]) -> Result<X509, Error> {
+        let missing = |name: &str| Error::MissingParameter {
+            name: name.to_string(),
+        };
+        let message = self
+            .message
+            .as_ref()
+            .ok_or_else(|| missing("SAMLRequest"))?;
+        let sig_alg = self.sig_alg.as_ref().ok_or_else(|| missing("SigAlg"))?;
+        let signature = self
+            .signature
+            .as_ref()
+            .ok_or_else(|| missing("Signature"))?;
+
+        let mut signed = format!("{}={}", message.name, message.raw_value);
+        if let Some(relay_state) = &self.relay_state {
+            signed.push_str(&format!("&RelayState={}", relay_state.raw_value));
+        }
+        signed.push_str(&format!("&SigAlg={}", sig_alg.raw_value));
+
+        let algorithm = SignatureAlgorithm::from_uri(&sig_alg.value).ok_or_else(|| {
+            Error::UnsupportedAlgorithm {
+                uri: sig_alg.value.clone(),
+            }
+        })?;
+        let signature = general_purpose::STANDARD
+            .decode(signature.value.replace(char::is_whitespace, ""))
+            .map_err(|_| Error::InvalidSignature)?;
+        certificates
+            .iter()
+            .find(|certificate| verify(algorithm, certificate, signed.as_bytes(), &signature))
+            .cloned()
+            .ok_or(Error::InvalidSignature)
+    }
+}
+
+/// Verifies the signature of a query string of the HTTP-Redirect binding, and returns the
+/// certificate it verifies with.
+pub fn verify_redirect_query(query: &str, certificates: &[X509]) -> Result<X509, Error> {
+    RedirectQuery::parse(query)?.verify(certificates)
+}
+
+/// Checks a signature made by a [`Signer`], ECDSA ones being the concatenation of `r` and `s`.
+fn verify(
+    algorithm: SignatureAlgorithm,
+    certificate: &X509,
+    data: &[u8],
+    signature: &[u8],
+) -> bool {
+    let verified = || -> Result<bool, openssl::error::ErrorStack> {
+        let public_key = certificate.public_key()?;
+        let signature = if algorithm.is_ecdsa() {
+            if signature.is_empty() || signature.len() % 2 != 0 {
+                return Ok(false);
+            }
+            let (r, s) = signature.split_at(signature.len() / 2);
+            EcdsaSig::from_private_components(BigNum::from_slice(r)?, BigNum::from_slice(s)?)?
+                .to_der()?
+        } else {
+            signature.to_vec()
+        };
+        let mut verifier = Verifier::new(algorithm.message_digest(), &public_key)?;
+        verifier.update(data)?;
+        verifier.verify(&signature)
+    };
+    verified().unwrap_or(false)
+}
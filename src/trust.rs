@@ -0,0 +1,245 @@
+//!
+//! Validation of IdP signing certificates against a PKI, for federations that do not pin the
+//! certificates found in metadata
+//!
+use crate::clock::Clock;
+use chrono::Duration;
+use openssl::asn1::Asn1Time;
+use openssl::ssl::SslFiletype;
+use openssl::stack::Stack;
+use openssl::x509::store::{X509Lookup, X509StoreBuilder};
+use openssl::x509::verify::X509VerifyFlags;
+use openssl::x509::{X509StoreContext, X509};
+use snafu::Snafu;
+use std::path::PathBuf;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("OpenSSL error stack: {}", error))]
+    OpenSSLError { error: openssl::error::ErrorStack },
+
+    #[snafu(display("Failed to load CRL file '{}': {}", path, error))]
+    FailedToLoadCrl {
+        path: String,
+        error: openssl::error::ErrorStack,
+    },
+
+    #[snafu(display("Certificate '{}' is not trusted: {}", subject, reason))]
+    UntrustedCertificate { subject: String, reason: String },
+
+    #[snafu(display("Certificate '{}' is not valid before {}", subject, not_before))]
+    CertificateNotYetValid { subject: String, not_before: String },
+
+    #[snafu(display("Certificate '{}' expired at {}", subject, not_after))]
+    CertificateExpired { subject: String, not_after: String },
+
+    #[snafu(display("Certificate '{}' is not allowed to make digital signatures", subject))]
+    InvalidKeyUsage { subject: String },
+}
+
+impl From<openssl::error::ErrorStack> for Error {
+    fn from(error: openssl::error::ErrorStack) -> Self {
+        Error::OpenSSLError { error }
+    }
+}
+
+/// How the certificates used to verify the signatures of an IdP are trusted.
+#[derive(Clone, Debug, Default)]
+pub enum CertificateTrust {
+    /// Trust the signing certificates listed in the IdP metadata, whatever their issuer and
+    /// validity dates.
+    #[default]
+    Metadata,
+    /// Only trust the signing certificates listed in the IdP metadata which chain up to a
+    /// configured certificate authority.
+    Pki(PkiTrust),
+}
+
+/// A set of trusted certificate authorities, along with the revocation lists to check the signing
+/// certificates against.
+#[derive(Clone, Debug, Default)]
+pub struct PkiTrust {
+    pub ca_certificates: Vec<X509>,
+    /// PEM files holding certificate revocation lists. They are read on every validation, so that
+    /// refreshed lists are picked up. When at least one is configured, the issuer of the signing
+    /// certificate must have a CRL.
+    pub crl_files: Vec<PathBuf>,
+}
+
+impl PkiTrust {
+    pub fn new(ca_certificates: Vec<X509>) -> Self {
+        PkiTrust {
+            ca_certificates,
+            crl_files: vec![],
+        }
+    }
+
+    pub fn with_crl_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.crl_files.push(path.into());
+        self
+    }
+
+    /// Checks that the certificate chains up to one of the certificate authorities, possibly
+    /// through some of the `untrusted` intermediate certificates, that it is not revoked, and
+    /// that every certificate of the chain is valid at the time of the clock, give or take
+    /// `max_clock_skew`. When the certificate restricts the usage of its key, it must allow
+    /// digital signatures.
+    pub fn validate(
+        &self,
+        certificate: &X509,
+        untrusted: &[X509],
+        max_clock_skew: Duration,
+        clock: &dyn Clock,
+    ) -> Result<(), Error> {
+        check_key_usage(certificate)?;
+
+        let mut store = X509StoreBuilder::new()?;
+        for ca_certificate in &self.ca_certificates {
+            store.add_cert(ca_certificate.clone())?;
+        }
+        // validity dates are checked below, as OpenSSL does not allow for clock skew
+        let mut flags = X509VerifyFlags::NO_CHECK_TIME;
+        if !self.crl_files.is_empty() {
+            let lookup = store.add_lookup(X509Lookup::file())?;
+            for path in &self.crl_files {
+                lookup
+                    .load_crl_file(path, SslFiletype::PEM)
+                    .map_err(|error| Error::FailedToLoadCrl {
+                        path: path.display().to_string(),
+                        error,
+                    })?;
+            }
+            flags |= X509VerifyFlags::CRL_CHECK;
+        }
+        store.set_flags(flags)?;
+        let store = store.build();
+
+        let mut untrusted_chain = Stack::new()?;
+        for cert in untrusted {
+            untrusted_chain.push(cert.clone())?;
+        }
+
+        let mut context = X509StoreContext::new()?;
+        let chain = context.init(&store, certificate, &untrusted_chain, |context| {
+            Ok(if context.verify_cert()? {
+                Ok(context
+                    .chain()
+                    .map(|chain| chain.iter().map(|cert| cert.to_owned()).collect())
+                    .unwrap_or_else(Vec::new))
+            } else {
+                Err(context.error())
+            })
+        })?;
+        let chain = chain.map_err(|error| Error::UntrustedCertificate {
+            subject: subject(certificate),
+            reason: error.error_string().to_string(),
+        })?;
+
+        let now = clock.now().0;
+        let latest_start = Asn1Time::from_unix((now + max_clock_skew).timestamp() as _)?;
+        let earliest_end = Asn1Time::from_unix((now - max_clock_skew).timestamp() as _)?;
+        for cert in &chain {
+            if cert.not_before() > latest_start {
+                return Err(Error::CertificateNotYetValid {
+                    subject: subject(cert),
+                    not_before: cert.not_before().to_string(),
+                });
+            }
+            if cert.not_after() < earliest_end {
+                return Err(Error::CertificateExpired {
+                    subject: subject(cert),
+                    not_after: cert.not_after().to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_key_usage(certificate: &X509) -> Result<(), Error> {
+    // a certificate whose extensions cannot be read allows nothing
+    let allowed = certificate
+        .to_der()
+        .ok()
+        .and_then(|der| allows_digital_signature(&der))
+        .unwrap_or(false);
+    if !allowed {
+        return Err(Error::InvalidKeyUsage {
+            subject: subject(certificate),
+        });
+    }
+    Ok(())
+}
+
+/// The DER encoding of the keyUsage extension OID, 2.5.29.15.
+const KEY_USAGE_OID: &[u8] = &[0x55, 0x1d, 0x0f];
+
+/// Whether the keyUsage extension of a DER certificate, if it has one, has the digitalSignature
+/// bit set. The openssl crate does not expose the extension, so it is read here. Returns `None`
+/// when the certificate is malformed.
+fn allows_digital_signature(der: &[u8]) -> Option<bool> {
+    let (_, certificate, _) = der_element(der)?;
+    let (_, mut tbs_certificate, _) = der_element(certificate)?;
+    while !tbs_certificate.is_empty() {
+        let (tag, content, rest) = der_element(tbs_certificate)?;
+        tbs_certificate = rest;
+        // extensions are the explicitly tagged [3] field
+        if tag != 0xa3 {
+            continue;
+        }
+        let (_, mut extensions, _) = der_element(content)?;
+        while !extensions.is_empty() {
+            let (_, extension, rest) = der_element(extensions)?;
+            extensions = rest;
+            let (_, oid, fields) = der_element(extension)?;
+            if oid != KEY_USAGE_OID {
+                continue;
+            }
+            let (mut tag, mut value, rest) = der_element(fields)?;
+            // skip the critical flag
+            if tag == 0x01 {
+                (tag, value, _) = der_element(rest)?;
+            }
+            if tag != 0x04 {
+                return None;
+            }
+            // a BIT STRING, whose first byte is the number of unused bits, and whose first bit
+            // is digitalSignature
+            let (_, bits, _) = der_element(value)?;
+            return Some(bits.get(1).is_some_and(|byte| byte & 0x80 != 0));
+        }
+    }
+    Some(true)
+}
+
+/// Splits the first element of DER data into its tag, its content, and the data after it.
+fn der_element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&first, mut data) = data.split_first()?;
+    let length = if first < 0x80 {
+        first as usize
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > std::mem::size_of::<usize>() || data.len() < count {
+            return None;
+        }
+        let (length, rest) = data.split_at(count);
+        data = rest;
+        length
+            .iter()
+            .fold(0usize, |length, byte| (length << 8) | *byte as usize)
+    };
+    if data.len() < length {
+        return None;
+    }
+    let (content, rest) = data.split_at(length);
+    Some((tag, content, rest))
+}
+
+fn subject(cert: &X509) -> String {
+    cert.subject_name()
+        .entries()
+        .filter_map(|entry| entry.data().as_utf8().ok().map(|data| data.to_string()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
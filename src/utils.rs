@@ -3,22 +3,14 @@ use std::io::{Read, Write};
 use std::ops::Add;
 use yaserde::{YaDeserialize, YaSerialize};
 
-static mut NOW: Option<DateTime<Utc>> = None;
-
 #[derive(Clone, Debug, Eq, Hash, PartialEq, Ord, PartialOrd)]
 pub struct UtcDateTime(pub DateTime<Utc>);
 
 impl UtcDateTime {
+    /// The time of the system. Validation goes through a [`crate::clock::Clock`] instead, so that
+    /// it can be tested at a given time.
     pub fn now() -> Self {
-        match unsafe { NOW } {
-            Some(now) => Self(now),
-            None => Self(Utc::now()),
-        }
-    }
-
-    #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn set_now(now: DateTime<Utc>) {
-        NOW = Some(now);
+        Self(Utc::now())
     }
 }
 
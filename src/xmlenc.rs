@@ -0,0 +1,537 @@
+//!
+//! XML Encryption decryption of SAML encrypted assertions, built on the `dom` module and OpenSSL
+//!
+use crate::crypto::XMLNS_SAML_ASSERTION;
+use crate::dom::{self, Document, NodeId};
+use base64::{engine::general_purpose, Engine as _};
+use openssl::md::{Md, MdRef};
+use openssl::pkey::{PKey, Private};
+use openssl::pkey_ctx::PkeyCtx;
+use openssl::rsa::{Padding, Rsa};
+use openssl::symm::{decrypt_aead, Cipher, Crypter, Mode};
+use openssl::x509::X509;
+use snafu::Snafu;
+
+pub const XMLNS_XML_ENC: &str = "http://www.w3.org/2001/04/xmlenc#";
+pub const XMLNS_XML_ENC_11: &str = "http://www.w3.org/2009/xmlenc11#";
+const XMLNS_XML_DSIG: &str = "http://www.w3.org/2000/09/xmldsig#";
+
+pub const AES128_CBC: &str = "http://www.w3.org/2001/04/xmlenc#aes128-cbc";
+pub const AES192_CBC: &str = "http://www.w3.org/2001/04/xmlenc#aes192-cbc";
+pub const AES256_CBC: &str = "http://www.w3.org/2001/04/xmlenc#aes256-cbc";
+pub const TRIPLEDES_CBC: &str = "http://www.w3.org/2001/04/xmlenc#tripledes-cbc";
+pub const AES128_GCM: &str = "http://www.w3.org/2009/xmlenc11#aes128-gcm";
+pub const AES192_GCM: &str = "http://www.w3.org/2009/xmlenc11#aes192-gcm";
+pub const AES256_GCM: &str = "http://www.w3.org/2009/xmlenc11#aes256-gcm";
+
+pub const RSA_OAEP_MGF1P: &str = "http://www.w3.org/2001/04/xmlenc#rsa-oaep-mgf1p";
+pub const RSA_OAEP: &str = "http://www.w3.org/2009/xmlenc11#rsa-oaep";
+
+pub const DIGEST_SHA1: &str = "http://www.w3.org/2000/09/xmldsig#sha1";
+pub const DIGEST_SHA224: &str = "http://www.w3.org/2001/04/xmldsig-more#sha224";
+pub const DIGEST_SHA256: &str = "http://www.w3.org/2001/04/xmlenc#sha256";
+pub const DIGEST_SHA384: &str = "http://www.w3.org/2001/04/xmldsig-more#sha384";
+pub const DIGEST_SHA512: &str = "http://www.w3.org/2001/04/xmlenc#sha512";
+
+pub const MGF1_SHA1: &str = "http://www.w3.org/2009/xmlenc11#mgf1sha1";
+pub const MGF1_SHA224: &str = "http://www.w3.org/2009/xmlenc11#mgf1sha224";
+pub const MGF1_SHA256: &str = "http://www.w3.org/2009/xmlenc11#mgf1sha256";
+pub const MGF1_SHA384: &str = "http://www.w3.org/2009/xmlenc11#mgf1sha384";
+pub const MGF1_SHA512: &str = "http://www.w3.org/2009/xmlenc11#mgf1sha512";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("xml Error: {}", error))]
+    XmlDomError { error: dom::Error },
+
+    #[snafu(display("base64 decoding Error: {}", error))]
+    Base64Error { error: base64::DecodeError },
+
+    #[snafu(display("missing {} in encrypted data", name))]
+    MissingEncryptionElement { name: String },
+
+    #[snafu(display("unsupported algorithm: {}", algorithm))]
+    UnsupportedAlgorithm { algorithm: String },
+
+    #[snafu(display("none of the private keys can decrypt the encrypted key"))]
+    NoMatchingKey,
+
+    #[snafu(display("failed to unwrap the encrypted key: {}", message))]
+    KeyUnwrapFailed { message: String },
+
+    #[snafu(display("failed to decrypt the encrypted data"))]
+    DecryptionFailed,
+
+    #[snafu(display("the decrypted data is not a single saml:Assertion"))]
+    InvalidDecryptedAssertion,
+}
+
+impl From<dom::Error> for Error {
+    fn from(error: dom::Error) -> Self {
+        Error::XmlDomError { error }
+    }
+}
+
+impl From<base64::DecodeError> for Error {
+    fn from(error: base64::DecodeError) -> Self {
+        Error::Base64Error { error }
+    }
+}
+
+/// An `xenc:EncryptedKey`, along with what its `ds:KeyInfo` tells about the key it was encrypted
+/// for.
+#[derive(Clone)]
+pub struct EncryptedKey {
+    /// The content of a `ds:KeyName`.
+    pub key_name: Option<String>,
+    /// The `ds:X509Certificate` of the key.
+    pub certificate: Option<X509>,
+    pub oaep: OaepParameters,
+    /// The decoded `xenc:CipherValue`.
+    pub cipher_data: Vec<u8>,
+}
+
+/// The RSA-OAEP parameters of an `xenc:EncryptedKey`.
+#[derive(Clone)]
+pub struct OaepParameters {
+    pub digest: &'static MdRef,
+    pub mgf1_digest: &'static MdRef,
+    pub label: Option<Vec<u8>>,
+}
+
+/// Unwraps the symmetric keys of encrypted assertions with RSA private keys, which may stay in an
+/// HSM.
+pub trait DecryptionKeyProvider: Send + Sync {
+    /// Decrypts the `cipher_data` of an encrypted key with the private key it names by certificate
+    /// or key name, or with any private key when it names none. Returns
+    /// [`Error::NoMatchingKey`] when there is no such key.
+    fn unwrap_key(&self, encrypted_key: &EncryptedKey) -> Result<Vec<u8>, Error>;
+}
+
+#[derive(Clone)]
+struct SoftwareKey {
+    key: Rsa<Private>,
+    name: Option<String>,
+}
+
+/// A [`DecryptionKeyProvider`] holding OpenSSL private keys in memory. An encrypted key naming a
+/// certificate is only decrypted by the private key of that certificate. One naming a key is
+/// tried with the key of that name first, then with the unnamed keys, as the name is only a hint;
+/// otherwise every key is tried in turn.
+#[derive(Clone, Default)]
+pub struct SoftwareKeyProvider {
+    keys: Vec<SoftwareKey>,
+}
+
+impl SoftwareKeyProvider {
+    pub fn new(keys: Vec<Rsa<Private>>) -> Self {
+        SoftwareKeyProvider {
+            keys: keys
+                .into_iter()
+                .map(|key| SoftwareKey { key, name: None })
+                .collect(),
+        }
+    }
+
+    pub fn with_key(mut self, key: Rsa<Private>) -> Self {
+        self.keys.push(SoftwareKey { key, name: None });
+        self
+    }
+
+    /// Adds a key that encrypted keys can refer to with a `ds:KeyName`.
+    pub fn with_named_key<S: Into<String>>(mut self, name: S, key: Rsa<Private>) -> Self {
+        self.keys.push(SoftwareKey {
+            key,
+            name: Some(name.into()),
+        });
+        self
+    }
+
+    fn candidates(&self, encrypted_key: &EncryptedKey) -> Result<Vec<&Rsa<Private>>, Error> {
+        let certificate_key = encrypted_key
+            .certificate
+            .as_ref()
+            .map(|certificate| certificate.public_key()?.public_key_to_der())
+            .transpose()
+            .map_err(|_| Error::NoMatchingKey)?;
+        let mut candidates: Vec<_> = self
+            .keys
+            .iter()
+            .filter(
+                |software_key| match (&encrypted_key.key_name, &software_key.name) {
+                    (Some(wanted), Some(name)) => wanted == name,
+                    _ => true,
+                },
+            )
+            .filter(|software_key| {
+                certificate_key.as_ref().map_or(true, |certificate_key| {
+                    software_key.key.public_key_to_der().ok().as_ref() == Some(certificate_key)
+                })
+            })
+            .collect();
+        // the keys of the name come first, the sort being stable
+        candidates.sort_by_key(|software_key| {
+            encrypted_key.key_name.is_none() || software_key.name != encrypted_key.key_name
+        });
+        Ok(candidates
+            .into_iter()
+            .map(|software_key| &software_key.key)
+            .collect())
+    }
+}
+
+impl DecryptionKeyProvider for SoftwareKeyProvider {
+    fn unwrap_key(&self, encrypted_key: &EncryptedKey) -> Result<Vec<u8>, Error> {
+        self.candidates(encrypted_key)?
+            .into_iter()
+            .find_map(|key| {
+                rsa_oaep_decrypt(key, &encrypted_key.oaep, &encrypted_key.cipher_data).ok()
+            })
+            .ok_or(Error::NoMatchingKey)
+    }
+}
+
+/// Replaces the `saml:EncryptedAssertion` children of the document element, such as a
+/// `samlp:Response`, with the assertions they hold. The encrypted keys are looked for in the
+/// `ds:KeyInfo` of the `xenc:EncryptedData` and next to it, and the first one the provider unwraps
+/// is used. Returns `None` when there is no encrypted assertion.
+pub fn decrypt_assertions(
+    xml: &str,
+    provider: &dyn DecryptionKeyProvider,
+) -> Result<Option<String>, Error> {
+    let mut document = Document::parse(xml)?;
+    let root = document
+        .root_element()
+        .ok_or(dom::Error::XmlMissingRootElement)?;
+    let encrypted_assertions: Vec<_> = document
+        .child_elements(root)
+        .filter(|node| {
+            document
+                .element(*node)
+                .map(|element| element.is("EncryptedAssertion", XMLNS_SAML_ASSERTION))
+                .unwrap_or(false)
+        })
+        .collect();
+    if encrypted_assertions.is_empty() {
+        return Ok(None);
+    }
+
+    for encrypted_assertion in encrypted_assertions {
+        let (decrypted, assertion) =
+            decrypt_encrypted_assertion(&document, encrypted_assertion, provider)?;
+        document.replace(encrypted_assertion, &decrypted, assertion);
+    }
+    Ok(Some(document.to_xml()))
+}
+
+fn decrypt_encrypted_assertion(
+    document: &Document,
+    encrypted_assertion: NodeId,
+    provider: &dyn DecryptionKeyProvider,
+) -> Result<(Document, NodeId), Error> {
+    let encrypted_data = required_child(document, encrypted_assertion, "EncryptedData")?;
+    let algorithm = algorithm_attribute(
+        document,
+        required_child(document, encrypted_data, "EncryptionMethod")?,
+    )?;
+
+    let mut encrypted_keys: Vec<_> = document
+        .first_child(encrypted_data, "KeyInfo", XMLNS_XML_DSIG)
+        .map(|key_info| children_named(document, key_info, "EncryptedKey"))
+        .unwrap_or_default();
+    encrypted_keys.extend(children_named(
+        document,
+        encrypted_assertion,
+        "EncryptedKey",
+    ));
+    if encrypted_keys.is_empty() {
+        return Err(Error::MissingEncryptionElement {
+            name: "EncryptedKey".to_string(),
+        });
+    }
+
+    let mut symmetric_key = None;
+    for encrypted_key in encrypted_keys {
+        let encrypted_key = parse_encrypted_key(document, encrypted_key)?;
+        match provider.unwrap_key(&encrypted_key) {
+            Ok(decrypted) => {
+                symmetric_key = Some(decrypted);
+                break;
+            }
+            Err(Error::NoMatchingKey) => continue,
+            Err(error) => return Err(error),
+        }
+    }
+    let symmetric_key = symmetric_key.ok_or(Error::NoMatchingKey)?;
+
+    let plaintext = decrypt_data(
+        algorithm,
+        &symmetric_key,
+        &cipher_value(document, encrypted_data)?,
+    )?;
+    // CBC does not authenticate the ciphertext, so telling apart why its plaintext is not an
+    // assertion would let an attacker altering the ciphertext learn about the plaintext
+    parse_in_context(document, encrypted_assertion, &plaintext).map_err(|error| {
+        if is_authenticated(algorithm) {
+            error
+        } else {
+            Error::DecryptionFailed
+        }
+    })
+}
+
+fn parse_encrypted_key(document: &Document, node: NodeId) -> Result<EncryptedKey, Error> {
+    let oaep = oaep_parameters(
+        document,
+        required_child(document, node, "EncryptionMethod")?,
+    )?;
+    let key_info = document.first_child(node, "KeyInfo", XMLNS_XML_DSIG);
+    let key_name = key_info
+        .and_then(|key_info| document.first_child(key_info, "KeyName", XMLNS_XML_DSIG))
+        .map(|key_name| document.text(key_name).trim().to_string());
+    let certificate = key_info
+        .and_then(|key_info| document.first_child(key_info, "X509Data", XMLNS_XML_DSIG))
+        .and_then(|x509_data| document.first_child(x509_data, "X509Certificate", XMLNS_XML_DSIG))
+        .map(|certificate| {
+            let der = decode_base64(&document.text(certificate))?;
+            X509::from_der(&der).map_err(|_| Error::MissingEncryptionElement {
+                name: "X509Certificate".to_string(),
+            })
+        })
+        .transpose()?;
+    Ok(EncryptedKey {
+        key_name,
+        certificate,
+        oaep,
+        cipher_data: cipher_value(document, node)?,
+    })
+}
+
+/// Reads the key transport algorithm of an `xenc:EncryptedKey`. Only RSA-OAEP is supported, as
+/// RSA PKCS#1 v1.5 key transport is open to padding oracle attacks.
+fn oaep_parameters(document: &Document, method: NodeId) -> Result<OaepParameters, Error> {
+    let algorithm = algorithm_attribute(document, method)?;
+    let digest = document
+        .first_child(method, "DigestMethod", XMLNS_XML_DSIG)
+        .map(|node| algorithm_attribute(document, node).and_then(digest_method))
+        .transpose()?
+        .unwrap_or_else(Md::sha1);
+    let mgf1_digest = match algorithm {
+        RSA_OAEP_MGF1P => Md::sha1(),
+        RSA_OAEP => document
+            .first_child(method, "MGF", XMLNS_XML_ENC_11)
+            .map(|node| algorithm_attribute(document, node).and_then(mgf_method))
+            .transpose()?
+            .unwrap_or_else(Md::sha1),
+        _ => {
+            return Err(Error::UnsupportedAlgorithm {
+                algorithm: algorithm.to_string(),
+            })
+        }
+    };
+    let label = document
+        .first_child(method, "OAEPparams", XMLNS_XML_ENC)
+        .map(|node| decode_base64(&document.text(node)))
+        .transpose()?;
+    Ok(OaepParameters {
+        digest,
+        mgf1_digest,
+        label,
+    })
+}
+
+fn rsa_oaep_decrypt(
+    key: &Rsa<Private>,
+    oaep: &OaepParameters,
+    data: &[u8],
+) -> Result<Vec<u8>, openssl::error::ErrorStack> {
+    let key = PKey::from_rsa(key.clone())?;
+    let mut context = PkeyCtx::new(&key)?;
+    context.decrypt_init()?;
+    context.set_rsa_padding(Padding::PKCS1_OAEP)?;
+    context.set_rsa_oaep_md(oaep.digest)?;
+    context.set_rsa_mgf1_md(oaep.mgf1_digest)?;
+    if let Some(label) = &oaep.label {
+        context.set_rsa_oaep_label(label)?;
+    }
+    let mut decrypted = vec![];
+    context.decrypt_to_vec(data, &mut decrypted)?;
+    Ok(decrypted)
+}
+
+/// Decrypts the content of an `xenc:CipherValue`, which starts with the initialization vector.
+/// Every failure is reported as [`Error::DecryptionFailed`], so as not to act as a padding oracle.
+fn decrypt_data(algorithm: &str, key: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+    let (cipher, authenticated) = match algorithm {
+        AES128_CBC => (Cipher::aes_128_cbc(), false),
+        AES192_CBC => (Cipher::aes_192_cbc(), false),
+        AES256_CBC => (Cipher::aes_256_cbc(), false),
+        TRIPLEDES_CBC => (Cipher::des_ede3_cbc(), false),
+        AES128_GCM => (Cipher::aes_128_gcm(), true),
+        AES192_GCM => (Cipher::aes_192_gcm(), true),
+        AES256_GCM => (Cipher::aes_256_gcm(), true),
+        _ => {
+            return Err(Error::UnsupportedAlgorithm {
+                algorithm: algorithm.to_string(),
+            })
+        }
+    };
+    if key.len() != cipher.key_len() {
+        return Err(Error::DecryptionFailed);
+    }
+
+    if authenticated {
+        // a 96 bits IV, the ciphertext, then a 128 bits authentication tag
+        if data.len() < 12 + 16 {
+            return Err(Error::DecryptionFailed);
+        }
+        let (iv, rest) = data.split_at(12);
+        let (ciphertext, tag) = rest.split_at(rest.len() - 16);
+        return decrypt_aead(cipher, key, Some(iv), &[], ciphertext, tag)
+            .map_err(|_| Error::DecryptionFailed);
+    }
+
+    let block_size = cipher.block_size();
+    let iv_len = cipher.iv_len().unwrap_or(block_size);
+    if data.len() < iv_len + block_size || (data.len() - iv_len) % block_size != 0 {
+        return Err(Error::DecryptionFailed);
+    }
+    let (iv, ciphertext) = data.split_at(iv_len);
+    let mut decrypted = vec![0; ciphertext.len() + block_size];
+    let length = Crypter::new(cipher, Mode::Decrypt, key, Some(iv))
+        .and_then(|mut crypter| {
+            // XML Encryption padding only requires the last byte to hold the padding length
+            crypter.pad(false);
+            let length = crypter.update(ciphertext, &mut decrypted)?;
+            Ok(length + crypter.finalize(&mut decrypted[length..])?)
+        })
+        .map_err(|_| Error::DecryptionFailed)?;
+    decrypted.truncate(length);
+    let padding = decrypted.last().copied().unwrap_or_default() as usize;
+    if padding == 0 || padding > block_size || padding > decrypted.len() {
+        return Err(Error::DecryptionFailed);
+    }
+    decrypted.truncate(decrypted.len() - padding);
+    Ok(decrypted)
+}
+
+/// Whether the content encryption algorithm authenticates the ciphertext.
+fn is_authenticated(algorithm: &str) -> bool {
+    matches!(algorithm, AES128_GCM | AES192_GCM | AES256_GCM)
+}
+
+/// Parses decrypted element content in the context of the element it replaces, so that it may
+/// use the namespace prefixes declared above it, and returns the assertion it holds.
+fn parse_in_context(
+    document: &Document,
+    node: NodeId,
+    plaintext: &[u8],
+) -> Result<(Document, NodeId), Error> {
+    let plaintext = std::str::from_utf8(plaintext).map_err(|_| Error::InvalidDecryptedAssertion)?;
+    let plaintext = match plaintext.strip_prefix("<?xml") {
+        Some(rest) => rest
+            .split_once("?>")
+            .map(|(_, content)| content)
+            .ok_or(Error::InvalidDecryptedAssertion)?,
+        None => plaintext,
+    };
+
+    let mut wrapper = String::from("<DecryptedContent");
+    for (prefix, uri) in document.in_scope_namespaces(node) {
+        let value = dom::escape_attribute(&uri);
+        if prefix.is_empty() {
+            wrapper.push_str(&format!(" xmlns=\"{}\"", value));
+        } else {
+            wrapper.push_str(&format!(" xmlns:{}=\"{}\"", prefix, value));
+        }
+    }
+    wrapper.push('>');
+    wrapper.push_str(plaintext);
+    wrapper.push_str("</DecryptedContent>");
+
+    let decrypted = Document::parse(&wrapper)?;
+    let root = decrypted
+        .root_element()
+        .ok_or(dom::Error::XmlMissingRootElement)?;
+    let elements: Vec<_> = decrypted.child_elements(root).collect();
+    match elements[..] {
+        [assertion]
+            if decrypted
+                .element(assertion)
+                .map(|element| element.is("Assertion", XMLNS_SAML_ASSERTION))
+                .unwrap_or(false) =>
+        {
+            Ok((decrypted, assertion))
+        }
+        _ => Err(Error::InvalidDecryptedAssertion),
+    }
+}
+
+fn digest_method(algorithm: &str) -> Result<&'static MdRef, Error> {
+    match algorithm {
+        DIGEST_SHA1 => Ok(Md::sha1()),
+        DIGEST_SHA224 => Ok(Md::sha224()),
+        DIGEST_SHA256 => Ok(Md::sha256()),
+        DIGEST_SHA384 => Ok(Md::sha384()),
+        DIGEST_SHA512 => Ok(Md::sha512()),
+        _ => Err(Error::UnsupportedAlgorithm {
+            algorithm: algorithm.to_string(),
+        }),
+    }
+}
+
+fn mgf_method(algorithm: &str) -> Result<&'static MdRef, Error> {
+    match algorithm {
+        MGF1_SHA1 => Ok(Md::sha1()),
+        MGF1_SHA224 => Ok(Md::sha224()),
+        MGF1_SHA256 => Ok(Md::sha256()),
+        MGF1_SHA384 => Ok(Md::sha384()),
+        MGF1_SHA512 => Ok(Md::sha512()),
+        _ => Err(Error::UnsupportedAlgorithm {
+            algorithm: algorithm.to_string(),
+        }),
+    }
+}
+
+/// Decodes base64 element content, which may be wrapped over several lines.
+fn decode_base64(text: &str) -> Result<Vec<u8>, Error> {
+    let stripped = text
+        .bytes()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect::<Vec<u8>>();
+    Ok(general_purpose::STANDARD.decode(stripped)?)
+}
+
+fn cipher_value(document: &Document, node: NodeId) -> Result<Vec<u8>, Error> {
+    let cipher_data = required_child(document, node, "CipherData")?;
+    decode_base64(&document.text(required_child(document, cipher_data, "CipherValue")?))
+}
+
+fn children_named(document: &Document, node: NodeId, name: &str) -> Vec<NodeId> {
+    document
+        .child_elements(node)
+        .filter(|child| {
+            document
+                .element(*child)
+                .map(|element| element.is(name, XMLNS_XML_ENC))
+                .unwrap_or(false)
+        })
+        .collect()
+}
+
+fn required_child(document: &Document, node: NodeId, name: &str) -> Result<NodeId, Error> {
+    document
+        .first_child(node, name, XMLNS_XML_ENC)
+        .ok_or_else(|| Error::MissingEncryptionElement {
+            name: name.to_string(),
+        })
+}
+
+fn algorithm_attribute(document: &Document, node: NodeId) -> Result<&str, Error> {
+    document
+        .element(node)
+        .and_then(|element| element.attribute("Algorithm"))
+        .ok_or_else(|| Error::MissingEncryptionElement {
+            name: "Algorithm".to_string(),
+        })
+}
@@ -0,0 +1,220 @@
+use chrono::{TimeZone, Utc};
+use samael::attribute::{
+    self, ScopedValue, EDU_PERSON_ENTITLEMENT_URI, EDU_PERSON_SCOPED_AFFILIATION_URI, MAIL_URI,
+};
+use samael::metadata::{EntityDescriptor, Scope};
+use samael::schema::{Assertion, AttributeError, FromAssertion, NameId, Response};
+
+fn assertion() -> Assertion {
+    let xml = r#"<samlp:Response xmlns:samlp="urn:oasis:names:tc:SAML:2.0:protocol" xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion" xmlns:xs="http://www.w3.org/2001/XMLSchema" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" ID="_response" Version="2.0" IssueInstant="2024-01-01T00:00:00Z">
+  <samlp:Status><samlp:StatusCode Value="urn:oasis:names:tc:SAML:2.0:status:Success"/></samlp:Status>
+  <saml:Assertion ID="_assertion" Version="2.0" IssueInstant="2024-01-01T00:00:00Z">
+    <saml:Issuer>https://samltest.id/saml/idp</saml:Issuer>
+    <saml:AttributeStatement>
+      <saml:Attribute Name="urn:oid:0.9.2342.19200300.100.1.3" FriendlyName="mail" NameFormat="urn:oasis:names:tc:SAML:2.0:attrname-format:uri">
+        <saml:AttributeValue xsi:type="xs:string">jdoe@samltest.id</saml:AttributeValue>
+      </saml:Attribute>
+      <saml:Attribute Name="displayName" NameFormat="urn:oasis:names:tc:SAML:2.0:attrname-format:basic">
+        <saml:AttributeValue>John Doe</saml:AttributeValue>
+      </saml:Attribute>
+      <saml:Attribute Name="urn:oid:1.3.6.1.4.1.5923.1.1.1.7" FriendlyName="eduPersonEntitlement">
+        <saml:AttributeValue>urn:example:staff</saml:AttributeValue>
+        <saml:AttributeValue>urn:example:library</saml:AttributeValue>
+      </saml:Attribute>
+      <saml:Attribute Name="urn:oid:1.3.6.1.4.1.5923.1.1.1.9" FriendlyName="eduPersonScopedAffiliation">
+        <saml:AttributeValue>member@samltest.id</saml:AttributeValue>
+        <saml:AttributeValue>staff@samltest.id</saml:AttributeValue>
+      </saml:Attribute>
+      <saml:Attribute Name="urn:example:forged" FriendlyName="forged">
+        <saml:AttributeValue>admin@example.edu</saml:AttributeValue>
+      </saml:Attribute>
+      <saml:Attribute Name="urn:example:verified">
+        <saml:AttributeValue xsi:type="xs:string">1</saml:AttributeValue>
+      </saml:Attribute>
+      <saml:Attribute Name="urn:example:quota">
+        <saml:AttributeValue xsi:type="xs:integer">2048</saml:AttributeValue>
+      </saml:Attribute>
+      <saml:Attribute Name="urn:example:expires">
+        <saml:AttributeValue xsi:type="xs:dateTime">2030-06-01T00:00:00Z</saml:AttributeValue>
+      </saml:Attribute>
+      <saml:Attribute Name="urn:oid:1.3.6.1.4.1.5923.1.1.1.10" FriendlyName="eduPersonTargetedID">
+        <saml:AttributeValue><saml:NameID Format="urn:oasis:names:tc:SAML:2.0:nameid-format:persistent">c2f1a5e0</saml:NameID></saml:AttributeValue>
+      </saml:Attribute>
+    </saml:AttributeStatement>
+  </saml:Assertion>
+</samlp:Response>"#;
+    let response: Response = xml.parse().unwrap();
+    response.assertions.into_iter().next().unwrap()
+}
+
+fn idp_metadata() -> EntityDescriptor {
+    include_str!("../test_vectors/samltest_id_metadata.xml")
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn test_lookup_by_name_friendly_name_and_constant() {
+    let assertion = assertion();
+    assert_eq!(
+        assertion.attribute(MAIL_URI).string().unwrap(),
+        "jdoe@samltest.id"
+    );
+    assert_eq!(
+        assertion.attribute("mail").string().unwrap(),
+        "jdoe@samltest.id"
+    );
+    assert_eq!(
+        assertion.attribute("displayName").string().unwrap(),
+        "John Doe"
+    );
+    assert_eq!(
+        assertion
+            .attribute("eduPersonEntitlement")
+            .strings()
+            .unwrap(),
+        vec!["urn:example:staff", "urn:example:library"]
+    );
+    assert!(assertion.attribute("givenName").is_empty());
+    assert!(matches!(
+        assertion.attribute("givenName").string(),
+        Err(AttributeError::NotFound { .. })
+    ));
+    assert!(matches!(
+        assertion.attribute(EDU_PERSON_ENTITLEMENT_URI).string(),
+        Err(AttributeError::NotUnique { count: 2, .. })
+    ));
+}
+
+#[test]
+fn test_parse_values() {
+    let assertion = assertion();
+    assert!(assertion
+        .attribute("urn:example:verified")
+        .boolean()
+        .unwrap());
+    assert_eq!(
+        assertion.attribute("urn:example:quota").integer().unwrap(),
+        2048
+    );
+    assert_eq!(
+        assertion
+            .attribute("urn:example:expires")
+            .date_time()
+            .unwrap(),
+        Utc.with_ymd_and_hms(2030, 6, 1, 0, 0, 0).unwrap()
+    );
+    assert_eq!(
+        assertion
+            .attribute("eduPersonTargetedID")
+            .single::<NameId>()
+            .unwrap()
+            .value,
+        "c2f1a5e0"
+    );
+    assert!(matches!(
+        assertion.attribute("displayName").boolean(),
+        Err(AttributeError::InvalidValue {
+            error: attribute::Error::InvalidValue { .. },
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_scoped_values() {
+    let assertion = assertion();
+    let metadata = idp_metadata();
+    let scopes = metadata.idp_scopes();
+
+    let affiliations = assertion
+        .attribute("eduPersonScopedAffiliation")
+        .scoped(&scopes)
+        .unwrap();
+    assert_eq!(
+        affiliations,
+        vec![
+            ScopedValue {
+                value: "member".to_string(),
+                scope: "samltest.id".to_string(),
+            },
+            ScopedValue {
+                value: "staff".to_string(),
+                scope: "samltest.id".to_string(),
+            },
+        ]
+    );
+
+    // samltest.id is not authoritative for example.edu
+    assert!(matches!(
+        assertion.attribute("forged").scoped(&scopes),
+        Err(AttributeError::InvalidValue {
+            error: attribute::Error::ScopeNotAllowed { .. },
+            ..
+        })
+    ));
+    assert!(matches!(
+        assertion.attribute("displayName").scoped(&scopes),
+        Err(AttributeError::InvalidValue {
+            error: attribute::Error::NotScoped { .. },
+            ..
+        })
+    ));
+
+    let regexp = Scope {
+        regexp: Some(true),
+        value: r"([a-z]+\.)?example\.edu".to_string(),
+    };
+    assert!(regexp.matches("example.edu"));
+    assert!(regexp.matches("cs.example.edu"));
+    assert!(!regexp.matches("example.edu.evil.com"));
+    // scopes are DNS domains, whose case does not matter
+    assert!(regexp.matches("CS.Example.EDU"));
+    assert!(scopes[0].matches("SAMLtest.ID"));
+    assert_eq!(
+        assertion.attribute("forged").scoped(&[&regexp]).unwrap()[0].value,
+        "admin"
+    );
+}
+
+samael::from_assertion! {
+    #[derive(Debug)]
+    struct User {
+        #[attribute = MAIL_URI]
+        mail: String,
+        #[attribute = "displayName"]
+        display_name: Option<String>,
+        #[attribute = "givenName"]
+        given_name: Option<String>,
+        #[attribute = EDU_PERSON_ENTITLEMENT_URI]
+        entitlements: Vec<String>,
+        #[attribute = EDU_PERSON_SCOPED_AFFILIATION_URI]
+        affiliations: Vec<ScopedValue>,
+        #[attribute = "urn:example:quota"]
+        quota: i64,
+    }
+}
+
+samael::from_assertion! {
+    struct Employee {
+        #[attribute = "employeeNumber"]
+        employee_number: String,
+    }
+}
+
+#[test]
+fn test_map_into_struct() {
+    let assertion = assertion();
+    let user = User::from_assertion(&assertion).unwrap();
+    assert_eq!(user.mail, "jdoe@samltest.id");
+    assert_eq!(user.display_name.as_deref(), Some("John Doe"));
+    assert_eq!(user.given_name, None);
+    assert_eq!(user.entitlements.len(), 2);
+    assert_eq!(user.affiliations[1].value, "staff");
+    assert_eq!(user.quota, 2048);
+
+    assert!(matches!(
+        Employee::from_assertion(&assertion),
+        Err(AttributeError::NotFound { .. })
+    ));
+}
@@ -0,0 +1,145 @@
+use samael::attribute_mapping::{
+    AttributeMapper, AttributeProfile, DISPLAY_NAME, GIVEN_NAME, GROUPS, MAIL, PRINCIPAL_NAME,
+    SURNAME,
+};
+use samael::schema::{Assertion, Response};
+
+fn assertion(attributes: &str) -> Assertion {
+    let xml = format!(
+        r#"<samlp:Response xmlns:samlp="urn:oasis:names:tc:SAML:2.0:protocol" xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion" ID="_response" Version="2.0" IssueInstant="2024-01-01T00:00:00Z">
+  <samlp:Status><samlp:StatusCode Value="urn:oasis:names:tc:SAML:2.0:status:Success"/></samlp:Status>
+  <saml:Assertion ID="_assertion" Version="2.0" IssueInstant="2024-01-01T00:00:00Z">
+    <saml:Issuer>https://idp.example.com</saml:Issuer>
+    <saml:AttributeStatement>{}</saml:AttributeStatement>
+  </saml:Assertion>
+</samlp:Response>"#,
+        attributes
+    );
+    let response: Response = xml.parse().unwrap();
+    response.assertions.into_iter().next().unwrap()
+}
+
+fn attribute(name: &str, name_format: Option<&str>, values: &[&str]) -> String {
+    let values: String = values
+        .iter()
+        .map(|value| format!("<saml:AttributeValue>{}</saml:AttributeValue>", value))
+        .collect();
+    match name_format {
+        Some(format) => format!(
+            r#"<saml:Attribute Name="{}" NameFormat="{}">{}</saml:Attribute>"#,
+            name, format, values
+        ),
+        None => format!(
+            r#"<saml:Attribute Name="{}">{}</saml:Attribute>"#,
+            name, values
+        ),
+    }
+}
+
+const URI: Option<&str> = Some("urn:oasis:names:tc:SAML:2.0:attrname-format:uri");
+const BASIC: Option<&str> = Some("urn:oasis:names:tc:SAML:2.0:attrname-format:basic");
+
+#[test]
+fn test_idps_map_to_the_same_keys() {
+    let shibboleth = assertion(
+        &[
+            attribute(
+                "urn:oid:0.9.2342.19200300.100.1.3",
+                URI,
+                &["jdoe@example.com"],
+            ),
+            attribute("urn:oid:2.5.4.42", URI, &["John"]),
+            attribute("urn:oid:2.5.4.4", URI, &["Doe"]),
+            attribute(
+                "urn:oid:1.3.6.1.4.1.5923.1.1.1.6",
+                URI,
+                &["jdoe@example.com"],
+            ),
+        ]
+        .concat(),
+    );
+    let azure_ad = assertion(
+        &[
+            attribute(
+                "http://schemas.xmlsoap.org/ws/2005/05/identity/claims/emailaddress",
+                None,
+                &["jdoe@example.com"],
+            ),
+            attribute(
+                "http://schemas.xmlsoap.org/ws/2005/05/identity/claims/givenname",
+                None,
+                &["John"],
+            ),
+            attribute(
+                "http://schemas.xmlsoap.org/ws/2005/05/identity/claims/surname",
+                None,
+                &["Doe"],
+            ),
+            attribute(
+                "http://schemas.xmlsoap.org/ws/2005/05/identity/claims/name",
+                None,
+                &["jdoe@example.com"],
+            ),
+        ]
+        .concat(),
+    );
+    let okta = assertion(
+        &[
+            attribute("email", BASIC, &["jdoe@example.com"]),
+            attribute("firstName", BASIC, &["John"]),
+            attribute("lastName", BASIC, &["Doe"]),
+            attribute("eduPersonPrincipalName", None, &["jdoe@example.com"]),
+        ]
+        .concat(),
+    );
+
+    let mapper = AttributeMapper::standard();
+    for assertion in [&shibboleth, &azure_ad, &okta] {
+        let mapped = mapper.map(assertion);
+        assert_eq!(
+            mapped.keys().collect::<Vec<_>>(),
+            vec![PRINCIPAL_NAME, GIVEN_NAME, MAIL, SURNAME]
+        );
+        assert_eq!(mapped.attribute(MAIL).string().unwrap(), "jdoe@example.com");
+        assert_eq!(mapped.attribute(GIVEN_NAME).string().unwrap(), "John");
+        assert_eq!(mapped.attribute(SURNAME).string().unwrap(), "Doe");
+        assert!(mapped.unmapped().is_empty());
+    }
+}
+
+#[test]
+fn test_user_rules_take_precedence() {
+    let assertion = assertion(
+        &[
+            attribute("urn:example:first-name", URI, &["John"]),
+            attribute(
+                "http://schemas.microsoft.com/ws/2008/06/identity/claims/groups",
+                None,
+                &["staff", "admins"],
+            ),
+            // some IdPs send the display name in the common name
+            attribute("urn:oid:2.5.4.3", URI, &["John Doe"]),
+            attribute("urn:example:unknown", URI, &["?"]),
+        ]
+        .concat(),
+    );
+    let mapper = AttributeMapper::standard().with_rules(
+        AttributeProfile::new()
+            .rule("urn:example:first-name", None, GIVEN_NAME)
+            .rule("urn:oid:2.5.4.3", URI, DISPLAY_NAME),
+    );
+    let mapped = mapper.map(&assertion);
+
+    assert_eq!(mapped.attribute(GIVEN_NAME).string().unwrap(), "John");
+    assert_eq!(mapped.attribute(DISPLAY_NAME).string().unwrap(), "John Doe");
+    assert_eq!(
+        mapped.attribute(GROUPS).strings().unwrap(),
+        vec!["staff", "admins"]
+    );
+    assert_eq!(mapped.unmapped().len(), 1);
+    assert_eq!(
+        mapped.unmapped()[0].name.as_deref(),
+        Some("urn:example:unknown")
+    );
+    assert!(mapped.attribute(MAIL).is_empty());
+}
@@ -0,0 +1,153 @@
+use samael::attribute::{
+    DISPLAY_NAME_URI, EDU_PERSON_ENTITLEMENT_URI, MAIL_URI, NAME_FORMAT_URI, UID_URI,
+};
+use samael::idp::attribute_release::{
+    AttributeMatcher, AttributeReleasePolicy, Reason, ReleaseRule, SpMatcher,
+};
+use samael::idp::response_builder::ResponseAttribute;
+use samael::idp::sp_extractor::{RequiredAttribute, SPMetadataExtractor};
+use samael::metadata::{REFEDS_CODE_OF_CONDUCT, RESEARCH_AND_SCHOLARSHIP};
+
+fn sp_metadata(entity_id: &str, category: &str, requested_attributes: &str) -> SPMetadataExtractor {
+    SPMetadataExtractor::try_from_xml(&format!(
+        r#"<md:EntityDescriptor xmlns:md="urn:oasis:names:tc:SAML:2.0:metadata" xmlns:mdattr="urn:oasis:names:tc:SAML:metadata:attribute" xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion" entityID="{}">
+  <md:Extensions>
+    <mdattr:EntityAttributes>
+      <saml:Attribute Name="http://macedir.org/entity-category" NameFormat="urn:oasis:names:tc:SAML:2.0:attrname-format:uri">
+        <saml:AttributeValue>{}</saml:AttributeValue>
+      </saml:Attribute>
+    </mdattr:EntityAttributes>
+  </md:Extensions>
+  <md:SPSSODescriptor protocolSupportEnumeration="urn:oasis:names:tc:SAML:2.0:protocol">
+    <md:AssertionConsumerService Binding="urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST" Location="{}/acs" index="0"/>
+    <md:AttributeConsumingService index="0">
+      <md:ServiceName xml:lang="en">Example</md:ServiceName>
+      {}
+    </md:AttributeConsumingService>
+  </md:SPSSODescriptor>
+</md:EntityDescriptor>"#,
+        entity_id, category, entity_id, requested_attributes
+    ))
+    .unwrap()
+}
+
+fn user_attribute(name: &str, values: &[&str]) -> ResponseAttribute {
+    ResponseAttribute {
+        required_attribute: RequiredAttribute {
+            name: name.to_string(),
+            format: Some(NAME_FORMAT_URI.to_string()),
+        },
+        friendly_name: None,
+        values: values.iter().map(|value| (*value).into()).collect(),
+    }
+}
+
+fn user_attributes() -> Vec<ResponseAttribute> {
+    vec![
+        user_attribute(MAIL_URI, &["jdoe@example.com"]),
+        user_attribute(DISPLAY_NAME_URI, &["John Doe"]),
+        user_attribute(UID_URI, &["jdoe"]),
+        user_attribute(
+            EDU_PERSON_ENTITLEMENT_URI,
+            &["urn:example:library", "urn:example:admin"],
+        ),
+    ]
+}
+
+#[test]
+fn test_entity_categories() {
+    let sp = sp_metadata("https://sp.example.com", RESEARCH_AND_SCHOLARSHIP, "");
+    assert_eq!(
+        sp.entity_descriptor().entity_categories(),
+        [RESEARCH_AND_SCHOLARSHIP]
+    );
+}
+
+#[test]
+fn test_research_and_scholarship_bundle() {
+    let sp = sp_metadata("https://sp.example.com", RESEARCH_AND_SCHOLARSHIP, "");
+    let policy = AttributeReleasePolicy::new().with_research_and_scholarship();
+    let release = policy.release(&sp, None, &user_attributes());
+
+    let released = release
+        .attributes
+        .iter()
+        .map(|attribute| attribute.required_attribute.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(released, [MAIL_URI, DISPLAY_NAME_URI]);
+    assert_eq!(release.decisions.len(), 4);
+    assert_eq!(
+        release.decisions[0].reason,
+        Reason::Released {
+            rule: "research-and-scholarship".to_string()
+        }
+    );
+    assert_eq!(release.decisions[2].reason, Reason::NoMatchingRule);
+
+    // the bundle is not released to SPs outside the category
+    let other = sp_metadata("https://sp.example.com", "urn:example:other", "");
+    assert!(policy
+        .release(&other, None, &user_attributes())
+        .attributes
+        .is_empty());
+}
+
+#[test]
+fn test_code_of_conduct_releases_requested_values() {
+    let sp = sp_metadata(
+        "https://sp.example.com",
+        REFEDS_CODE_OF_CONDUCT,
+        r#"<md:RequestedAttribute Name="urn:oid:0.9.2342.19200300.100.1.3" FriendlyName="mail" isRequired="true"/>
+      <md:RequestedAttribute Name="urn:oid:1.3.6.1.4.1.5923.1.1.1.7" FriendlyName="eduPersonEntitlement">
+        <saml:AttributeValue>urn:example:library</saml:AttributeValue>
+      </md:RequestedAttribute>
+      <md:RequestedAttribute Name="urn:oid:2.5.4.42" FriendlyName="givenName" isRequired="true"/>"#,
+    );
+    let policy = AttributeReleasePolicy::new().with_code_of_conduct();
+    let release = policy.release(&sp, None, &user_attributes());
+
+    assert_eq!(release.attributes.len(), 2);
+    assert_eq!(release.attributes[0].friendly_name.as_deref(), Some("mail"));
+    let entitlement = &release.attributes[1];
+    assert_eq!(entitlement.values, vec!["urn:example:library".into()]);
+    assert_eq!(release.decisions[3].filtered_values, 1);
+    assert_eq!(
+        release.decisions[3].to_string(),
+        "urn:oid:1.3.6.1.4.1.5923.1.1.1.7 released by code-of-conduct (1 values filtered)"
+    );
+    assert_eq!(
+        release.decisions[1].reason,
+        Reason::NotRequested {
+            rule: "code-of-conduct".to_string()
+        }
+    );
+    assert_eq!(release.missing_required, ["urn:oid:2.5.4.42"]);
+}
+
+#[test]
+fn test_deny_wins() {
+    let sp = sp_metadata("https://tracker.example.com", RESEARCH_AND_SCHOLARSHIP, "");
+    let policy = AttributeReleasePolicy::new()
+        .with_research_and_scholarship()
+        .with_rule(ReleaseRule::release(
+            "uid-to-everyone",
+            SpMatcher::Any,
+            AttributeMatcher::names([UID_URI]),
+        ))
+        .with_rule(ReleaseRule::deny(
+            "no-mail-to-tracker",
+            SpMatcher::EntityId("https://tracker.example.com".to_string()),
+            AttributeMatcher::names([MAIL_URI]),
+        ));
+    let release = policy.release(&sp, None, &user_attributes());
+
+    assert_eq!(
+        release.decisions[0].reason,
+        Reason::Denied {
+            rule: "no-mail-to-tracker".to_string()
+        }
+    );
+    assert!(!release.decisions[0].is_released());
+    assert!(release.decisions[2].is_released());
+    assert_eq!(release.attributes.len(), 2);
+}
@@ -0,0 +1,279 @@
+use chrono::Duration;
+use openssl::asn1::Asn1Time;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::x509::extension::KeyUsage;
+use openssl::x509::{X509Name, X509};
+use samael::clock::ManualClock;
+use samael::metadata::EntityDescriptor;
+use samael::service_provider::{Error, ServiceProvider};
+use samael::trust::{self, CertificateTrust, PkiTrust};
+use std::sync::Arc;
+
+fn clock(now: &str) -> ManualClock {
+    ManualClock::new(now.parse().unwrap())
+}
+
+fn pem(name: &str) -> X509 {
+    let path = format!("{}/test_vectors/pki/{}", env!("CARGO_MANIFEST_DIR"), name);
+    X509::from_pem(&std::fs::read(path).unwrap()).unwrap()
+}
+
+fn crl_file() -> String {
+    format!(
+        "{}/test_vectors/pki/intermediate_ca.crl.pem",
+        env!("CARGO_MANIFEST_DIR")
+    )
+}
+
+#[test]
+fn test_accept_certificate_issued_by_ca() {
+    let now = clock("2025-01-01T00:00:00Z");
+    let pki = PkiTrust::new(vec![pem("root_ca.pem")]);
+    pki.validate(
+        &pem("idp_signing.pem"),
+        &[pem("intermediate_ca.pem")],
+        Duration::seconds(180),
+        &now,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_reject_certificate_without_chain() {
+    let now = clock("2025-01-01T00:00:00Z");
+    let pki = PkiTrust::new(vec![pem("root_ca.pem")]);
+    assert!(matches!(
+        pki.validate(&pem("idp_signing.pem"), &[], Duration::seconds(180), &now),
+        Err(trust::Error::UntrustedCertificate { .. })
+    ));
+}
+
+#[test]
+fn test_reject_certificate_from_other_ca() {
+    let now = clock("2025-01-01T00:00:00Z");
+    let other_ca = X509::from_der(include_bytes!("../test_vectors/public.der")).unwrap();
+    let pki = PkiTrust::new(vec![other_ca]);
+    assert!(matches!(
+        pki.validate(
+            &pem("idp_signing.pem"),
+            &[pem("intermediate_ca.pem")],
+            Duration::seconds(180),
+            &now
+        ),
+        Err(trust::Error::UntrustedCertificate { .. })
+    ));
+}
+
+#[test]
+fn test_reject_revoked_certificate() {
+    let now = clock("2025-01-01T00:00:00Z");
+    let pki = PkiTrust::new(vec![pem("root_ca.pem")]).with_crl_file(crl_file());
+    pki.validate(
+        &pem("idp_signing.pem"),
+        &[pem("intermediate_ca.pem")],
+        Duration::seconds(180),
+        &now,
+    )
+    .unwrap();
+    assert!(matches!(
+        pki.validate(
+            &pem("idp_revoked.pem"),
+            &[pem("intermediate_ca.pem")],
+            Duration::seconds(180),
+            &now
+        ),
+        Err(trust::Error::UntrustedCertificate { .. })
+    ));
+}
+
+#[test]
+fn test_reject_missing_crl_file() {
+    let now = clock("2025-01-01T00:00:00Z");
+    let pki = PkiTrust::new(vec![pem("root_ca.pem")]).with_crl_file("/nonexistent.crl.pem");
+    assert!(matches!(
+        pki.validate(
+            &pem("idp_signing.pem"),
+            &[pem("intermediate_ca.pem")],
+            Duration::seconds(180),
+            &now
+        ),
+        Err(trust::Error::FailedToLoadCrl { .. })
+    ));
+}
+
+#[test]
+fn test_validity_dates_with_clock_skew() {
+    let pki = PkiTrust::new(vec![pem("root_ca.pem")]);
+    let now = clock("2029-12-31T23:59:00Z");
+    let validate = |skew| {
+        pki.validate(
+            &pem("idp_signing.pem"),
+            &[pem("intermediate_ca.pem")],
+            Duration::seconds(skew),
+            &now,
+        )
+    };
+
+    validate(0).unwrap();
+
+    now.advance(Duration::minutes(3));
+    assert!(matches!(
+        validate(60),
+        Err(trust::Error::CertificateExpired { .. })
+    ));
+    validate(180).unwrap();
+
+    now.set("2019-12-31T23:58:00Z".parse().unwrap());
+    assert!(matches!(
+        validate(60),
+        Err(trust::Error::CertificateNotYetValid { .. })
+    ));
+    validate(180).unwrap();
+}
+
+#[test]
+fn test_service_provider_pki_trust() {
+    let idp_cert = X509::from_der(include_bytes!("../test_vectors/public.der")).unwrap();
+    let response_xml = include_str!("../test_vectors/response_signed_by_idp_2.xml");
+
+    // the self-signed IdP certificate is its own certificate authority
+    let sp = ServiceProvider {
+        idp_metadata: include_str!("../test_vectors/idp_2_metadata.xml")
+            .parse()
+            .unwrap(),
+        idp_certificate_trust: CertificateTrust::Pki(PkiTrust::new(vec![idp_cert])),
+        clock: Arc::new(clock("2025-01-01T00:00:00Z")),
+        ..Default::default()
+    };
+    assert_eq!(sp.idp_signing_certs().unwrap().unwrap().len(), 1);
+    #[cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
+    sp.verify_response_signatures(response_xml).unwrap();
+
+    let sp = ServiceProvider {
+        idp_certificate_trust: CertificateTrust::Pki(PkiTrust::new(vec![pem("root_ca.pem")])),
+        ..sp
+    };
+    assert!(matches!(
+        sp.idp_signing_certs(),
+        Err(Error::UntrustedSigningCertificate { .. })
+    ));
+    assert!(matches!(
+        sp.parse_xml_response(response_xml, &[""]),
+        Err(Error::UntrustedSigningCertificate { .. })
+    ));
+}
+
+#[test]
+fn test_reject_certificate_not_for_signatures() {
+    let now = clock("2025-01-01T00:00:00Z");
+    let pki = PkiTrust::new(vec![pem("root_ca.pem")]);
+    // a certificate authority only signs certificates and CRLs
+    assert!(matches!(
+        pki.validate(
+            &pem("intermediate_ca.pem"),
+            &[],
+            Duration::seconds(180),
+            &now
+        ),
+        Err(trust::Error::InvalidKeyUsage { .. })
+    ));
+}
+
+fn self_signed(key_usage: Option<KeyUsage>) -> X509 {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509Name::builder().unwrap();
+    name.append_entry_by_nid(Nid::COMMONNAME, "idp.example.com")
+        .unwrap();
+    let name = name.build();
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(365).unwrap())
+        .unwrap();
+    if let Some(mut key_usage) = key_usage {
+        builder
+            .append_extension(key_usage.critical().build().unwrap())
+            .unwrap();
+    }
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+    builder.build()
+}
+
+#[test]
+fn test_reject_leaf_certificate_without_digital_signature() {
+    let now = clock("2025-01-01T00:00:00Z");
+    let pki = PkiTrust::new(vec![pem("root_ca.pem")]);
+    let validate =
+        |certificate: &X509| pki.validate(certificate, &[], Duration::seconds(180), &now);
+
+    // an encryption certificate
+    let mut encryption = KeyUsage::new();
+    encryption.key_encipherment().data_encipherment();
+    assert!(matches!(
+        validate(&self_signed(Some(encryption))),
+        Err(trust::Error::InvalidKeyUsage { .. })
+    ));
+
+    // the certificates below pass the key usage check, and fail for not chaining up to the CA
+    let mut signing = KeyUsage::new();
+    signing.digital_signature().key_encipherment();
+    assert!(matches!(
+        validate(&self_signed(Some(signing))),
+        Err(trust::Error::UntrustedCertificate { .. })
+    ));
+    assert!(matches!(
+        validate(&self_signed(None)),
+        Err(trust::Error::UntrustedCertificate { .. })
+    ));
+}
+
+#[test]
+fn test_pki_trust_without_metadata_certificates() {
+    let idp_cert = X509::from_der(include_bytes!("../test_vectors/public.der")).unwrap();
+    let response_xml = include_str!("../test_vectors/response_signed_by_idp_2.xml");
+    let mut idp_metadata: EntityDescriptor = include_str!("../test_vectors/idp_2_metadata.xml")
+        .parse()
+        .unwrap();
+    for descriptor in &mut idp_metadata.idp_sso_descriptors {
+        descriptor.key_descriptors.clear();
+    }
+
+    // the PKI vouches for the certificate embedded in the signature
+    let sp = ServiceProvider {
+        idp_metadata,
+        idp_certificate_trust: CertificateTrust::Pki(PkiTrust::new(vec![idp_cert])),
+        clock: Arc::new(clock("2025-01-01T00:00:00Z")),
+        ..Default::default()
+    };
+    assert!(sp.idp_signing_certs().unwrap().is_none());
+    #[cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
+    {
+        let report = sp.verify_response_signatures(response_xml).unwrap();
+        assert_eq!(report.signatures.len(), 1);
+    }
+
+    // an unsigned response is refused rather than accepted unchecked
+    let unsigned_xml = include_str!("../test_vectors/response.xml");
+    assert!(matches!(
+        sp.parse_xml_response(unsigned_xml, &[""]),
+        Err(Error::MissingSigningCertificate)
+    ));
+
+    let sp = ServiceProvider {
+        idp_certificate_trust: CertificateTrust::Pki(PkiTrust::new(vec![pem("root_ca.pem")])),
+        ..sp
+    };
+    assert!(matches!(
+        sp.parse_xml_response(response_xml, &[""]),
+        Err(Error::UntrustedSigningCertificate { .. })
+    ));
+}
@@ -0,0 +1,293 @@
+#![cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
+
+use base64::{engine::general_purpose, Engine as _};
+use openssl::rsa::Rsa;
+use openssl::x509::X509;
+use samael::clock::ManualClock;
+use samael::crypto;
+use samael::schema::Response;
+use samael::service_provider::{Error, KeyPair, ServiceProvider};
+use samael::xmlenc::{self, DecryptionKeyProvider, EncryptedKey, SoftwareKeyProvider};
+use std::sync::{Arc, Mutex};
+
+const REQUEST_ID: &str = "ONELOGIN_4fee3b046395c4e751011e97f8900b5273d56685";
+
+fn key_pair(name: &str) -> KeyPair {
+    let path = format!(
+        "{}/test_vectors/encryption/sp_{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    KeyPair {
+        key: Rsa::private_key_from_pem(&std::fs::read(format!("{}_key.pem", path)).unwrap())
+            .unwrap(),
+        certificate: X509::from_pem(&std::fs::read(format!("{}_cert.pem", path)).unwrap()).unwrap(),
+    }
+}
+
+fn keys(names: &[&str]) -> SoftwareKeyProvider {
+    SoftwareKeyProvider::new(names.iter().map(|name| key_pair(name).key).collect())
+}
+
+fn idp_cert() -> X509 {
+    X509::from_der(include_bytes!("../test_vectors/public.der")).unwrap()
+}
+
+fn service_provider() -> ServiceProvider {
+    let current = key_pair("current");
+    ServiceProvider {
+        entity_id: "http://test_accept_signed_with_correct_key.test".to_string(),
+        key: Some(current.key),
+        certificate: Some(current.certificate),
+        next_key_pair: Some(key_pair("next")),
+        acs_url: Some("http://sp.example.com/demo1/index.php?acs".into()),
+        idp_metadata: include_str!("../test_vectors/idp_2_metadata.xml")
+            .parse()
+            .unwrap(),
+        // the responses of the test vectors were issued then
+        clock: Arc::new(ManualClock::new("2014-07-17T01:01:50Z".parse().unwrap())),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_decrypt_with_every_key() {
+    let response_xml = include_str!("../test_vectors/encryption/response_encrypted_assertion.xml");
+
+    assert!(matches!(
+        xmlenc::decrypt_assertions(response_xml, &keys(&["current"])),
+        Err(xmlenc::Error::NoMatchingKey)
+    ));
+
+    let decrypted = xmlenc::decrypt_assertions(response_xml, &keys(&["current", "next"]))
+        .unwrap()
+        .unwrap();
+    assert!(!decrypted.contains("EncryptedAssertion"));
+    // the assertion uses the namespace prefixes of the response, and its signature still holds
+    let report = crypto::verify_signatures(&decrypted, &[idp_cert()]).unwrap();
+    assert_eq!(
+        report.signatures[0].path,
+        "/samlp:Response/saml:Assertion/ds:Signature"
+    );
+}
+
+#[test]
+fn test_decrypt_aes_cbc_with_key_next_to_encrypted_data() {
+    let response_xml =
+        include_str!("../test_vectors/encryption/response_encrypted_assertion_cbc.xml");
+    let decrypted = xmlenc::decrypt_assertions(response_xml, &keys(&["next", "current"]))
+        .unwrap()
+        .unwrap();
+    crypto::verify_signatures(&decrypted, &[idp_cert()]).unwrap();
+
+    let plain_xml = include_str!("../test_vectors/xsw/response_signed_assertion.xml");
+    assert!(xmlenc::decrypt_assertions(plain_xml, &keys(&["current"]))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_decrypt_with_prefix_declared_on_encrypted_assertion() {
+    let response_xml =
+        include_str!("../test_vectors/encryption/response_encrypted_assertion_saml2_prefix.xml");
+    let decrypted = xmlenc::decrypt_assertions(response_xml, &keys(&["current"]))
+        .unwrap()
+        .unwrap();
+    // the declaration went away with the EncryptedAssertion, and comes back on the assertion
+    let response: Response = decrypted.parse().unwrap();
+    let assertion = &response.assertions[0];
+    assert_eq!(assertion.id, "_d71a3a8e9fcc45c9e9d248ef7049393fc8f04e5f75");
+    assert_eq!(
+        assertion.attribute("mail").string().unwrap(),
+        "test@example.com"
+    );
+}
+
+#[test]
+fn test_parse_signed_response_with_encrypted_assertion() {
+    let response_xml =
+        include_str!("../test_vectors/encryption/signed_response_encrypted_assertion.xml");
+
+    let assertion = service_provider()
+        .parse_xml_response(response_xml, &[REQUEST_ID])
+        .unwrap();
+    assert_eq!(assertion.id, "_d71a3a8e9fcc45c9e9d248ef7049393fc8f04e5f75");
+
+    // the assertion was encrypted for the current key, which is still tried once rolled over
+    let mut sp = service_provider();
+    sp.promote_next_key_pair();
+    assert_eq!(sp.previous_key_pairs.len(), 1);
+    sp.parse_xml_response(response_xml, &[REQUEST_ID]).unwrap();
+
+    sp.previous_key_pairs.clear();
+    assert!(matches!(
+        sp.parse_xml_response(response_xml, &[REQUEST_ID]),
+        Err(Error::FailedToDecryptAssertion {
+            error: xmlenc::Error::NoMatchingKey
+        })
+    ));
+}
+
+#[test]
+fn test_reject_unsigned_encrypted_assertion() {
+    let response_xml =
+        include_str!("../test_vectors/encryption/response_encrypted_unsigned_assertion.xml");
+    let result = service_provider().parse_xml_response(response_xml, &[REQUEST_ID]);
+    assert!(result.is_err(), "{:?}", result);
+}
+
+#[test]
+fn test_metadata_publishes_current_and_next_certificates() {
+    let certificate =
+        |pair: KeyPair| general_purpose::STANDARD.encode(pair.certificate.to_der().unwrap());
+    let published = |sp: &ServiceProvider| {
+        sp.metadata().unwrap().sp_sso_descriptors[0]
+            .key_descriptors
+            .iter()
+            .map(|descriptor| {
+                (
+                    descriptor.key_use.clone().unwrap(),
+                    descriptor.key_info.x509_data.as_ref().unwrap().certificates[0].clone(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut sp = service_provider();
+    let (current, next) = (
+        certificate(key_pair("current")),
+        certificate(key_pair("next")),
+    );
+    assert_eq!(
+        published(&sp),
+        vec![
+            ("signing".to_string(), current.clone()),
+            ("encryption".to_string(), current),
+            ("signing".to_string(), next.clone()),
+            ("encryption".to_string(), next.clone()),
+        ]
+    );
+
+    sp.promote_next_key_pair();
+    assert_eq!(
+        published(&sp),
+        vec![
+            ("signing".to_string(), next.clone()),
+            ("encryption".to_string(), next),
+        ]
+    );
+}
+
+#[test]
+fn test_select_key_by_certificate() {
+    let response_xml =
+        include_str!("../test_vectors/encryption/response_encrypted_assertion_certificates.xml");
+    // the first encrypted key names the next certificate, so only the second one is tried
+    let decrypted = xmlenc::decrypt_assertions(response_xml, &keys(&["current"]))
+        .unwrap()
+        .unwrap();
+    crypto::verify_signatures(&decrypted, &[idp_cert()]).unwrap();
+
+    assert!(xmlenc::decrypt_assertions(response_xml, &keys(&["next"])).is_ok());
+
+    // a key whose certificate no encrypted key names is never tried
+    let other_key = Rsa::generate(2048).unwrap();
+    assert!(matches!(
+        xmlenc::decrypt_assertions(response_xml, &SoftwareKeyProvider::new(vec![other_key])),
+        Err(xmlenc::Error::NoMatchingKey)
+    ));
+}
+
+/// Unwraps keys the way an HSM would: the private key is only reachable by its name.
+struct HsmKeyProvider {
+    key_name: String,
+    key: Rsa<openssl::pkey::Private>,
+    requests: Mutex<Vec<(Option<String>, bool)>>,
+}
+
+impl DecryptionKeyProvider for HsmKeyProvider {
+    fn unwrap_key(&self, encrypted_key: &EncryptedKey) -> Result<Vec<u8>, xmlenc::Error> {
+        self.requests.lock().unwrap().push((
+            encrypted_key.key_name.clone(),
+            encrypted_key.certificate.is_some(),
+        ));
+        if encrypted_key.key_name.as_ref() != Some(&self.key_name) {
+            return Err(xmlenc::Error::NoMatchingKey);
+        }
+        SoftwareKeyProvider::new(vec![self.key.clone()]).unwrap_key(&EncryptedKey {
+            key_name: None,
+            ..encrypted_key.clone()
+        })
+    }
+}
+
+#[test]
+fn test_select_key_by_name() {
+    let response_xml =
+        include_str!("../test_vectors/encryption/response_encrypted_assertion_key_name.xml");
+    // the name is only a hint, unnamed keys are still tried
+    assert!(xmlenc::decrypt_assertions(response_xml, &keys(&["current", "next"])).is_ok());
+    let named = SoftwareKeyProvider::default().with_named_key("sp-hsm-key", key_pair("next").key);
+    assert!(xmlenc::decrypt_assertions(response_xml, &named).is_ok());
+    let mixed = keys(&["current"]).with_named_key("sp-hsm-key", key_pair("next").key);
+    assert!(xmlenc::decrypt_assertions(response_xml, &mixed).is_ok());
+    // a key of another name is not
+    let other_name = SoftwareKeyProvider::default().with_named_key("other", key_pair("next").key);
+    assert!(matches!(
+        xmlenc::decrypt_assertions(response_xml, &other_name),
+        Err(xmlenc::Error::NoMatchingKey)
+    ));
+
+    let provider = Arc::new(HsmKeyProvider {
+        key_name: "sp-hsm-key".to_string(),
+        key: key_pair("next").key,
+        requests: Mutex::new(vec![]),
+    });
+    // the SP has no private key in memory, the provider unwraps the key of the assertion
+    let sp = ServiceProvider {
+        key: None,
+        next_key_pair: None,
+        decryption_key_provider: Some(provider.clone()),
+        ..service_provider()
+    };
+    let assertion = sp
+        .parse_xml_response(
+            include_str!(
+                "../test_vectors/encryption/signed_response_encrypted_assertion_key_name.xml"
+            ),
+            &[REQUEST_ID],
+        )
+        .unwrap();
+    assert_eq!(assertion.id, "_d71a3a8e9fcc45c9e9d248ef7049393fc8f04e5f75");
+    assert_eq!(
+        *provider.requests.lock().unwrap(),
+        vec![(Some("sp-hsm-key".to_string()), false)]
+    );
+}
+
+#[test]
+fn test_altered_cbc_plaintext_is_a_decryption_failure() {
+    let response_xml =
+        include_str!("../test_vectors/encryption/response_encrypted_assertion_cbc.xml");
+    let (start, end) = (
+        response_xml.find("<xenc:CipherValue>").unwrap() + "<xenc:CipherValue>".len(),
+        response_xml.find("</xenc:CipherValue>").unwrap(),
+    );
+    // altering the IV alters the first byte of the plaintext, its opening '<'
+    for mask in [0x80, b'<' ^ b'a'] {
+        let mut data = general_purpose::STANDARD
+            .decode(&response_xml[start..end])
+            .unwrap();
+        data[0] ^= mask;
+        let altered_xml = format!(
+            "{}{}{}",
+            &response_xml[..start],
+            general_purpose::STANDARD.encode(data),
+            &response_xml[end..]
+        );
+        assert!(matches!(
+            xmlenc::decrypt_assertions(&altered_xml, &keys(&["current"])),
+            Err(xmlenc::Error::DecryptionFailed)
+        ));
+    }
+}
@@ -0,0 +1,127 @@
+use openssl::x509::X509;
+use samael::idp::error::Error;
+use samael::idp::key_set::{KeySet, SigningKey};
+use samael::idp::{CertificateParams, IdentityProvider, KeyType};
+use std::sync::Arc;
+
+fn signing_key(common_name: &str) -> SigningKey {
+    let idp = IdentityProvider::generate_new(KeyType::Rsa2048).unwrap();
+    let certificate = idp
+        .create_certificate(&CertificateParams {
+            common_name,
+            issuer_name: common_name,
+            days_until_expiration: 365,
+        })
+        .unwrap();
+    SigningKey::new(
+        idp.key_set().active().private_key().unwrap().clone(),
+        X509::from_der(&certificate).unwrap(),
+    )
+    .unwrap()
+}
+
+fn common_names(keys: &KeySet) -> Vec<String> {
+    keys.certificates()
+        .into_iter()
+        .map(|cert| {
+            cert.subject_name()
+                .entries()
+                .next()
+                .unwrap()
+                .data()
+                .as_utf8()
+                .unwrap()
+                .to_string()
+        })
+        .collect()
+}
+
+#[test]
+fn test_key_set_rotation() {
+    let mut keys = KeySet::new(signing_key("active"))
+        .with_next(signing_key("next"))
+        .with_previous(signing_key("previous"));
+    assert_eq!(common_names(&keys), vec!["active", "next", "previous"]);
+
+    keys.rotate().unwrap();
+    assert_eq!(common_names(&keys), vec!["next", "active", "previous"]);
+    assert!(keys.next().is_none());
+    assert!(matches!(keys.rotate(), Err(Error::NoNextKey)));
+
+    keys.retire_previous(1);
+    assert_eq!(common_names(&keys), vec!["next", "active"]);
+}
+
+#[test]
+fn test_switch_active_key_at_runtime() {
+    let active = signing_key("active");
+    let next = signing_key("next");
+    let idp = Arc::new(IdentityProvider::from_key_set(
+        KeySet::new(active.clone()).with_next(next.clone()),
+    ));
+    let exported = |idp: &IdentityProvider| idp.export_private_key_der().unwrap();
+    assert_eq!(
+        exported(&idp),
+        active
+            .private_key()
+            .unwrap()
+            .rsa()
+            .unwrap()
+            .private_key_to_der()
+            .unwrap()
+    );
+
+    let shared = Arc::clone(&idp);
+    std::thread::spawn(move || shared.rotate_keys().unwrap())
+        .join()
+        .unwrap();
+    assert_eq!(
+        exported(&idp),
+        next.private_key()
+            .unwrap()
+            .rsa()
+            .unwrap()
+            .private_key_to_der()
+            .unwrap()
+    );
+    assert_eq!(common_names(&idp.key_set()), vec!["next", "active"]);
+
+    idp.set_key_set(KeySet::new(active.clone()));
+    assert_eq!(common_names(&idp.key_set()), vec!["active"]);
+}
+
+#[test]
+fn test_metadata_lists_every_certificate() {
+    let idp = IdentityProvider::from_key_set(
+        KeySet::new(signing_key("active"))
+            .with_next(signing_key("next"))
+            .with_previous(signing_key("previous")),
+    );
+    let metadata = idp
+        .metadata("https://idp.example.com", "https://idp.example.com/sso")
+        .unwrap();
+    let key_descriptors = &metadata.idp_sso_descriptors[0].key_descriptors;
+    assert_eq!(key_descriptors.len(), 3);
+    assert!(key_descriptors
+        .iter()
+        .all(|descriptor| descriptor.is_signing()));
+
+    // the SP side finds the same certificates, whichever key signs
+    let sp = samael::service_provider::ServiceProvider {
+        idp_metadata: metadata,
+        ..Default::default()
+    };
+    let sp_certs = sp.idp_signing_certs().unwrap().unwrap();
+    let idp_certs = idp.key_set();
+    assert_eq!(
+        sp_certs
+            .iter()
+            .map(|cert| cert.to_der().unwrap())
+            .collect::<Vec<_>>(),
+        idp_certs
+            .certificates()
+            .into_iter()
+            .map(|cert| cert.to_der().unwrap())
+            .collect::<Vec<_>>()
+    );
+}
@@ -0,0 +1,121 @@
+use chrono::Duration;
+use openssl::x509::X509;
+use samael::attribute::{Attribute, MAIL_URI, NAME_FORMAT_URI};
+use samael::clock::ManualClock;
+use samael::idp::key_set::{KeySet, SigningKey};
+use samael::idp::metadata::IdpMetadataBuilder;
+use samael::idp::{CertificateParams, IdentityProvider, KeyType};
+use samael::metadata::{
+    ContactPerson, ContactType, Endpoint, EntityDescriptor, NameIdFormat, Scope, HTTP_POST_BINDING,
+    HTTP_REDIRECT_BINDING,
+};
+use samael::service_provider::ServiceProvider;
+use std::sync::Arc;
+
+fn identity_provider() -> IdentityProvider {
+    let idp = IdentityProvider::generate_new(KeyType::Rsa2048).unwrap();
+    let certificate = idp
+        .create_certificate(&CertificateParams {
+            common_name: "https://idp.example.com",
+            issuer_name: "https://idp.example.com",
+            days_until_expiration: 365,
+        })
+        .unwrap();
+    IdentityProvider::from_key_set(KeySet::new(
+        SigningKey::new(
+            idp.key_set().active().private_key().unwrap().clone(),
+            X509::from_der(&certificate).unwrap(),
+        )
+        .unwrap(),
+    ))
+}
+
+fn endpoints(location: &str) -> Vec<Endpoint> {
+    [HTTP_REDIRECT_BINDING, HTTP_POST_BINDING]
+        .iter()
+        .map(|binding| Endpoint {
+            binding: binding.to_string(),
+            location: location.to_string(),
+            response_location: None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_idp_metadata() {
+    let idp = identity_provider().with_clock(Arc::new(ManualClock::new(
+        "2025-01-01T00:00:00Z".parse().unwrap(),
+    )));
+    let encryption_certificate = idp.key_set().active().certificate().unwrap();
+    let metadata = IdpMetadataBuilder::default()
+        .entity_id("https://idp.example.com")
+        .single_sign_on_services(endpoints("https://idp.example.com/sso"))
+        .single_logout_services(endpoints("https://idp.example.com/slo"))
+        .name_id_formats(vec![
+            NameIdFormat::PersistentNameIDFormat.value().to_string(),
+            NameIdFormat::TransientNameIDFormat.value().to_string(),
+        ])
+        .want_authn_requests_signed(true)
+        .attributes(vec![Attribute {
+            friendly_name: Some("mail".to_string()),
+            name: Some(MAIL_URI.to_string()),
+            name_format: Some(NAME_FORMAT_URI.to_string()),
+            values: vec![],
+        }])
+        .scopes(vec![Scope {
+            regexp: Some(false),
+            value: "example.com".to_string(),
+        }])
+        .encryption_certificates(vec![encryption_certificate])
+        .contact_people(vec![ContactPerson {
+            contact_type: ContactType::Technical.value().to_string(),
+            email_addresses: vec!["mailto:admin@idp.example.com".to_string()],
+            ..ContactPerson::default()
+        }])
+        .valid_duration(Duration::days(7))
+        .cache_duration(Duration::hours(6))
+        .build()
+        .unwrap();
+
+    let entity_descriptor = idp.build_metadata(&metadata).unwrap();
+    let xml = yaserde::ser::to_string(&entity_descriptor).unwrap();
+    let parsed: EntityDescriptor = xml.parse().unwrap();
+    assert_eq!(parsed, entity_descriptor);
+
+    assert_eq!(parsed.cache_duration.as_deref(), Some("PT21600S"));
+    assert_eq!(
+        parsed.valid_until.clone().unwrap().0,
+        "2025-01-08T00:00:00Z"
+            .parse::<chrono::DateTime<chrono::Utc>>()
+            .unwrap()
+    );
+    assert_eq!(parsed.contact_person.len(), 1);
+    let descriptor = &parsed.idp_sso_descriptors[0];
+    assert_eq!(descriptor.want_authn_requests_signed, Some(true));
+    assert_eq!(descriptor.single_sign_on_services.len(), 2);
+    assert_eq!(descriptor.single_logout_services.len(), 2);
+    assert_eq!(descriptor.name_id_formats.len(), 2);
+    assert_eq!(
+        descriptor.attributes[0].friendly_name.as_deref(),
+        Some("mail")
+    );
+    assert!(parsed.idp_scopes()[0].matches("example.com"));
+    assert!(parsed.idp_scopes()[0].matches("Example.COM"));
+    let key_uses: Vec<_> = descriptor
+        .key_descriptors
+        .iter()
+        .map(|descriptor| descriptor.key_use.as_deref().unwrap())
+        .collect();
+    assert_eq!(key_uses, vec!["signing", "encryption"]);
+
+    // an SP configured with the metadata uses the right endpoints and certificates
+    let sp = ServiceProvider {
+        idp_metadata: parsed,
+        ..ServiceProvider::default()
+    };
+    assert_eq!(
+        sp.sso_binding_location(HTTP_POST_BINDING).as_deref(),
+        Some("https://idp.example.com/sso")
+    );
+    assert_eq!(sp.idp_signing_certs().unwrap().unwrap().len(), 1);
+}
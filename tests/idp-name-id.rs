@@ -0,0 +1,185 @@
+use samael::clock::SystemClock;
+use samael::idp::error::Error;
+use samael::idp::name_id::{NameIdGenerator, Principal};
+use samael::idp::response_builder::AuthnResponseBuilder;
+use samael::idp::sp_extractor::SPMetadataExtractor;
+use samael::metadata::{EntityDescriptor, NameIdFormat, SpSsoDescriptor};
+use samael::schema::{AuthnRequest, NameIdPolicy};
+
+const IDP: &str = "https://idp.example.com";
+const SP: &str = "https://sp.example.com";
+const PERSISTENT: &str = "urn:oasis:names:tc:SAML:2.0:nameid-format:persistent";
+const TRANSIENT: &str = "urn:oasis:names:tc:SAML:2.0:nameid-format:transient";
+const EMAIL: &str = "urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress";
+
+fn generator() -> NameIdGenerator {
+    NameIdGenerator::new(IDP, b"a secret of the IdP".to_vec())
+}
+
+fn principal() -> Principal {
+    Principal {
+        user_id: "jdoe".to_string(),
+        email: Some("jdoe@example.com".to_string()),
+        ..Principal::default()
+    }
+}
+
+fn policy(format: &str) -> NameIdPolicy {
+    NameIdPolicy {
+        format: Some(format.to_string()),
+        ..NameIdPolicy::default()
+    }
+}
+
+#[test]
+fn test_persistent_name_id_is_pairwise() {
+    let generator = generator();
+    let name_id = generator
+        .generate(&principal(), SP, Some(&policy(PERSISTENT)), &[])
+        .unwrap();
+    assert_eq!(name_id.format.as_deref(), Some(PERSISTENT));
+    assert_eq!(name_id.name_qualifier.as_deref(), Some(IDP));
+    assert_eq!(name_id.sp_name_qualifier.as_deref(), Some(SP));
+    assert_ne!(name_id.value, "jdoe");
+
+    let again = generator
+        .generate(&principal(), SP, Some(&policy(PERSISTENT)), &[])
+        .unwrap();
+    assert_eq!(again.value, name_id.value);
+    let other_sp = generator
+        .generate(
+            &principal(),
+            "https://other.example.com",
+            Some(&policy(PERSISTENT)),
+            &[],
+        )
+        .unwrap();
+    assert_ne!(other_sp.value, name_id.value);
+    let other_key = NameIdGenerator::new(IDP, b"another secret".to_vec())
+        .generate(&principal(), SP, Some(&policy(PERSISTENT)), &[])
+        .unwrap();
+    assert_ne!(other_key.value, name_id.value);
+}
+
+#[test]
+fn test_format_preference() {
+    let generator = generator();
+    let transient = generator.generate(&principal(), SP, None, &[]).unwrap();
+    assert_eq!(transient.format.as_deref(), Some(TRANSIENT));
+    assert_ne!(
+        transient.value,
+        generator
+            .generate(&principal(), SP, None, &[])
+            .unwrap()
+            .value
+    );
+
+    // without a format in the request, the first format of the SP metadata the IdP supports
+    let sp = SPMetadataExtractor::from(EntityDescriptor {
+        entity_id: SP.to_string(),
+        sp_sso_descriptors: vec![SpSsoDescriptor {
+            name_id_formats: vec![
+                "urn:oasis:names:tc:SAML:1.1:nameid-format:unspecified".to_string(),
+                "urn:oasis:names:tc:SAML:2.0:nameid-format:kerberos".to_string(),
+                EMAIL.to_string(),
+            ],
+            ..SpSsoDescriptor::default()
+        }],
+        ..EntityDescriptor::default()
+    });
+    let request = AuthnRequest {
+        name_id_policy: Some(policy(
+            "urn:oasis:names:tc:SAML:1.1:nameid-format:unspecified",
+        )),
+        ..AuthnRequest::default()
+    };
+    let email = generator.for_request(&principal(), &request, &sp).unwrap();
+    assert_eq!(email.format.as_deref(), Some(EMAIL));
+    assert_eq!(email.value, "jdoe@example.com");
+    assert_eq!(email.name_qualifier, None);
+
+    let unspecified = generator
+        .with_default_format(NameIdFormat::UnspecifiedNameIDFormat)
+        .generate(&principal(), SP, None, &[])
+        .unwrap();
+    assert_eq!(unspecified.value, "jdoe");
+}
+
+#[test]
+fn test_invalid_name_id_policy() {
+    let generator = generator().with_supported_formats(vec![
+        NameIdFormat::PersistentNameIDFormat,
+        NameIdFormat::EmailAddressNameIDFormat,
+    ]);
+    let invalid = |result| matches!(result, Err(Error::InvalidNameIDPolicy { .. }));
+
+    assert!(invalid(generator.generate(
+        &principal(),
+        SP,
+        Some(&policy(TRANSIENT)),
+        &[]
+    )));
+
+    let no_email = Principal {
+        email: None,
+        ..principal()
+    };
+    assert!(invalid(generator.generate(
+        &no_email,
+        SP,
+        Some(&policy(EMAIL)),
+        &[]
+    )));
+
+    let no_create = NameIdPolicy {
+        allow_create: Some(false),
+        ..policy(PERSISTENT)
+    };
+    assert!(invalid(generator.generate(
+        &principal(),
+        SP,
+        Some(&no_create),
+        &[]
+    )));
+    let known = Principal {
+        has_persistent_id: true,
+        ..principal()
+    };
+    assert!(generator
+        .generate(&known, SP, Some(&no_create), &[])
+        .is_ok());
+
+    let affiliation = NameIdPolicy {
+        sp_name_qualifier: Some("https://affiliation.example.com".to_string()),
+        ..policy(PERSISTENT)
+    };
+    assert!(invalid(generator.generate(
+        &principal(),
+        SP,
+        Some(&affiliation),
+        &[]
+    )));
+}
+
+#[test]
+fn test_response_subject() {
+    let name_id = generator()
+        .generate(&principal(), SP, Some(&policy(PERSISTENT)), &[])
+        .unwrap();
+    let response = AuthnResponseBuilder::default()
+        .issuer(IDP)
+        .destination("https://sp.example.com/acs")
+        .subject_name_id(&name_id)
+        .audiences(vec![SP.to_string()])
+        .build()
+        .unwrap()
+        .to_response(&SystemClock);
+    let subject = response.assertions[0]
+        .subject
+        .as_ref()
+        .unwrap()
+        .name_id
+        .as_ref()
+        .unwrap();
+    assert_eq!(subject, &name_id);
+}
@@ -0,0 +1,301 @@
+#![cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
+
+use chrono::{Duration, TimeZone, Utc};
+use openssl::asn1::Asn1Time;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::x509::{X509Name, X509};
+use samael::clock::ManualClock;
+use samael::idp::error::Error;
+use samael::idp::request_validator::AuthnRequestValidator;
+use samael::idp::sp_extractor::{BindType, SPMetadataExtractor};
+use samael::metadata::{IndexedEndpoint, HTTP_POST_BINDING};
+use samael::schema::AuthnRequest;
+use samael::service_provider::ServiceProvider;
+use std::sync::Arc;
+
+const SP: &str = "https://sp.example.com";
+const SSO_URL: &str = "https://idp.example.com/sso";
+
+fn service_provider(clock: Arc<ManualClock>) -> ServiceProvider {
+    let key = Rsa::generate(2048).unwrap();
+    let private_key = PKey::from_rsa(key.clone()).unwrap();
+    let mut name = X509Name::builder().unwrap();
+    name.append_entry_by_nid(Nid::COMMONNAME, SP).unwrap();
+    let name = name.build();
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&private_key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(365).unwrap())
+        .unwrap();
+    builder.sign(&private_key, MessageDigest::sha256()).unwrap();
+
+    ServiceProvider {
+        entity_id: SP.to_string(),
+        key: Some(key),
+        certificate: Some(builder.build()),
+        acs_url: Some("https://sp.example.com/acs".to_string()),
+        clock,
+        ..ServiceProvider::default()
+    }
+}
+
+fn validator(
+    sp: &ServiceProvider,
+    clock: Arc<ManualClock>,
+) -> AuthnRequestValidator<Vec<SPMetadataExtractor>> {
+    let mut metadata = sp.metadata().unwrap();
+    let descriptor = &mut metadata.sp_sso_descriptors[0];
+    descriptor.authn_requests_signed = Some(true);
+    descriptor
+        .assertion_consumer_services
+        .push(IndexedEndpoint {
+            binding: HTTP_POST_BINDING.to_string(),
+            location: "https://sp.example.com/acs/2".to_string(),
+            index: 2,
+            ..IndexedEndpoint::default()
+        });
+    let mut validator = AuthnRequestValidator::new(
+        vec![SPMetadataExtractor::from(metadata)],
+        vec![SSO_URL.to_string()],
+    );
+    validator.clock = clock;
+    validator
+}
+
+fn clock() -> Arc<ManualClock> {
+    Arc::new(ManualClock::new(
+        Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+    ))
+}
+
+#[test]
+fn test_validate_signed_request() {
+    let clock = clock();
+    let sp = service_provider(clock.clone());
+    let validator = validator(&sp, clock.clone());
+    let request = sp.make_authentication_request(SSO_URL).unwrap();
+    let xml = sp.sign_authentication_request(&request).unwrap();
+
+    clock.advance(Duration::seconds(30));
+    let validated = validator.validate_xml(&xml).unwrap();
+    assert_eq!(validated.sp_entity_id, SP);
+    assert_eq!(validated.acs.url, "https://sp.example.com/acs");
+    assert_eq!(validated.acs.bind_type, BindType::Post);
+    assert!(validated.signed);
+    assert_eq!(validated.request.id, request.id);
+
+    // the signature covers the whole request
+    let tampered = xml.replace(
+        "https://sp.example.com/acs",
+        "https://attacker.example.com/acs",
+    );
+    assert!(matches!(
+        validator.validate_xml(&tampered),
+        Err(Error::VerificationError { .. })
+    ));
+}
+
+#[test]
+fn test_reject_unsigned_and_unknown_requests() {
+    let clock = clock();
+    let sp = service_provider(clock.clone());
+    let validator = validator(&sp, clock.clone());
+    let request = sp.make_authentication_request(SSO_URL).unwrap();
+
+    assert!(matches!(
+        validator.validate_xml(&request.as_xml().unwrap()),
+        Err(Error::AuthnRequestNotSigned)
+    ));
+
+    // a key which is not in the SP metadata
+    let other = service_provider(clock.clone());
+    let xml = other.sign_authentication_request(&request).unwrap();
+    assert!(matches!(
+        validator.validate_xml(&xml),
+        Err(Error::VerificationError { .. })
+    ));
+
+    let stranger = ServiceProvider {
+        entity_id: "https://stranger.example.com".to_string(),
+        ..service_provider(clock.clone())
+    };
+    let xml = stranger
+        .sign_authentication_request(&stranger.make_authentication_request(SSO_URL).unwrap())
+        .unwrap();
+    assert!(matches!(
+        validator.validate_xml(&xml),
+        Err(Error::UnknownServiceProvider { entity_id }) if entity_id == "https://stranger.example.com"
+    ));
+}
+
+#[test]
+fn test_assertion_consumer_service() {
+    let clock = clock();
+    let sp = service_provider(clock.clone());
+    let validator = validator(&sp, clock.clone());
+
+    let mut request = sp.make_authentication_request(SSO_URL).unwrap();
+    request.assertion_consumer_service_url = None;
+    request.protocol_binding = None;
+    request.assertion_consumer_service_index = Some(2);
+    let xml = sp.sign_authentication_request(&request).unwrap();
+    assert_eq!(
+        validator.validate_xml(&xml).unwrap().acs.url,
+        "https://sp.example.com/acs/2"
+    );
+
+    request.assertion_consumer_service_index = Some(3);
+    let xml = sp.sign_authentication_request(&request).unwrap();
+    assert!(matches!(
+        validator.validate_xml(&xml),
+        Err(Error::UnregisteredAcs {
+            url: None,
+            index: Some(3)
+        })
+    ));
+
+    request.assertion_consumer_service_index = None;
+    request.assertion_consumer_service_url = Some("https://attacker.example.com/acs".to_string());
+    let xml = sp.sign_authentication_request(&request).unwrap();
+    assert!(matches!(
+        validator.validate_xml(&xml),
+        Err(Error::UnregisteredAcs {
+            url: Some(_),
+            index: None
+        })
+    ));
+}
+
+#[test]
+fn test_issue_instant_and_destination() {
+    let clock = clock();
+    let sp = service_provider(clock.clone());
+    let validator = validator(&sp, clock.clone());
+    let xml = sp
+        .sign_authentication_request(&sp.make_authentication_request(SSO_URL).unwrap())
+        .unwrap();
+
+    clock.advance(Duration::seconds(91));
+    assert!(matches!(
+        validator.validate_xml(&xml),
+        Err(Error::AuthnRequestExpired { time }) if time == "2024-01-01T00:01:30Z"
+    ));
+
+    clock.set(Utc.with_ymd_and_hms(2023, 12, 31, 23, 56, 59).unwrap());
+    assert!(matches!(
+        validator.validate_xml(&xml),
+        Err(Error::AuthnRequestNotYetValid { time }) if time == "2023-12-31T23:57:00Z"
+    ));
+
+    clock.set(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+    let xml = sp
+        .sign_authentication_request(
+            &sp.make_authentication_request("https://other-idp.example.com/sso")
+                .unwrap(),
+        )
+        .unwrap();
+    assert!(matches!(
+        validator.validate_xml(&xml),
+        Err(Error::DestinationMismatch {
+            destination: Some(_)
+        })
+    ));
+}
+
+#[test]
+fn test_validate_signed_redirect() {
+    let clock = clock();
+    let sp = service_provider(clock.clone());
+    let validator = validator(&sp, clock.clone());
+    let request = sp.make_authentication_request(SSO_URL).unwrap();
+    let url = sp.signed_redirect(&request, Some("state")).unwrap();
+    let query = url.query().unwrap();
+
+    let validated = validator.validate_redirect(query).unwrap();
+    assert_eq!(validated.sp_entity_id, SP);
+    assert!(validated.signed);
+    assert_eq!(validated.request.id, request.id);
+    assert_eq!(validated.relay_state.as_deref(), Some("state"));
+
+    // the signature covers the RelayState and the request
+    let tampered = query.replace("RelayState=state", "RelayState=other");
+    assert!(matches!(
+        validator.validate_redirect(&tampered),
+        Err(Error::InvalidRedirectSignature { .. })
+    ));
+    let other = sp
+        .signed_redirect(
+            &sp.make_authentication_request(SSO_URL).unwrap(),
+            Some("state"),
+        )
+        .unwrap();
+    let signature = |query: &str| query[query.find("&Signature=").unwrap()..].to_string();
+    let swapped = query.replace(&signature(query), &signature(other.query().unwrap()));
+    assert!(matches!(
+        validator.validate_redirect(&swapped),
+        Err(Error::InvalidRedirectSignature { .. })
+    ));
+
+    // a key which is not in the SP metadata
+    let stranger = service_provider(clock.clone());
+    let url = stranger.signed_redirect(&request, None).unwrap();
+    assert!(matches!(
+        validator.validate_redirect(url.query().unwrap()),
+        Err(Error::InvalidRedirectSignature { .. })
+    ));
+
+    let url = request.redirect(Some("state")).unwrap();
+    assert!(matches!(
+        validator.validate_redirect(url.query().unwrap()),
+        Err(Error::AuthnRequestNotSigned)
+    ));
+    assert!(matches!(
+        validator.validate_redirect("RelayState=state"),
+        Err(Error::InvalidRedirectQuery { .. })
+    ));
+}
+
+#[test]
+fn test_reject_duplicate_redirect_parameters() {
+    let clock = clock();
+    let sp = service_provider(clock.clone());
+    let validator = validator(&sp, clock.clone());
+    let url = sp
+        .signed_redirect(
+            &sp.make_authentication_request(SSO_URL).unwrap(),
+            Some("state"),
+        )
+        .unwrap();
+    let query = url.query().unwrap();
+
+    // a forged request in front of a signed query, under a percent-encoded name
+    let forged = AuthnRequest {
+        id: "EVIL_ID".to_string(),
+        ..sp.make_authentication_request(SSO_URL).unwrap()
+    };
+    let forged_url = forged.redirect(None).unwrap();
+    let forged_request = forged_url
+        .query()
+        .unwrap()
+        .strip_prefix("SAMLRequest=")
+        .unwrap();
+    for prefix in [
+        format!("SAML%52equest={}&", forged_request),
+        "Relay%53tate=evil&".to_string(),
+        format!("SAMLResponse={}&", forged_request),
+    ] {
+        assert!(matches!(
+            validator.validate_redirect(&format!("{}{}", prefix, query)),
+            Err(Error::InvalidRedirectQuery { .. })
+        ));
+    }
+}
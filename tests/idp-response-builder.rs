@@ -0,0 +1,394 @@
+#![cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
+
+use chrono::{Duration, TimeZone, Utc};
+use openssl::asn1::Asn1Time;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::x509::{X509Name, X509};
+use samael::attribute::{
+    Attribute, TypedValue, EDU_PERSON_ENTITLEMENT_URI, EDU_PERSON_TARGETED_ID_URI, NAME_FORMAT_URI,
+};
+use samael::clock::SystemClock;
+use samael::crypto;
+use samael::dom::Document;
+use samael::idp::key_set::{KeySet, SigningKey};
+use samael::idp::response_builder::{
+    AuthnResponseBuilder, ErrorResponse, ErrorResponseBuilder, ResponseAttribute, SignedParts,
+    AUTHN_CONTEXT_PASSWORD_PROTECTED_TRANSPORT,
+};
+use samael::idp::sp_extractor::RequiredAttribute;
+use samael::idp::IdentityProvider;
+use samael::schema::{
+    AuthnRequest, NameId, Response, StatusCode, SubjectLocality, STATUS_NO_PASSIVE,
+    STATUS_REQUESTER, STATUS_RESPONDER,
+};
+use samael::service_provider::{Error, ServiceProvider};
+use samael::signer;
+use samael::utils::UtcDateTime;
+
+fn identity_provider() -> (IdentityProvider, X509) {
+    let private_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509Name::builder().unwrap();
+    name.append_entry_by_nid(Nid::COMMONNAME, "https://idp.example.com")
+        .unwrap();
+    let name = name.build();
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&private_key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(365).unwrap())
+        .unwrap();
+    builder.sign(&private_key, MessageDigest::sha256()).unwrap();
+    let certificate = builder.build();
+    let signing_key = SigningKey::new(private_key, certificate.clone()).unwrap();
+    (
+        IdentityProvider::from_key_set(KeySet::new(signing_key)),
+        certificate,
+    )
+}
+
+fn response_builder() -> AuthnResponseBuilder {
+    let mut builder = AuthnResponseBuilder::default();
+    builder
+        .issuer("https://idp.example.com")
+        .destination("https://sp.example.com/acs")
+        .in_response_to(Some("request-id".to_string()))
+        .name_id("testuser@example.com");
+    builder
+}
+
+fn signature_paths(xml: &str, certificate: &X509) -> (Vec<String>, Vec<String>) {
+    let report = crypto::verify_signatures(xml, std::slice::from_ref(certificate)).unwrap();
+    let paths = report
+        .signatures
+        .into_iter()
+        .map(|signature| signature.path)
+        .collect();
+    (paths, report.removed_elements)
+}
+
+#[test]
+fn test_response_fields() {
+    let issued = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+    let response = response_builder()
+        .name_id_format(Some(
+            "urn:oasis:names:tc:SAML:2.0:nameid-format:persistent".to_string(),
+        ))
+        .name_qualifier(Some("https://idp.example.com".to_string()))
+        .sp_name_qualifier(Some("https://sp.example.com".to_string()))
+        .audiences(vec![
+            "https://sp.example.com".to_string(),
+            "https://other.example.com".to_string(),
+        ])
+        .not_before(Some(UtcDateTime(issued - Duration::minutes(1))))
+        .not_on_or_after(Some(UtcDateTime(issued + Duration::minutes(5))))
+        .authn_instant(Some(UtcDateTime(issued - Duration::minutes(10))))
+        .authn_context_class(Some(AUTHN_CONTEXT_PASSWORD_PROTECTED_TRANSPORT.to_string()))
+        .session_index(Some("session-1".to_string()))
+        .session_not_on_or_after(Some(UtcDateTime(issued + Duration::hours(8))))
+        .subject_locality(Some(SubjectLocality {
+            address: Some("192.0.2.1".to_string()),
+            dns_name: None,
+        }))
+        .build()
+        .unwrap()
+        .to_response(&SystemClock);
+
+    let xml = yaserde::ser::to_string(&response).unwrap();
+    let response: Response = xml.parse().unwrap();
+    let assertion = response.assertions.into_iter().next().unwrap();
+
+    let subject = assertion.subject.unwrap();
+    let name_id = subject.name_id.unwrap();
+    assert_eq!(name_id.value, "testuser@example.com");
+    assert_eq!(
+        name_id.format.as_deref(),
+        Some("urn:oasis:names:tc:SAML:2.0:nameid-format:persistent")
+    );
+    assert_eq!(
+        name_id.name_qualifier.as_deref(),
+        Some("https://idp.example.com")
+    );
+    assert_eq!(
+        name_id.sp_name_qualifier.as_deref(),
+        Some("https://sp.example.com")
+    );
+    let confirmation_data = subject.subject_confirmations[0]
+        .subject_confirmation_data
+        .clone()
+        .unwrap();
+    assert_eq!(
+        confirmation_data.recipient.as_deref(),
+        Some("https://sp.example.com/acs")
+    );
+    assert_eq!(
+        confirmation_data.not_on_or_after.unwrap().0,
+        issued + Duration::minutes(5)
+    );
+
+    let conditions = assertion.conditions.unwrap();
+    assert_eq!(
+        conditions.not_before.unwrap().0,
+        issued - Duration::minutes(1)
+    );
+    assert_eq!(
+        conditions.not_on_or_after.unwrap().0,
+        issued + Duration::minutes(5)
+    );
+    assert_eq!(
+        conditions.audience_restrictions[0].audience,
+        vec!["https://sp.example.com", "https://other.example.com"]
+    );
+
+    let statement = &assertion.authn_statements[0];
+    assert_eq!(
+        statement.authn_instant.clone().unwrap().0,
+        issued - Duration::minutes(10)
+    );
+    assert_eq!(statement.session_index.as_deref(), Some("session-1"));
+    assert_eq!(
+        statement.session_not_on_or_after.clone().unwrap().0,
+        issued + Duration::hours(8)
+    );
+    assert_eq!(
+        statement
+            .subject_locality
+            .clone()
+            .unwrap()
+            .address
+            .as_deref(),
+        Some("192.0.2.1")
+    );
+    assert_eq!(
+        statement
+            .authn_context
+            .clone()
+            .unwrap()
+            .value
+            .unwrap()
+            .value
+            .as_deref(),
+        Some(AUTHN_CONTEXT_PASSWORD_PROTECTED_TRANSPORT)
+    );
+}
+
+#[test]
+fn test_sign_response_assertion_or_both() {
+    let (idp, certificate) = identity_provider();
+
+    let signed = |signed_parts| {
+        let response = response_builder()
+            .audiences(vec!["https://sp.example.com".to_string()])
+            .signed_parts(signed_parts)
+            .build()
+            .unwrap();
+        idp.signed_response(&response).unwrap()
+    };
+
+    let (paths, removed) = signature_paths(&signed(SignedParts::Response), &certificate);
+    assert_eq!(paths, vec!["/samlp:Response/ds:Signature"]);
+    assert!(removed.is_empty());
+
+    // only the assertion is covered, the rest of the response is not trusted
+    let (paths, removed) = signature_paths(&signed(SignedParts::Assertion), &certificate);
+    assert_eq!(paths, vec!["/samlp:Response/saml:Assertion/ds:Signature"]);
+    assert!(!removed.is_empty());
+    assert!(removed
+        .iter()
+        .all(|path| !path.starts_with("/samlp:Response/saml:Assertion")));
+
+    let (paths, removed) = signature_paths(&signed(SignedParts::Both), &certificate);
+    assert_eq!(
+        paths,
+        vec![
+            "/samlp:Response/ds:Signature",
+            "/samlp:Response/saml:Assertion/ds:Signature"
+        ]
+    );
+    assert!(removed.is_empty());
+}
+
+#[test]
+fn test_multi_valued_and_typed_attributes() {
+    let uri = |name: &str| RequiredAttribute {
+        name: name.to_string(),
+        format: Some(NAME_FORMAT_URI.to_string()),
+    };
+    let targeted_id = NameId {
+        format: Some("urn:oasis:names:tc:SAML:2.0:nameid-format:persistent".to_string()),
+        name_qualifier: Some("https://idp.example.com".to_string()),
+        sp_name_qualifier: Some("https://sp.example.com".to_string()),
+        value: "c2f1a5e0".to_string(),
+        ..Default::default()
+    };
+    let expires = Utc.with_ymd_and_hms(2030, 6, 1, 0, 0, 0).unwrap();
+    let attributes = [
+        ResponseAttribute {
+            required_attribute: uri(EDU_PERSON_ENTITLEMENT_URI),
+            friendly_name: Some("eduPersonEntitlement".to_string()),
+            values: vec!["urn:example:staff".into(), "urn:example:library".into()],
+        },
+        ResponseAttribute::new(uri(EDU_PERSON_TARGETED_ID_URI), targeted_id.clone()),
+        ResponseAttribute::new(uri("urn:example:verified"), true),
+        ResponseAttribute::new(uri("urn:example:quota"), 1024),
+        ResponseAttribute::new(uri("urn:example:expires"), expires),
+    ];
+    let response = response_builder()
+        .attributes(attributes.iter().map(Attribute::from).collect::<Vec<_>>())
+        .build()
+        .unwrap()
+        .to_response(&SystemClock);
+
+    let xml = yaserde::ser::to_string(&response).unwrap();
+    assert!(xml.contains(r#"xsi:type="xs:boolean">true<"#));
+    let response: Response = xml.parse().unwrap();
+    let assertion = response.assertions.into_iter().next().unwrap();
+
+    assert_eq!(
+        assertion.attribute_values(EDU_PERSON_ENTITLEMENT_URI),
+        vec!["urn:example:staff", "urn:example:library"]
+    );
+    assert_eq!(
+        assertion.attributes_by_uri(EDU_PERSON_ENTITLEMENT_URI)[0]
+            .friendly_name
+            .as_deref(),
+        Some("eduPersonEntitlement")
+    );
+    assert_eq!(
+        assertion
+            .typed_attribute_value(EDU_PERSON_TARGETED_ID_URI)
+            .unwrap(),
+        TypedValue::NameId(targeted_id)
+    );
+    assert_eq!(
+        assertion
+            .typed_attribute_value("urn:example:verified")
+            .unwrap()
+            .as_bool(),
+        Some(true)
+    );
+    assert_eq!(
+        assertion
+            .typed_attribute_value("urn:example:quota")
+            .unwrap()
+            .as_integer(),
+        Some(1024)
+    );
+    assert_eq!(
+        assertion
+            .typed_attribute_value("urn:example:expires")
+            .unwrap()
+            .as_date_time(),
+        Some(expires)
+    );
+}
+
+#[test]
+fn test_verify_several_signed_assertions() {
+    let (idp, certificate) = identity_provider();
+    let key_set = idp.key_set();
+    let mut response = response_builder()
+        .audiences(vec!["https://sp.example.com".to_string()])
+        .build()
+        .unwrap()
+        .to_response(&SystemClock);
+    // an attribute-only assertion next to the authentication assertion
+    let mut attributes = response.assertions[0].clone();
+    attributes.id = "_attributes".to_string();
+    attributes.authn_statements.clear();
+    response.assertions.push(attributes);
+
+    let signed_xml = |signed_assertions: usize| {
+        let xml = yaserde::ser::to_string(&response).unwrap();
+        let mut document = Document::parse(&xml).unwrap();
+        let root = document.root_element().unwrap();
+        let assertions: Vec<_> = document
+            .child_elements(root)
+            .filter(|node| document.element(*node).unwrap().local_name == "Assertion")
+            .take(signed_assertions)
+            .collect();
+        for assertion in assertions {
+            signer::sign_element(&mut document, assertion, key_set.active().signer()).unwrap();
+        }
+        document.to_xml()
+    };
+
+    let (paths, removed) = signature_paths(&signed_xml(2), &certificate);
+    assert_eq!(paths.len(), 2);
+    assert!(removed
+        .iter()
+        .all(|path| !path.starts_with("/samlp:Response/saml:Assertion")));
+
+    // an unsigned assertion must not be slipped next to a signed one
+    assert!(matches!(
+        crypto::verify_signatures(&signed_xml(1), std::slice::from_ref(&certificate)),
+        Err(crypto::Error::MultipleAssertions)
+    ));
+}
+
+#[test]
+fn test_signed_error_response() {
+    let (idp, certificate) = identity_provider();
+    let request = AuthnRequest {
+        id: "request-id".to_string(),
+        assertion_consumer_service_url: Some("https://sp.example.com/acs".to_string()),
+        ..AuthnRequest::default()
+    };
+    let error_response = ErrorResponse {
+        status_message: Some("The user is not logged in".to_string()),
+        ..ErrorResponse::for_request(
+            &request,
+            "https://idp.example.com",
+            StatusCode::with_sub_code(STATUS_REQUESTER, STATUS_NO_PASSIVE),
+        )
+    };
+    let xml = idp.signed_error_response(&error_response).unwrap();
+
+    let (paths, removed) = signature_paths(&xml, &certificate);
+    assert_eq!(paths, vec!["/samlp:Response/ds:Signature"]);
+    assert!(removed.is_empty());
+    let response: Response = xml.parse().unwrap();
+    assert!(response.assertions.is_empty());
+    assert_eq!(response.in_response_to.as_deref(), Some("request-id"));
+    assert_eq!(
+        response.destination.as_deref(),
+        Some("https://sp.example.com/acs")
+    );
+    assert!(response.status.has_code(STATUS_NO_PASSIVE));
+
+    let sp = ServiceProvider {
+        acs_url: Some("https://sp.example.com/acs".to_string()),
+        idp_metadata: idp
+            .metadata("https://idp.example.com", "https://idp.example.com/sso")
+            .unwrap(),
+        ..ServiceProvider::default()
+    };
+    match sp.parse_xml_response(&xml, &["request-id"]) {
+        Err(Error::ResponseBadStatusCode { status }) => {
+            assert!(status.is_requester_error());
+            assert!(status.is_no_passive());
+            assert_eq!(status.message.as_deref(), Some("The user is not logged in"));
+        }
+        result => panic!("unexpected result {:?}", result),
+    }
+
+    // Responder is the default top-level code
+    let response = ErrorResponseBuilder::default()
+        .issuer("https://idp.example.com")
+        .destination("https://sp.example.com/acs")
+        .build()
+        .unwrap()
+        .to_response(&SystemClock);
+    assert_eq!(
+        response.status.status_code,
+        StatusCode::new(STATUS_RESPONDER)
+    );
+    assert_eq!(response.status.status_message, None);
+}
@@ -2,10 +2,12 @@
 
 use chrono::prelude::*;
 use samael::crypto::verify_signed_xml;
+use samael::idp::key_set::{KeySet, SigningKey};
+use samael::idp::metadata::IdpMetadataBuilder;
 use samael::idp::response_builder::ResponseAttribute;
 use samael::idp::sp_extractor::{RequiredAttribute, SPMetadataExtractor};
 use samael::idp::verified_request::UnverifiedAuthnRequest;
-use samael::idp::{CertificateParams, IdentityProvider};
+use samael::idp::{CertificateParams, IdentityProvider, KeyType};
 use samael::service_provider::ServiceProvider;
 
 #[test]
@@ -80,12 +82,14 @@ fn test_signed_response() {
 
     let attrs = attrs
         .into_iter()
-        .map(|attr| ResponseAttribute {
-            required_attribute: RequiredAttribute {
-                name: attr.1.to_string(),
-                format: Some(attr.0.to_string()),
-            },
-            value: attr.2,
+        .map(|attr| {
+            ResponseAttribute::new(
+                RequiredAttribute {
+                    name: attr.1.to_string(),
+                    format: Some(attr.0.to_string()),
+                },
+                attr.2,
+            )
         })
         .collect::<Vec<ResponseAttribute>>();
 
@@ -107,6 +111,70 @@ fn test_signed_response() {
         .expect("verification failed");
 }
 
+#[test]
+fn test_sign_response_with_active_key() {
+    let signing_key = |common_name: &str| {
+        let idp = IdentityProvider::generate_new(KeyType::Rsa2048).unwrap();
+        let cert = idp
+            .create_certificate(&CertificateParams {
+                common_name,
+                issuer_name: common_name,
+                days_until_expiration: 365,
+            })
+            .unwrap();
+        SigningKey::new(
+            idp.key_set().active().private_key().unwrap().clone(),
+            openssl::x509::X509::from_der(&cert).unwrap(),
+        )
+        .unwrap()
+    };
+    let (active, next) = (signing_key("active"), signing_key("next"));
+    let idp = IdentityProvider::from_key_set(KeySet::new(active).with_next(next.clone()));
+    idp.rotate_keys().unwrap();
+
+    let response = idp
+        .sign_response(
+            "testuser@example.com",
+            "https://sp.example.com/audience",
+            "https://sp.example.com/acs",
+            "https://idp.example.com",
+            "",
+            &[],
+        )
+        .expect("failed to created and sign response");
+    let out_xml = yaserde::ser::to_string(&response).expect("failed to serialize response xml");
+    let next_cert = next.certificate().unwrap().to_der().unwrap();
+    verify_signed_xml(out_xml.as_bytes(), &next_cert, Some("ID")).expect("verification failed");
+}
+
+#[test]
+fn test_signed_idp_metadata() {
+    let idp = IdentityProvider::generate_new(KeyType::Rsa2048).unwrap();
+    let cert = idp
+        .create_certificate(&CertificateParams {
+            common_name: "https://idp.example.com",
+            issuer_name: "https://idp.example.com",
+            days_until_expiration: 365,
+        })
+        .unwrap();
+    let idp = IdentityProvider::from_key_set(KeySet::new(
+        SigningKey::new(
+            idp.key_set().active().private_key().unwrap().clone(),
+            openssl::x509::X509::from_der(&cert).unwrap(),
+        )
+        .unwrap(),
+    ));
+    let metadata = IdpMetadataBuilder::default()
+        .entity_id("https://idp.example.com")
+        .build()
+        .unwrap();
+
+    let signed_xml = idp
+        .signed_metadata(&metadata)
+        .expect("failed to sign metadata");
+    verify_signed_xml(signed_xml.as_bytes(), &cert, Some("ID")).expect("verification failed");
+}
+
 #[test]
 fn test_signed_response_threads() {
     let verify = move || {
@@ -231,7 +299,7 @@ fn test_do_not_accept_signed_with_wrong_key() {
 
     assert!(matches!(
         resp.err().unwrap(),
-        samael::service_provider::Error::FailedToValidateSignature
+        samael::service_provider::Error::FailedToValidateSignature { .. }
     ));
 }
 
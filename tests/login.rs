@@ -0,0 +1,106 @@
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{Duration, TimeZone, Utc};
+use samael::clock::ManualClock;
+use samael::metadata::EntityDescriptor;
+use samael::service_provider::ServiceProvider;
+use std::sync::Arc;
+
+const IDP: &str = "https://idp.example.com";
+const SP: &str = "https://sp.example.com";
+
+const RESPONSE: &str = r#"<samlp:Response xmlns:samlp="urn:oasis:names:tc:SAML:2.0:protocol" xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion" ID="_response" InResponseTo="request-id" Version="2.0" IssueInstant="2024-01-01T00:00:00Z">
+  <saml:Issuer>https://idp.example.com</saml:Issuer>
+  <samlp:Status><samlp:StatusCode Value="urn:oasis:names:tc:SAML:2.0:status:Success"/></samlp:Status>
+  <saml:Assertion ID="_assertion" Version="2.0" IssueInstant="2024-01-01T00:00:00Z">
+    <saml:Issuer>https://idp.example.com</saml:Issuer>
+    <saml:Subject>
+      <saml:NameID Format="urn:oasis:names:tc:SAML:2.0:nameid-format:persistent" NameQualifier="https://idp.example.com" SPNameQualifier="https://sp.example.com">c2f1a5e0</saml:NameID>
+      <saml:SubjectConfirmation Method="urn:oasis:names:tc:SAML:2.0:cm:bearer"/>
+    </saml:Subject>
+    <saml:Conditions><saml:AudienceRestriction><saml:Audience>https://sp.example.com</saml:Audience></saml:AudienceRestriction></saml:Conditions>
+    <saml:AuthnStatement AuthnInstant="2023-12-31T23:55:00Z" SessionIndex="session-1" SessionNotOnOrAfter="2024-01-01T08:00:00Z">
+      <saml:AuthnContext><saml:AuthnContextClassRef>urn:oasis:names:tc:SAML:2.0:ac:classes:PasswordProtectedTransport</saml:AuthnContextClassRef></saml:AuthnContext>
+    </saml:AuthnStatement>
+    <saml:AttributeStatement>
+      <saml:Attribute Name="mail"><saml:AttributeValue>jdoe@example.com</saml:AttributeValue></saml:Attribute>
+      <saml:Attribute Name="groups"><saml:AttributeValue>staff</saml:AttributeValue><saml:AttributeValue>admins</saml:AttributeValue></saml:Attribute>
+    </saml:AttributeStatement>
+  </saml:Assertion>
+</samlp:Response>"#;
+
+fn service_provider(clock: Arc<ManualClock>) -> ServiceProvider {
+    // without signing certificates in the IdP metadata, responses are not verified
+    ServiceProvider {
+        entity_id: SP.to_string(),
+        idp_metadata: EntityDescriptor {
+            entity_id: IDP.to_string(),
+            ..EntityDescriptor::default()
+        },
+        clock,
+        ..ServiceProvider::default()
+    }
+}
+
+#[test]
+fn test_login_fields() {
+    let clock = Arc::new(ManualClock::new(
+        Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 30).unwrap(),
+    ));
+    let sp = service_provider(clock.clone());
+    let login = sp
+        .parse_login(
+            &general_purpose::STANDARD.encode(RESPONSE),
+            Some("/dashboard"),
+            &["request-id"],
+        )
+        .unwrap();
+
+    assert_eq!(login.idp_entity_id, IDP);
+    let name_id = login.name_id.clone().unwrap();
+    assert_eq!(name_id.value, "c2f1a5e0");
+    assert_eq!(
+        name_id.format.as_deref(),
+        Some("urn:oasis:names:tc:SAML:2.0:nameid-format:persistent")
+    );
+    assert_eq!(name_id.name_qualifier.as_deref(), Some(IDP));
+    assert_eq!(name_id.sp_name_qualifier.as_deref(), Some(SP));
+    assert_eq!(login.session_index.as_deref(), Some("session-1"));
+    assert_eq!(
+        login.authn_instant,
+        Some(Utc.with_ymd_and_hms(2023, 12, 31, 23, 55, 0).unwrap())
+    );
+    assert_eq!(
+        login.authn_context_class.as_deref(),
+        Some("urn:oasis:names:tc:SAML:2.0:ac:classes:PasswordProtectedTransport")
+    );
+    assert_eq!(login.in_response_to.as_deref(), Some("request-id"));
+    assert_eq!(login.relay_state.as_deref(), Some("/dashboard"));
+    assert_eq!(login.attribute_values("mail"), ["jdoe@example.com"]);
+    assert_eq!(login.attribute_values("groups"), ["staff", "admins"]);
+    assert!(login.attribute_values("uid").is_empty());
+    assert_eq!(login.response_xml, RESPONSE);
+
+    assert!(!login.is_session_expired(clock.as_ref()));
+    clock.advance(Duration::hours(8));
+    assert!(login.is_session_expired(clock.as_ref()));
+}
+
+#[test]
+fn test_logout_request_for_login() {
+    let sp = service_provider(Arc::new(ManualClock::new(
+        Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 30).unwrap(),
+    )));
+    let login = sp.parse_xml_login(RESPONSE, None, &["request-id"]).unwrap();
+
+    let request = sp.make_logout_request(&login, "https://idp.example.com/slo");
+    assert_eq!(
+        request.destination.as_deref(),
+        Some("https://idp.example.com/slo")
+    );
+    assert_eq!(
+        request.issuer.unwrap().value.as_deref(),
+        Some("https://sp.example.com")
+    );
+    assert_eq!(request.name_id, login.name_id);
+    assert_eq!(request.session_index.as_deref(), Some("session-1"));
+}
@@ -0,0 +1,245 @@
+use samael::clock::ManualClock;
+use samael::metadata::EntityDescriptor;
+use samael::schema::Response;
+use samael::service_provider::{Error, ServiceProvider};
+use std::sync::Arc;
+
+const IDP: &str = "https://idp.example.com";
+const SP: &str = "https://sp.example.com";
+
+fn service_provider() -> ServiceProvider {
+    // without signing certificates in the IdP metadata, responses are not verified
+    ServiceProvider {
+        entity_id: SP.to_string(),
+        acs_url: Some(format!("{}/acs", SP)),
+        idp_metadata: EntityDescriptor {
+            entity_id: IDP.to_string(),
+            ..EntityDescriptor::default()
+        },
+        clock: Arc::new(ManualClock::new("2024-01-01T00:00:30Z".parse().unwrap())),
+        ..ServiceProvider::default()
+    }
+}
+
+fn subject(name_id: &str, bearer: bool) -> String {
+    let confirmation = if bearer {
+        r#"<saml:SubjectConfirmation Method="urn:oasis:names:tc:SAML:2.0:cm:bearer"/>"#
+    } else {
+        ""
+    };
+    format!(
+        r#"<saml:Subject><saml:NameID Format="urn:oasis:names:tc:SAML:2.0:nameid-format:persistent">{}</saml:NameID>{}</saml:Subject>"#,
+        name_id, confirmation
+    )
+}
+
+fn assertion(id: &str, subject: &str, authn: bool, attribute: (&str, &str)) -> String {
+    let authn_statement = if authn {
+        r#"<saml:AuthnStatement AuthnInstant="2024-01-01T00:00:00Z"><saml:AuthnContext><saml:AuthnContextClassRef>urn:oasis:names:tc:SAML:2.0:ac:classes:PasswordProtectedTransport</saml:AuthnContextClassRef></saml:AuthnContext></saml:AuthnStatement>"#
+    } else {
+        ""
+    };
+    format!(
+        r#"<saml:Assertion ID="{}" Version="2.0" IssueInstant="2024-01-01T00:00:00Z">
+    <saml:Issuer>{}</saml:Issuer>
+    {}
+    <saml:Conditions><saml:AudienceRestriction><saml:Audience>{}</saml:Audience></saml:AudienceRestriction></saml:Conditions>
+    {}
+    <saml:AttributeStatement><saml:Attribute Name="{}"><saml:AttributeValue>{}</saml:AttributeValue></saml:Attribute></saml:AttributeStatement>
+  </saml:Assertion>"#,
+        id, IDP, subject, SP, authn_statement, attribute.0, attribute.1
+    )
+}
+
+fn response(assertions: &[String]) -> String {
+    format!(
+        r#"<samlp:Response xmlns:samlp="urn:oasis:names:tc:SAML:2.0:protocol" xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion" ID="_response" InResponseTo="request-id" Destination="{}/acs" Version="2.0" IssueInstant="2024-01-01T00:00:00Z">
+  <saml:Issuer>{}</saml:Issuer>
+  <samlp:Status><samlp:StatusCode Value="urn:oasis:names:tc:SAML:2.0:status:Success"/></samlp:Status>
+  {}
+</samlp:Response>"#,
+        SP,
+        IDP,
+        assertions.concat()
+    )
+}
+
+#[test]
+fn test_parse_every_assertion() {
+    let xml = response(&[
+        assertion("_a1", &subject("jdoe", false), false, ("mail", "a@b.c")),
+        assertion("_a2", &subject("jdoe", true), true, ("uid", "jdoe")),
+    ]);
+    let parsed: Response = xml.parse().unwrap();
+    assert_eq!(parsed.assertions.len(), 2);
+    assert!(parsed.encrypted_assertions.is_empty());
+    let serialized = yaserde::ser::to_string(&parsed).unwrap();
+    assert_eq!(serialized.parse::<Response>().unwrap(), parsed);
+}
+
+#[test]
+fn test_merge_attribute_only_assertion() {
+    let xml = response(&[
+        assertion(
+            "_attributes",
+            &subject("jdoe", false),
+            false,
+            ("mail", "jdoe@example.com"),
+        ),
+        assertion("_authn", &subject("jdoe", true), true, ("uid", "jdoe")),
+    ]);
+    let assertion = service_provider()
+        .parse_xml_response(&xml, &["request-id"])
+        .unwrap();
+
+    assert_eq!(assertion.id, "_authn");
+    assert_eq!(assertion.attribute_statements.len(), 2);
+    assert_eq!(assertion.attribute("uid").string().unwrap(), "jdoe");
+    assert_eq!(
+        assertion.attribute("mail").string().unwrap(),
+        "jdoe@example.com"
+    );
+}
+
+#[test]
+fn test_reject_attributes_about_another_subject() {
+    let xml = response(&[
+        assertion("_authn", &subject("jdoe", true), true, ("uid", "jdoe")),
+        assertion("_other", &subject("admin", false), false, ("role", "admin")),
+    ]);
+    assert!(matches!(
+        service_provider().parse_xml_response(&xml, &["request-id"]),
+        Err(Error::AssertionSubjectMismatch { assertion_id }) if assertion_id == "_other"
+    ));
+}
+
+#[test]
+fn test_require_bearer_authn_assertion() {
+    let attributes_only = response(&[assertion(
+        "_attributes",
+        &subject("jdoe", true),
+        false,
+        ("mail", "jdoe@example.com"),
+    )]);
+    assert!(matches!(
+        service_provider().parse_xml_response(&attributes_only, &["request-id"]),
+        Err(Error::MissingAuthnAssertion)
+    ));
+
+    let not_bearer = response(&[assertion(
+        "_authn",
+        &subject("jdoe", false),
+        true,
+        ("uid", "jdoe"),
+    )]);
+    assert!(matches!(
+        service_provider().parse_xml_response(&not_bearer, &["request-id"]),
+        Err(Error::MissingAuthnAssertion)
+    ));
+}
+
+#[cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
+mod signed {
+    use super::*;
+    use base64::{engine::general_purpose, Engine as _};
+    use openssl::pkey::PKey;
+    use openssl::x509::X509;
+    use samael::crypto;
+    use samael::dom::Document;
+    use samael::key_info::{KeyInfo, X509Data};
+    use samael::metadata::{IdpSsoDescriptor, KeyDescriptor};
+    use samael::signer::{self, SoftwareSigner};
+
+    const IDP_CERT: &[u8] = include_bytes!("../test_vectors/public.der");
+
+    fn idp_signer() -> SoftwareSigner {
+        let private_key =
+            PKey::private_key_from_der(include_bytes!("../test_vectors/private.der")).unwrap();
+        SoftwareSigner::new(private_key, Some(X509::from_der(IDP_CERT).unwrap())).unwrap()
+    }
+
+    fn verifying_service_provider() -> ServiceProvider {
+        let mut sp = service_provider();
+        sp.idp_metadata.idp_sso_descriptors = vec![IdpSsoDescriptor {
+            key_descriptors: vec![KeyDescriptor {
+                key_use: Some("signing".to_string()),
+                key_info: KeyInfo {
+                    id: None,
+                    x509_data: Some(X509Data {
+                        certificates: vec![general_purpose::STANDARD.encode(IDP_CERT)],
+                    }),
+                },
+                encryption_methods: vec![],
+            }],
+            ..IdpSsoDescriptor::default()
+        }];
+        sp
+    }
+
+    /// Signs the assertions with the given IDs, then the response itself if asked to.
+    fn sign(xml: &str, assertion_ids: &[&str], response: bool) -> String {
+        let signer = idp_signer();
+        let mut document = Document::parse(xml).unwrap();
+        let root = document.root_element().unwrap();
+        for id in assertion_ids {
+            let assertion = document
+                .child_elements(root)
+                .find(|node| document.element(*node).unwrap().attribute("ID") == Some(id))
+                .unwrap();
+            signer::sign_element(&mut document, assertion, &signer).unwrap();
+        }
+        if response {
+            signer::sign_element(&mut document, root, &signer).unwrap();
+        }
+        document.to_xml()
+    }
+
+    fn two_assertions() -> String {
+        response(&[
+            assertion(
+                "_attributes",
+                &subject("jdoe", false),
+                false,
+                ("mail", "jdoe@example.com"),
+            ),
+            assertion("_authn", &subject("jdoe", true), true, ("uid", "jdoe")),
+        ])
+    }
+
+    #[test]
+    fn test_signed_response_with_two_assertions() {
+        let sp = verifying_service_provider();
+        let xml = sign(&two_assertions(), &[], true);
+        let assertion = sp.parse_xml_response(&xml, &["request-id"]).unwrap();
+        assert_eq!(assertion.id, "_authn");
+        assert_eq!(
+            assertion.attribute("mail").string().unwrap(),
+            "jdoe@example.com"
+        );
+
+        // each assertion signed on its own
+        let xml = sign(&two_assertions(), &["_attributes", "_authn"], false);
+        let report = sp.verify_response_signatures(&xml).unwrap();
+        assert_eq!(report.signatures.len(), 2);
+        // only the unsigned parts of the response itself are left out
+        assert_eq!(
+            report.removed_elements,
+            [
+                "/samlp:Response/saml:Issuer",
+                "/samlp:Response/samlp:Status"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reject_unsigned_assertion_next_to_signed_one() {
+        // an assertion could be smuggled in next to the signed authn assertion
+        let xml = sign(&two_assertions(), &["_authn"], false);
+        assert!(matches!(
+            verifying_service_provider().parse_xml_response(&xml, &["request-id"]),
+            Err(Error::FailedToValidateSignature {
+                error: crypto::Error::MultipleAssertions
+            })
+        ));
+    }
+}
@@ -0,0 +1,147 @@
+#![cfg(feature = "native-xmldsig")]
+
+use chrono::Duration;
+use samael::clock::ManualClock;
+use samael::service_provider::{Error, ServiceProvider};
+use std::sync::Arc;
+
+#[test]
+fn test_do_not_accept_unsigned_response() {
+    let idp_metadata_xml = include_str!("../test_vectors/idp_metadata.xml");
+
+    let sp = ServiceProvider {
+        idp_metadata: idp_metadata_xml.parse().unwrap(),
+        ..Default::default()
+    };
+
+    let unsigned_response_xml = include_str!("../test_vectors/response.xml");
+
+    let resp = sp.parse_xml_response(unsigned_response_xml, &[""]);
+    assert!(matches!(resp, Err(Error::FailedToParseSamlResponse)));
+}
+
+#[test]
+fn test_do_not_accept_signed_with_wrong_key() {
+    let idp_metadata_xml = include_str!("../test_vectors/idp_metadata.xml");
+
+    let sp = ServiceProvider {
+        idp_metadata: idp_metadata_xml.parse().unwrap(),
+        ..Default::default()
+    };
+
+    let wrong_cert_signed_response_xml =
+        include_str!("../test_vectors/response_signed_by_idp_2.xml");
+
+    let resp = sp.parse_xml_response(wrong_cert_signed_response_xml, &[""]);
+    assert!(matches!(
+        resp,
+        Err(Error::FailedToValidateSignature {
+            error: samael::crypto::Error::InvalidSignature
+        })
+    ));
+}
+
+#[test]
+fn test_do_not_accept_tampered_response() {
+    let idp_metadata_xml = include_str!("../test_vectors/idp_2_metadata.xml");
+
+    let sp = ServiceProvider {
+        idp_metadata: idp_metadata_xml.parse().unwrap(),
+        ..Default::default()
+    };
+
+    let tampered_response_xml = include_str!("../test_vectors/response_signed_by_idp_2.xml")
+        .replace("_ce3d2948b4cf20146dee0a0b3dd6f69b6cf86f62d7", "_tampered");
+
+    let resp = sp.parse_xml_response(&tampered_response_xml, &[""]);
+    assert!(matches!(
+        resp,
+        Err(Error::FailedToValidateSignature {
+            error: samael::crypto::Error::DigestMismatch { .. }
+        })
+    ));
+}
+
+#[test]
+fn test_accept_signed_with_correct_key_idp_2() {
+    let idp_metadata_xml = include_str!("../test_vectors/idp_2_metadata.xml");
+
+    let sp = ServiceProvider {
+        entity_id: "http://test_accept_signed_with_correct_key.test".to_string(),
+        acs_url: Some("http://sp.example.com/demo1/index.php?acs".into()),
+        idp_metadata: idp_metadata_xml.parse().unwrap(),
+        clock: Arc::new(ManualClock::new("2014-07-17T01:01:48Z".parse().unwrap())),
+        ..Default::default()
+    };
+
+    let signed_response_xml = include_str!("../test_vectors/response_signed_by_idp_2.xml");
+
+    let assertion = sp
+        .parse_xml_response(
+            signed_response_xml,
+            &["ONELOGIN_4fee3b046395c4e751011e97f8900b5273d56685"],
+        )
+        .unwrap();
+    assert_eq!(
+        assertion.subject.unwrap().name_id.unwrap().value,
+        "_ce3d2948b4cf20146dee0a0b3dd6f69b6cf86f62d7"
+    );
+}
+
+#[test]
+fn test_validity_window_follows_the_clock() {
+    let clock = Arc::new(ManualClock::new("2014-07-17T01:01:48Z".parse().unwrap()));
+    let sp = ServiceProvider {
+        entity_id: "http://test_accept_signed_with_correct_key.test".to_string(),
+        acs_url: Some("http://sp.example.com/demo1/index.php?acs".into()),
+        idp_metadata: include_str!("../test_vectors/idp_2_metadata.xml")
+            .parse()
+            .unwrap(),
+        clock: clock.clone(),
+        ..Default::default()
+    };
+    let parse = || {
+        sp.parse_xml_response(
+            include_str!("../test_vectors/response_signed_by_idp_2.xml"),
+            &["ONELOGIN_4fee3b046395c4e751011e97f8900b5273d56685"],
+        )
+    };
+
+    parse().unwrap();
+
+    clock.advance(Duration::seconds(91));
+    assert!(matches!(parse(), Err(Error::ResponseExpired { .. })));
+
+    // NotBefore is 2014-07-17T01:01:18Z, beyond the clock skew of 180 seconds
+    clock.set("2014-07-17T00:58:00Z".parse().unwrap());
+    assert!(matches!(
+        parse(),
+        Err(Error::AssertionConditionExpiredBefore { .. })
+    ));
+}
+
+#[test]
+fn test_signature_verification_report() {
+    let idp_metadata_xml = include_str!("../test_vectors/idp_2_metadata.xml");
+
+    let sp = ServiceProvider {
+        idp_metadata: idp_metadata_xml.parse().unwrap(),
+        ..Default::default()
+    };
+
+    let signed_response_xml = include_str!("../test_vectors/response_signed_by_idp_2.xml");
+
+    let report = sp.verify_response_signatures(signed_response_xml).unwrap();
+    assert_eq!(report.signatures.len(), 1);
+    assert_eq!(report.signatures[0].path, "/samlp:Response/ds:Signature");
+    assert_eq!(
+        report.signatures[0].reference_uris,
+        vec!["#pfxf63324d7-7ba2-b371-90d6-171637d97253"]
+    );
+    let idp_cert = &sp.idp_signing_certs().unwrap().unwrap()[0];
+    assert_eq!(
+        report.signatures[0].certificate.to_der().unwrap(),
+        idp_cert.to_der().unwrap()
+    );
+    assert!(report.removed_elements.is_empty());
+}
@@ -0,0 +1,48 @@
+#![cfg(not(any(feature = "xmlsec", feature = "native-xmldsig")))]
+
+use samael::clock::ManualClock;
+use samael::service_provider::{Error, ServiceProvider};
+use std::sync::Arc;
+
+#[test]
+fn test_refuse_to_parse_without_signature_backend() {
+    let idp_metadata_xml = include_str!("../test_vectors/idp_2_metadata.xml");
+
+    let sp = ServiceProvider {
+        idp_metadata: idp_metadata_xml.parse().unwrap(),
+        ..Default::default()
+    };
+
+    let signed_response_xml = include_str!("../test_vectors/response_signed_by_idp_2.xml");
+
+    let resp = sp.parse_xml_response(signed_response_xml, &[""]);
+    assert!(matches!(resp, Err(Error::SignatureVerificationUnavailable)));
+}
+
+#[test]
+fn test_dangerously_accept_unverified_responses() {
+    let idp_metadata_xml = include_str!("../test_vectors/idp_2_metadata.xml");
+
+    let sp = ServiceProvider {
+        entity_id: "http://test_accept_signed_with_correct_key.test".to_string(),
+        acs_url: Some("http://sp.example.com/demo1/index.php?acs".into()),
+        idp_metadata: idp_metadata_xml.parse().unwrap(),
+        dangerously_accept_unverified_responses: true,
+        clock: Arc::new(ManualClock::new("2014-07-17T01:01:48Z".parse().unwrap())),
+        ..Default::default()
+    };
+
+    let tampered_response_xml = include_str!("../test_vectors/response_signed_by_idp_2.xml")
+        .replace("_ce3d2948b4cf20146dee0a0b3dd6f69b6cf86f62d7", "_tampered");
+
+    let assertion = sp
+        .parse_xml_response(
+            &tampered_response_xml,
+            &["ONELOGIN_4fee3b046395c4e751011e97f8900b5273d56685"],
+        )
+        .unwrap();
+    assert_eq!(
+        assertion.subject.unwrap().name_id.unwrap().value,
+        "_tampered"
+    );
+}
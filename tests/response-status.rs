@@ -0,0 +1,118 @@
+use samael::schema::{
+    Response, StatusCode, STATUS_AUTHN_FAILED, STATUS_NO_PASSIVE, STATUS_REQUESTER,
+    STATUS_RESPONDER,
+};
+use samael::service_provider::{Error, ResponseStatus, ServiceProvider};
+
+fn error_response(status: &str) -> String {
+    format!(
+        r#"<samlp:Response xmlns:samlp="urn:oasis:names:tc:SAML:2.0:protocol" xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion" ID="_response" InResponseTo="request-id" Version="2.0" IssueInstant="2014-07-17T01:01:48Z">
+  <saml:Issuer>http://idp.example.com/metadata.php</saml:Issuer>
+  {}
+</samlp:Response>"#,
+        status
+    )
+}
+
+fn response_status(status: &str) -> ResponseStatus {
+    // the metadata of the IdP lists a signing certificate, unsigned error responses are reported
+    // as unverified
+    let sp = ServiceProvider {
+        idp_metadata: include_str!("../test_vectors/idp_2_metadata.xml")
+            .parse()
+            .unwrap(),
+        ..Default::default()
+    };
+    match sp.parse_xml_response(&error_response(status), &["request-id"]) {
+        Err(Error::UnverifiedResponseBadStatusCode { status }) => status,
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn test_nested_status_code() {
+    let xml = error_response(
+        r#"<samlp:Status>
+    <samlp:StatusCode Value="urn:oasis:names:tc:SAML:2.0:status:Requester">
+      <samlp:StatusCode Value="urn:oasis:names:tc:SAML:2.0:status:NoPassive"/>
+    </samlp:StatusCode>
+    <samlp:StatusMessage>The user is not logged in</samlp:StatusMessage>
+  </samlp:Status>"#,
+    );
+    let response: Response = xml.parse().unwrap();
+    assert_eq!(
+        response.status.status_code,
+        StatusCode::with_sub_code(STATUS_REQUESTER, STATUS_NO_PASSIVE)
+    );
+    assert!(!response.status.is_success());
+    assert!(response.status.has_code(STATUS_NO_PASSIVE));
+    assert_eq!(response.status.message(), Some("The user is not logged in"));
+
+    let serialized = yaserde::ser::to_string(&response).unwrap();
+    assert_eq!(serialized.parse::<Response>().unwrap(), response);
+}
+
+#[test]
+fn test_passive_request_falls_back() {
+    let status = response_status(
+        r#"<samlp:Status>
+    <samlp:StatusCode Value="urn:oasis:names:tc:SAML:2.0:status:Requester">
+      <samlp:StatusCode Value="urn:oasis:names:tc:SAML:2.0:status:NoPassive"/>
+    </samlp:StatusCode>
+  </samlp:Status>"#,
+    );
+    assert!(status.is_requester_error());
+    assert!(status.is_no_passive());
+    assert!(!status.is_authn_failed());
+    assert_eq!(status.message, None);
+    assert_eq!(
+        status.to_string(),
+        "urn:oasis:names:tc:SAML:2.0:status:Requester / urn:oasis:names:tc:SAML:2.0:status:NoPassive"
+    );
+}
+
+#[test]
+fn test_authn_failed_with_message_and_detail() {
+    let status = response_status(
+        r#"<samlp:Status>
+    <samlp:StatusCode Value="urn:oasis:names:tc:SAML:2.0:status:Responder">
+      <samlp:StatusCode Value="urn:oasis:names:tc:SAML:2.0:status:AuthnFailed"/>
+    </samlp:StatusCode>
+    <samlp:StatusMessage>User cancelled the login</samlp:StatusMessage>
+    <samlp:StatusDetail><ex:Reason xmlns:ex="urn:example">cancelled</ex:Reason></samlp:StatusDetail>
+  </samlp:Status>"#,
+    );
+    assert!(status.is_responder_error());
+    assert!(status.is_authn_failed());
+    assert_eq!(status.code, STATUS_RESPONDER);
+    assert_eq!(status.sub_codes, vec![STATUS_AUTHN_FAILED]);
+    assert_eq!(status.message.as_deref(), Some("User cancelled the login"));
+    let detail = status.detail.unwrap();
+    assert!(detail.starts_with("<samlp:StatusDetail"));
+    assert!(detail.contains(r#"xmlns:samlp="urn:oasis:names:tc:SAML:2.0:protocol""#));
+    assert!(detail.contains(r#"<ex:Reason xmlns:ex="urn:example">cancelled</ex:Reason>"#));
+}
+
+#[test]
+fn test_unsigned_error_response_is_unverified() {
+    let sp = ServiceProvider {
+        idp_metadata: include_str!("../test_vectors/idp_2_metadata.xml")
+            .parse()
+            .unwrap(),
+        ..Default::default()
+    };
+    let xml = error_response(
+        r#"<samlp:Status>
+    <samlp:StatusCode Value="urn:oasis:names:tc:SAML:2.0:status:Requester">
+      <samlp:StatusCode Value="urn:oasis:names:tc:SAML:2.0:status:NoPassive"/>
+    </samlp:StatusCode>
+    <samlp:StatusMessage>Please call +1 555 0100 to log in</samlp:StatusMessage>
+  </samlp:Status>"#,
+    );
+    match sp.parse_xml_response(&xml, &["request-id"]) {
+        Err(Error::UnverifiedResponseBadStatusCode { status }) => {
+            assert!(status.is_no_passive());
+        }
+        result => panic!("unexpected result {:?}", result),
+    }
+}
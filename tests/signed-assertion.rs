@@ -1,8 +1,11 @@
+#![cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
+
+use samael::clock::{Clock, ManualClock};
 use samael::{
     metadata::{ContactPerson, ContactType, EntityDescriptor},
     service_provider::ServiceProviderBuilder,
-    utils::UtcDateTime,
 };
+use std::sync::Arc;
 
 #[test]
 fn test_response() {
@@ -29,11 +32,12 @@ fn test_response() {
         .idp_metadata(idp_metadata)
         .acs_url("http://localhost:8080/saml/acs".to_string())
         .slo_url("http://localhost:8080/saml/slo".to_string())
+        .clock(Arc::new(ManualClock::new(
+            "2022-02-08T15:53:10.421Z".parse().unwrap(),
+        )) as Arc<dyn Clock>)
         .build()
         .unwrap();
 
-    unsafe { UtcDateTime::set_now("2022-02-08T15:53:10.421Z".parse().unwrap()) };
-
     sp.parse_response(
         include_str!("../test_vectors/signed_response.txt"),
         &["dummy"],
@@ -0,0 +1,275 @@
+#![cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
+
+use base64::{engine::general_purpose, Engine as _};
+use openssl::asn1::Asn1Time;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::{hash, MessageDigest};
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::sign::Verifier;
+use openssl::x509::{X509Name, X509};
+use samael::c14n::{Algorithm, Canonicalization};
+use samael::crypto;
+use samael::dom::Document;
+use samael::idp::error::Error;
+use samael::idp::key_set::{KeySet, SigningKey};
+use samael::idp::metadata::IdpMetadataBuilder;
+use samael::idp::IdentityProvider;
+use samael::schema::AuthnRequest;
+use samael::service_provider::ServiceProvider;
+use samael::signer::{self, SignatureAlgorithm, Signer, SoftwareSigner};
+use std::sync::{Arc, Mutex};
+
+const XMLNS_XML_DSIG: &str = "http://www.w3.org/2000/09/xmldsig#";
+
+fn self_signed(private_key: &PKey<Private>) -> X509 {
+    let mut name = X509Name::builder().unwrap();
+    name.append_entry_by_nid(Nid::COMMONNAME, "https://idp.example.com")
+        .unwrap();
+    let name = name.build();
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(private_key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(365).unwrap())
+        .unwrap();
+    builder.sign(private_key, MessageDigest::sha256()).unwrap();
+    builder.build()
+}
+
+fn rsa_signer() -> SoftwareSigner {
+    let private_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let certificate = self_signed(&private_key);
+    SoftwareSigner::new(private_key, Some(certificate)).unwrap()
+}
+
+/// Only hands out signatures of digests, like an HSM would.
+struct RemoteSigner {
+    inner: SoftwareSigner,
+    digests: Mutex<Vec<Vec<u8>>>,
+}
+
+impl Signer for RemoteSigner {
+    fn algorithm(&self) -> SignatureAlgorithm {
+        self.inner.algorithm()
+    }
+
+    fn certificate(&self) -> Option<X509> {
+        self.inner.certificate()
+    }
+
+    fn sign_digest(&self, digest: &[u8]) -> Result<Vec<u8>, signer::Error> {
+        self.digests.lock().unwrap().push(digest.to_vec());
+        self.inner.sign_digest(digest)
+    }
+}
+
+fn sign_response(idp: &IdentityProvider) -> String {
+    let response = idp
+        .sign_response(
+            "testuser@example.com",
+            "https://sp.example.com/audience",
+            "https://sp.example.com/acs",
+            "https://idp.example.com",
+            "request-id",
+            &[],
+        )
+        .unwrap();
+    yaserde::ser::to_string(&response).unwrap()
+}
+
+/// Checks an enveloped signature over the root element, for messages other than responses.
+fn verify_enveloped(xml: &str, certificate: &X509) {
+    let document = Document::parse(xml).unwrap();
+    let root = document.root_element().unwrap();
+    let signature = document
+        .first_child(root, "Signature", XMLNS_XML_DSIG)
+        .unwrap();
+    let signed_info = document
+        .first_child(signature, "SignedInfo", XMLNS_XML_DSIG)
+        .unwrap();
+    let child_text = |parent, name| {
+        let child = document.first_child(parent, name, XMLNS_XML_DSIG).unwrap();
+        document.text(child)
+    };
+    let exclusive = Canonicalization::new(Algorithm::Exclusive);
+
+    let reference = document
+        .first_child(signed_info, "Reference", XMLNS_XML_DSIG)
+        .unwrap();
+    let digest = hash(
+        MessageDigest::sha256(),
+        exclusive
+            .canonicalize_node(&document, root, &[signature])
+            .as_bytes(),
+    )
+    .unwrap();
+    assert_eq!(
+        general_purpose::STANDARD.encode(digest),
+        child_text(reference, "DigestValue")
+    );
+
+    let public_key = certificate.public_key().unwrap();
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &public_key).unwrap();
+    verifier
+        .update(
+            exclusive
+                .canonicalize_node(&document, signed_info, &[])
+                .as_bytes(),
+        )
+        .unwrap();
+    let signature_value = general_purpose::STANDARD
+        .decode(child_text(signature, "SignatureValue"))
+        .unwrap();
+    assert!(verifier.verify(&signature_value).unwrap());
+}
+
+#[test]
+fn test_sign_response_with_remote_signer() {
+    let remote = Arc::new(RemoteSigner {
+        inner: rsa_signer(),
+        digests: Mutex::new(vec![]),
+    });
+    let certificate = remote.certificate().unwrap();
+    let idp = IdentityProvider::from_key_set(KeySet::new(SigningKey::from_signer(remote.clone())));
+
+    let response_xml = sign_response(&idp);
+    assert_eq!(remote.digests.lock().unwrap().len(), 1);
+    let report = crypto::verify_signatures(&response_xml, &[certificate]).unwrap();
+    assert_eq!(report.signatures[0].path, "/samlp:Response/ds:Signature");
+    assert!(report.removed_elements.is_empty());
+
+    // the private key never left the signer
+    assert!(matches!(
+        idp.export_private_key_der(),
+        Err(Error::PrivateKeyNotExportable)
+    ));
+}
+
+#[test]
+fn test_sign_response_with_ecdsa_key() {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let private_key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+    let certificate = self_signed(&private_key);
+    let signing_key = SigningKey::new(private_key, certificate.clone()).unwrap();
+    assert_eq!(
+        signing_key.signer().algorithm(),
+        SignatureAlgorithm::EcdsaSha256
+    );
+    let idp = IdentityProvider::from_key_set(KeySet::new(signing_key));
+
+    let response_xml = sign_response(&idp);
+    assert!(response_xml.contains("ecdsa-sha256"));
+    crypto::verify_signatures(&response_xml, &[certificate]).unwrap();
+}
+
+#[test]
+fn test_sign_authn_request_and_metadata() {
+    let signer = rsa_signer();
+    let certificate = signer.certificate().unwrap();
+
+    let request: AuthnRequest = include_str!("../test_vectors/authn_request_sign_template.xml")
+        .parse()
+        .unwrap();
+    let signed_request = request.sign(&signer).unwrap();
+    verify_enveloped(&signed_request, &certificate);
+    // the template is replaced in place, after the issuer
+    let parsed: AuthnRequest = signed_request.parse().unwrap();
+    assert_eq!(parsed.signature.unwrap().key_info.len(), 1);
+
+    let idp =
+        IdentityProvider::from_key_set(KeySet::new(SigningKey::from_signer(Arc::new(signer))));
+    let metadata = IdpMetadataBuilder::default()
+        .entity_id("https://idp.example.com")
+        .build()
+        .unwrap();
+    verify_enveloped(&idp.signed_metadata(&metadata).unwrap(), &certificate);
+}
+
+#[test]
+fn test_signed_redirect() {
+    let signer = rsa_signer();
+    let sp = ServiceProvider {
+        entity_id: "https://sp.example.com".to_string(),
+        signer: Some(Arc::new(signer.clone())),
+        ..ServiceProvider::default()
+    };
+    let request = sp
+        .make_authentication_request("https://idp.example.com/sso")
+        .unwrap();
+    let url = sp.signed_redirect(&request, Some("state")).unwrap();
+
+    let query = url.query().unwrap();
+    let (signed, _) = query.split_once("&Signature=").unwrap();
+    assert!(signed.starts_with("SAMLRequest="));
+    assert!(signed.ends_with(&format!(
+        "&RelayState=state&SigAlg={}",
+        url::form_urlencoded::byte_serialize(SignatureAlgorithm::RsaSha256.uri().as_bytes())
+            .collect::<String>()
+    )));
+    let signature = url
+        .query_pairs()
+        .find(|(name, _)| name == "Signature")
+        .map(|(_, value)| general_purpose::STANDARD.decode(value.as_bytes()).unwrap())
+        .unwrap();
+
+    let public_key = signer.certificate().unwrap().public_key().unwrap();
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &public_key).unwrap();
+    verifier.update(signed.as_bytes()).unwrap();
+    assert!(verifier.verify(&signature).unwrap());
+}
+
+#[test]
+fn test_sign_authn_response_certificate_fallback() {
+    let embedded_certificate = |idp: &IdentityProvider, fallback: &X509| {
+        let response = idp
+            .sign_authn_response(
+                &fallback.to_der().unwrap(),
+                "testuser@example.com",
+                "https://sp.example.com/audience",
+                "https://sp.example.com/acs",
+                "https://idp.example.com",
+                "request-id",
+                &[],
+            )
+            .unwrap();
+        let key_info = &response.signature.unwrap().key_info[0];
+        crypto::decode_x509_cert(&key_info.x509_data.as_ref().unwrap().certificates[0]).unwrap()
+    };
+    let other_certificate = rsa_signer().certificate().unwrap();
+
+    // the certificate of the active key wins over the one passed in
+    let signer = rsa_signer();
+    let certificate = signer.certificate().unwrap();
+    let idp =
+        IdentityProvider::from_key_set(KeySet::new(SigningKey::from_signer(Arc::new(signer))));
+    assert_eq!(
+        embedded_certificate(&idp, &other_certificate),
+        certificate.to_der().unwrap()
+    );
+
+    let private_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let signer = SoftwareSigner::new(private_key, None).unwrap();
+    let idp =
+        IdentityProvider::from_key_set(KeySet::new(SigningKey::from_signer(Arc::new(signer))));
+    assert_eq!(
+        embedded_certificate(&idp, &other_certificate),
+        other_certificate.to_der().unwrap()
+    );
+    assert!(idp
+        .sign_response(
+            "testuser@example.com",
+            "https://sp.example.com/audience",
+            "https://sp.example.com/acs",
+            "https://idp.example.com",
+            "request-id",
+            &[],
+        )
+        .is_err());
+}
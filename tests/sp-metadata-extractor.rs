@@ -0,0 +1,280 @@
+use base64::{engine::general_purpose, Engine as _};
+use samael::idp::error::Error;
+use samael::idp::sp_extractor::{Acs, BindType, SPMetadataExtractor};
+use samael::key_info::{KeyInfo, X509Data};
+use samael::metadata::{
+    AttributeConsumingService, EntityDescriptor, IndexedEndpoint, KeyDescriptor, SpSsoDescriptor,
+    HTTP_ARTIFACT_BINDING, HTTP_POST_BINDING, HTTP_REDIRECT_BINDING, PAOS_BINDING,
+};
+use samael::schema::AuthnRequest;
+
+const SP_CERT: &[u8] = include_bytes!("../test_vectors/sp_cert.der");
+const IDP_CERT: &[u8] = include_bytes!("../test_vectors/idp_cert.der");
+
+fn endpoint(binding: &str, path: &str, index: u16, is_default: Option<bool>) -> IndexedEndpoint {
+    IndexedEndpoint {
+        binding: binding.to_string(),
+        location: format!("https://sp.example.com{}", path),
+        index,
+        is_default,
+        ..IndexedEndpoint::default()
+    }
+}
+
+fn key_descriptor(key_use: Option<&str>, der: &[u8]) -> KeyDescriptor {
+    KeyDescriptor {
+        key_use: key_use.map(str::to_string),
+        key_info: KeyInfo {
+            id: None,
+            x509_data: Some(X509Data {
+                certificates: vec![general_purpose::STANDARD.encode(der)],
+            }),
+        },
+        encryption_methods: vec![],
+    }
+}
+
+fn extractor(descriptor: SpSsoDescriptor) -> SPMetadataExtractor {
+    SPMetadataExtractor::from(EntityDescriptor {
+        entity_id: "https://sp.example.com".to_string(),
+        sp_sso_descriptors: vec![descriptor],
+        ..EntityDescriptor::default()
+    })
+}
+
+fn assertion_consumer_services(is_default: Option<bool>) -> Vec<IndexedEndpoint> {
+    vec![
+        endpoint(HTTP_POST_BINDING, "/acs/post", 0, None),
+        endpoint(HTTP_REDIRECT_BINDING, "/acs/redirect", 1, is_default),
+        endpoint(HTTP_ARTIFACT_BINDING, "/acs/artifact", 2, None),
+        endpoint(PAOS_BINDING, "/ecp", 3, Some(false)),
+        endpoint("urn:example:unknown", "/acs/unknown", 4, Some(false)),
+    ]
+}
+
+#[test]
+fn test_default_acs() {
+    let sp = extractor(SpSsoDescriptor {
+        assertion_consumer_services: assertion_consumer_services(Some(true)),
+        ..SpSsoDescriptor::default()
+    });
+    assert_eq!(
+        sp.acs().unwrap(),
+        Acs {
+            bind_type: BindType::Redirect,
+            url: "https://sp.example.com/acs/redirect".to_string(),
+            index: 1,
+            is_default: true,
+        }
+    );
+
+    // without an explicit default, the first endpoint not marked otherwise
+    let mut endpoints = assertion_consumer_services(None);
+    endpoints[0].is_default = Some(false);
+    let sp = extractor(SpSsoDescriptor {
+        assertion_consumer_services: endpoints,
+        ..SpSsoDescriptor::default()
+    });
+    assert_eq!(sp.acs().unwrap().bind_type, BindType::Redirect);
+
+    assert!(matches!(
+        extractor(SpSsoDescriptor::default()).acs(),
+        Err(Error::MissingAcsUrl)
+    ));
+}
+
+#[test]
+fn test_acs_by_index_and_url() {
+    let sp = extractor(SpSsoDescriptor {
+        assertion_consumer_services: assertion_consumer_services(None),
+        ..SpSsoDescriptor::default()
+    });
+    assert_eq!(sp.acs_by_index(2).unwrap().bind_type, BindType::Artifact);
+    assert_eq!(sp.acs_by_index(3).unwrap().bind_type, BindType::Paos);
+    assert!(matches!(
+        sp.acs_by_index(4),
+        Err(Error::UnsupportedBinding { binding }) if binding == "urn:example:unknown"
+    ));
+    assert!(matches!(
+        sp.acs_by_index(5),
+        Err(Error::UnregisteredAcs {
+            url: None,
+            index: Some(5)
+        })
+    ));
+
+    let url = "https://sp.example.com/acs/redirect";
+    assert_eq!(sp.acs_by_url(url, None).unwrap().index, 1);
+    assert!(matches!(
+        sp.acs_by_url(url, Some(HTTP_POST_BINDING)),
+        Err(Error::UnregisteredAcs { .. })
+    ));
+
+    let request = AuthnRequest {
+        assertion_consumer_service_url: Some(url.to_string()),
+        protocol_binding: Some(HTTP_REDIRECT_BINDING.to_string()),
+        ..AuthnRequest::default()
+    };
+    assert_eq!(sp.acs_for_request(&request).unwrap().index, 1);
+    let request = AuthnRequest {
+        assertion_consumer_service_index: Some(2),
+        ..AuthnRequest::default()
+    };
+    assert_eq!(sp.acs_for_request(&request).unwrap().index, 2);
+    assert_eq!(
+        sp.acs_for_request(&AuthnRequest::default()).unwrap().index,
+        0
+    );
+}
+
+#[test]
+fn test_bind_type_bindings() {
+    for bind_type in [
+        BindType::Post,
+        BindType::Redirect,
+        BindType::Artifact,
+        BindType::Paos,
+    ] {
+        assert_eq!(BindType::from_binding(bind_type.binding()), Some(bind_type));
+    }
+    assert_eq!(
+        BindType::from_binding("urn:oasis:names:tc:SAML:2.0:bindings:SOAP"),
+        None
+    );
+}
+
+#[test]
+fn test_encryption_certificate() {
+    let sp = extractor(SpSsoDescriptor {
+        key_descriptors: vec![
+            key_descriptor(Some("signing"), IDP_CERT),
+            key_descriptor(None, IDP_CERT),
+            key_descriptor(Some("encryption"), SP_CERT),
+        ],
+        ..SpSsoDescriptor::default()
+    });
+    assert_eq!(
+        sp.encryption_certificate().unwrap().to_der().unwrap(),
+        SP_CERT
+    );
+
+    // a key without a use serves for encryption too
+    let sp = extractor(SpSsoDescriptor {
+        key_descriptors: vec![
+            key_descriptor(Some("signing"), IDP_CERT),
+            key_descriptor(None, SP_CERT),
+        ],
+        ..SpSsoDescriptor::default()
+    });
+    assert_eq!(
+        sp.encryption_certificate().unwrap().to_der().unwrap(),
+        SP_CERT
+    );
+
+    let sp = extractor(SpSsoDescriptor {
+        key_descriptors: vec![key_descriptor(Some("signing"), IDP_CERT)],
+        ..SpSsoDescriptor::default()
+    });
+    assert!(matches!(
+        sp.encryption_certificate(),
+        Err(Error::NoCertificate)
+    ));
+}
+
+#[test]
+fn test_attribute_consuming_service() {
+    let service = |index, is_default| AttributeConsumingService {
+        index,
+        is_default,
+        ..AttributeConsumingService::default()
+    };
+    let sp = extractor(SpSsoDescriptor {
+        attribute_consuming_services: vec![service(0, None), service(1, Some(true))],
+        ..SpSsoDescriptor::default()
+    });
+    assert_eq!(sp.attribute_consuming_service(None).unwrap().index, 1);
+    assert_eq!(sp.attribute_consuming_service(Some(0)).unwrap().index, 0);
+    assert!(sp.attribute_consuming_service(Some(2)).is_none());
+}
+
+fn sp_metadata_xml() -> String {
+    format!(
+        r#"<md:EntityDescriptor xmlns:md="urn:oasis:names:tc:SAML:2.0:metadata" xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion" entityID="https://sp.example.com">
+  <md:SPSSODescriptor AuthnRequestsSigned="true" WantAssertionsSigned="true" protocolSupportEnumeration="urn:oasis:names:tc:SAML:2.0:protocol">
+    <md:KeyDescriptor use="encryption">
+      <ds:KeyInfo xmlns:ds="http://www.w3.org/2000/09/xmldsig#"><ds:X509Data><ds:X509Certificate>{}</ds:X509Certificate></ds:X509Data></ds:KeyInfo>
+      <md:EncryptionMethod Algorithm="http://www.w3.org/2009/xmlenc11#aes256-gcm"/>
+      <md:EncryptionMethod Algorithm="http://www.w3.org/2009/xmlenc11#rsa-oaep"/>
+    </md:KeyDescriptor>
+    <md:SingleLogoutService Binding="urn:oasis:names:tc:SAML:2.0:bindings:HTTP-Redirect" Location="https://sp.example.com/slo/redirect"/>
+    <md:SingleLogoutService Binding="urn:oasis:names:tc:SAML:2.0:bindings:SOAP" Location="https://sp.example.com/slo/soap"/>
+    <md:NameIDFormat>urn:oasis:names:tc:SAML:2.0:nameid-format:persistent</md:NameIDFormat>
+    <md:NameIDFormat>urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress</md:NameIDFormat>
+    <md:AssertionConsumerService Binding="urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST" Location="https://sp.example.com/acs" index="0"/>
+    <md:AttributeConsumingService index="0">
+      <md:ServiceName xml:lang="en">Example</md:ServiceName>
+      <md:RequestedAttribute Name="urn:oid:0.9.2342.19200300.100.1.3" FriendlyName="mail" isRequired="true"/>
+      <md:RequestedAttribute Name="urn:oid:1.3.6.1.4.1.5923.1.1.1.7" FriendlyName="eduPersonEntitlement">
+        <saml:AttributeValue>urn:example:entitlement:library</saml:AttributeValue>
+      </md:RequestedAttribute>
+    </md:AttributeConsumingService>
+  </md:SPSSODescriptor>
+</md:EntityDescriptor>"#,
+        general_purpose::STANDARD.encode(SP_CERT)
+    )
+}
+
+#[test]
+fn test_attribute_release_inputs() {
+    let sp = SPMetadataExtractor::try_from_xml(&sp_metadata_xml()).unwrap();
+    assert!(sp.authn_requests_signed());
+    assert!(sp.want_assertions_signed());
+    assert_eq!(
+        sp.name_id_formats(),
+        [
+            "urn:oasis:names:tc:SAML:2.0:nameid-format:persistent",
+            "urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress"
+        ]
+    );
+
+    let requested = sp.requested_attributes(None);
+    assert_eq!(requested.len(), 2);
+    assert!(requested[0].is_required());
+    assert_eq!(requested[0].friendly_name.as_deref(), Some("mail"));
+    assert!(requested[0].accepts("jdoe@example.com"));
+
+    let optional = sp.optional_attributes();
+    assert_eq!(optional.len(), 1);
+    assert_eq!(
+        optional[0].friendly_name.as_deref(),
+        Some("eduPersonEntitlement")
+    );
+    assert!(optional[0].accepts("urn:example:entitlement:library"));
+    assert!(!optional[0].accepts("urn:example:entitlement:admin"));
+
+    let required = sp.required_attributes();
+    assert_eq!(required.len(), 1);
+    assert_eq!(required[0].name, "urn:oid:0.9.2342.19200300.100.1.3");
+    assert!(sp.requested_attributes(Some(1)).is_empty());
+
+    assert_eq!(sp.single_logout_services().len(), 2);
+    assert_eq!(
+        sp.single_logout_service(HTTP_REDIRECT_BINDING)
+            .unwrap()
+            .location,
+        "https://sp.example.com/slo/redirect"
+    );
+    assert!(sp.single_logout_service(HTTP_POST_BINDING).is_none());
+
+    assert_eq!(
+        sp.encryption_methods(),
+        [
+            "http://www.w3.org/2009/xmlenc11#aes256-gcm",
+            "http://www.w3.org/2009/xmlenc11#rsa-oaep"
+        ]
+    );
+    assert_eq!(
+        sp.encryption_certificate().unwrap().to_der().unwrap(),
+        SP_CERT
+    );
+}
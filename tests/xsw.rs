@@ -0,0 +1,106 @@
+#![cfg(any(feature = "xmlsec", feature = "native-xmldsig"))]
+
+use samael::crypto::{self, VerificationReport};
+use samael::service_provider::{Error, ServiceProvider};
+
+fn service_provider() -> ServiceProvider {
+    ServiceProvider {
+        entity_id: "http://test_accept_signed_with_correct_key.test".to_string(),
+        acs_url: Some("http://sp.example.com/demo1/index.php?acs".into()),
+        idp_metadata: include_str!("../test_vectors/idp_2_metadata.xml")
+            .parse()
+            .unwrap(),
+        ..Default::default()
+    }
+}
+
+fn verify(response_xml: &str) -> Result<VerificationReport, Error> {
+    service_provider().verify_response_signatures(response_xml)
+}
+
+#[test]
+fn test_accept_signed_assertion() {
+    let report = verify(include_str!(
+        "../test_vectors/xsw/response_signed_assertion.xml"
+    ))
+    .unwrap();
+    assert_eq!(report.signatures.len(), 1);
+    assert_eq!(
+        report.signatures[0].path,
+        "/samlp:Response/saml:Assertion/ds:Signature"
+    );
+    assert_eq!(
+        report.removed_elements,
+        vec![
+            "/samlp:Response/saml:Issuer",
+            "/samlp:Response/samlp:Status"
+        ]
+    );
+}
+
+#[test]
+fn test_reject_signature_wrapping_attacks() {
+    // the forged elements have IDs of their own, so that the attacks get past the duplicate ID
+    // check and must be caught by the signature not being a child of the element it references...
+    for (name, response_xml) in [
+        ("XSW1", include_str!("../test_vectors/xsw/xsw1.xml")),
+        ("XSW2", include_str!("../test_vectors/xsw/xsw2.xml")),
+        ("XSW5", include_str!("../test_vectors/xsw/xsw5.xml")),
+        ("XSW6", include_str!("../test_vectors/xsw/xsw6.xml")),
+        ("XSW8", include_str!("../test_vectors/xsw/xsw8.xml")),
+    ] {
+        let result = verify(response_xml);
+        assert!(
+            matches!(
+                result,
+                Err(Error::FailedToValidateSignature {
+                    error: crypto::Error::InvalidReferenceTarget { .. }
+                })
+            ),
+            "{} was not rejected: {:?}",
+            name,
+            result
+        );
+    }
+    // ...or by the forged assertion not being covered by the signature
+    for (name, response_xml) in [
+        ("XSW3", include_str!("../test_vectors/xsw/xsw3.xml")),
+        ("XSW4", include_str!("../test_vectors/xsw/xsw4.xml")),
+        ("XSW7", include_str!("../test_vectors/xsw/xsw7.xml")),
+    ] {
+        let result = verify(response_xml);
+        assert!(
+            matches!(
+                result,
+                Err(Error::FailedToValidateSignature {
+                    error: crypto::Error::MultipleAssertions
+                })
+            ),
+            "{} was not rejected: {:?}",
+            name,
+            result
+        );
+    }
+}
+
+#[test]
+fn test_reject_duplicate_ids() {
+    assert!(matches!(
+        verify(include_str!("../test_vectors/xsw/duplicate_id.xml")),
+        Err(Error::FailedToValidateSignature {
+            error: crypto::Error::DuplicateId { .. }
+        })
+    ));
+}
+
+#[test]
+fn test_reject_reference_to_ancestor() {
+    assert!(matches!(
+        verify(include_str!(
+            "../test_vectors/xsw/signature_in_assertion.xml"
+        )),
+        Err(Error::FailedToValidateSignature {
+            error: crypto::Error::InvalidReferenceTarget { .. }
+        })
+    ));
+}